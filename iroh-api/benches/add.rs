@@ -54,6 +54,7 @@ fn add_benchmark(c: &mut Criterion) {
                         FullLoaderConfig {
                             http_gateways: Vec::new(),
                             indexer: None,
+                            delegated_routers: Vec::new(),
                         },
                     )
                     .unwrap();
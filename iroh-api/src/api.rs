@@ -1,11 +1,13 @@
 use std::collections::HashMap;
 use std::fmt;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use crate::config::{Config, CONFIG_FILE_NAME, ENV_PREFIX};
 use crate::IpfsPath;
 use crate::P2pApi;
 use anyhow::{ensure, Context, Result};
+use bytes::Bytes;
 use cid::Cid;
 use futures::stream::BoxStream;
 use futures::{StreamExt, TryStreamExt};
@@ -16,6 +18,7 @@ use iroh_unixfs::{
     content_loader::{FullLoader, FullLoaderConfig},
 };
 use iroh_util::{iroh_config_path, make_config};
+use libp2p::PeerId;
 use relative_path::RelativePathBuf;
 use tokio::io::{AsyncRead, AsyncReadExt};
 
@@ -90,6 +93,13 @@ impl Api {
                     .collect::<Result<_>>()
                     .context("invalid gateway url")?,
                 indexer: config.indexer_endpoint,
+                delegated_routers: config
+                    .delegated_routers
+                    .iter()
+                    .flatten()
+                    .map(|u| u.parse())
+                    .collect::<Result<_, _>>()
+                    .context("invalid delegated router url")?,
             },
         )?;
         let resolver = Resolver::new(content_loader);
@@ -109,6 +119,52 @@ impl Api {
         self.client.try_p2p()?.start_providing(&cid).await
     }
 
+    /// Pins the [`Cid`] in the store.
+    ///
+    /// The p2p node keeps announcing pinned content to the DHT, depending on the configured
+    /// reprovider strategy. Use [`Api::announce_pinned`] to announce it right away.
+    pub async fn pin(&self, cid: Cid) -> Result<()> {
+        self.client.try_store()?.pin(cid).await
+    }
+
+    /// Asks the p2p node to announce newly pinned content to the DHT.
+    ///
+    /// Depending on the reprovider strategy only the [`Cid`] itself or all blocks it links to
+    /// are announced. The announcements happen in the background.
+    pub async fn announce_pinned(&self, cid: Cid) -> Result<()> {
+        self.client.try_p2p()?.announce_pinned(&cid).await
+    }
+
+    /// Publishes an IPNS record pointing to `path`.
+    ///
+    /// The record is signed with the keychain key `key`, or the identity of the p2p node if
+    /// `None`. It stays valid for `lifetime`, resolvers may cache it for `ttl`. Returns the
+    /// name the record is published under, it can be resolved as `/ipns/<name>`.
+    pub async fn name_publish(
+        &self,
+        key: Option<PeerId>,
+        path: &IpfsPath,
+        lifetime: Duration,
+        ttl: Duration,
+    ) -> Result<PeerId> {
+        let value = Bytes::from(path.to_string());
+        let (name, _) = self
+            .client
+            .try_p2p()?
+            .name_publish(key, value, lifetime, ttl)
+            .await?;
+        Ok(name)
+    }
+
+    /// Resolves the IPNS record published under `name` to the path it points to.
+    pub async fn name_resolve(&self, name: &PeerId) -> Result<IpfsPath> {
+        let value = self.client.try_p2p()?.name_resolve(*name).await?;
+        let value = std::str::from_utf8(&value).context("invalid ipns record value")?;
+        value
+            .parse()
+            .with_context(|| format!("invalid ipns record value {value}"))
+    }
+
     pub fn p2p(&self) -> Result<P2pApi> {
         let p2p_client = self.client.try_p2p()?;
         Ok(P2pApi::new(p2p_client))
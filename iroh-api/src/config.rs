@@ -18,6 +18,8 @@ pub struct Config {
     pub metrics: MetricsConfig,
     pub http_resolvers: Option<Vec<String>>,
     pub indexer_endpoint: Option<IndexerUrl>,
+    /// Delegated routing endpoints, serving `/routing/v1/providers/{cid}`.
+    pub delegated_routers: Option<Vec<String>>,
 }
 
 impl Default for Config {
@@ -27,6 +29,7 @@ impl Default for Config {
             metrics: Default::default(),
             http_resolvers: None,
             indexer_endpoint: Some(IndexerUrl::default()),
+            delegated_routers: None,
         }
     }
 }
@@ -45,6 +48,9 @@ impl Source for Config {
         if let Some(indexer_endpoint) = &self.indexer_endpoint {
             insert_into_config_map(&mut map, "indexer_endpoint", indexer_endpoint.clone());
         }
+        if let Some(delegated_routers) = &self.delegated_routers {
+            insert_into_config_map(&mut map, "delegated_routers", delegated_routers.clone());
+        }
 
         Ok(map)
     }
@@ -76,6 +82,10 @@ mod tests {
             "http_resolvers".to_string(),
             Value::new(None, default.http_resolvers.clone()),
         );
+        expect.insert(
+            "delegated_routers".to_string(),
+            Value::new(None, default.delegated_routers.clone()),
+        );
 
         let got = default.collect().unwrap();
 
@@ -8,6 +8,12 @@ pub use bytes::Bytes;
 pub use cid::Cid;
 pub use iroh_resolver::resolver::Path as IpfsPath;
 pub use iroh_rpc_client::{ClientStatus, Lookup, ServiceStatus, ServiceType, StatusType};
+pub use iroh_rpc_types::p2p::{
+    BandwidthProtocol, BandwidthStatsResponse as BandwidthStats, BandwidthTotals,
+    GossipsubAcceptance, GossipsubMessagesResponse as GossipsubMessage, KeyFormat, NetworkEvent,
+    NetworkEventKind, PeerFilterTarget, Reachability, RecordQuorum,
+    RecordValidateResponse as RecordToValidate,
+};
 pub use iroh_unixfs::builder::{
     Config as UnixfsConfig, DirectoryBuilder, Entry as UnixfsEntry, FileBuilder, SymlinkBuilder,
 };
@@ -1,8 +1,19 @@
 use crate::error::map_service_error;
 use anyhow::Result;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
 use iroh_rpc_client::{Lookup, P2pClient};
+use iroh_rpc_types::p2p::{
+    BandwidthStatsResponse as BandwidthStats, GossipsubAcceptance,
+    GossipsubMessagesResponse as GossipsubMessage, KeyFormat, NetworkEvent, NetworkEventKind,
+    PeerFilterList, PeerFilterTarget, RecordQuorum, RecordValidateResponse as RecordToValidate,
+};
+use libp2p::gossipsub::{MessageId, TopicHash};
 use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
 use std::collections::HashMap;
+use std::time::Duration;
+use zeroize::Zeroizing;
 
 #[derive(Debug)]
 pub struct P2p {
@@ -46,6 +57,192 @@ impl P2p {
         .map_err(|e| map_service_error("p2p", e))
     }
 
+    /// Closes all connections to the peer.
+    ///
+    /// With a `ban` the peer is also kept from connecting again for the given duration.
+    pub async fn disconnect(&self, addr: &PeerIdOrAddr, ban: Option<Duration>) -> Result<()> {
+        let peer_id = match addr {
+            PeerIdOrAddr::PeerId(peer_id) => *peer_id,
+            PeerIdOrAddr::Multiaddr(addr) => peer_id_from_multiaddr(addr)?,
+        };
+        self.client
+            .disconnect(peer_id, ban)
+            .await
+            .map_err(|e| map_service_error("p2p", e))
+    }
+
+    /// Rejects all connections to and from the peer or address range, closing open ones.
+    pub async fn block(&self, target: PeerFilterTarget) -> Result<()> {
+        self.client
+            .add_peer_filter(PeerFilterList::Deny, target)
+            .await
+            .map_err(|e| map_service_error("p2p", e))
+    }
+
+    /// Lifts a block, whether it was added at runtime or configured.
+    pub async fn unblock(&self, target: PeerFilterTarget) -> Result<()> {
+        self.client
+            .remove_peer_filter(PeerFilterList::Deny, target)
+            .await
+            .map_err(|e| map_service_error("p2p", e))
+    }
+
+    /// Puts a record into the DHT, once it is validated by the validator of its key namespace.
+    pub async fn put_record(&self, key: Bytes, value: Bytes, quorum: RecordQuorum) -> Result<()> {
+        self.client
+            .put_record(key, value, quorum)
+            .await
+            .map_err(|e| map_service_error("p2p", e))
+    }
+
+    /// Returns the valid records stored under `key` in the DHT, the best one first.
+    pub async fn get_record(&self, key: Bytes, quorum: RecordQuorum) -> Result<Vec<Bytes>> {
+        self.client
+            .get_record(key, quorum)
+            .await
+            .map_err(|e| map_service_error("p2p", e))
+    }
+
+    /// Validates the DHT records under `/<namespace>/`, which are only put into the DHT, stored
+    /// for other peers or returned from lookups once they are reported as valid with
+    /// [`P2p::report_record_validation`].
+    ///
+    /// The validation ends when the stream is dropped.
+    pub async fn validate_records(
+        &self,
+        namespace: String,
+    ) -> Result<BoxStream<'static, Result<RecordToValidate>>> {
+        let records = self
+            .client
+            .record_validate(namespace)
+            .await
+            .map_err(|e| map_service_error("p2p", e))?;
+        Ok(records.boxed())
+    }
+
+    pub async fn report_record_validation(&self, id: u64, valid: bool) -> Result<()> {
+        self.client
+            .record_report_validation(id, valid)
+            .await
+            .map_err(|e| map_service_error("p2p", e))
+    }
+
+    /// Subscribes to a gossipsub topic and streams the messages received on it.
+    pub async fn subscribe(
+        &self,
+        topic: String,
+    ) -> Result<BoxStream<'static, Result<GossipsubMessage>>> {
+        let messages = self
+            .client
+            .gossipsub_messages(TopicHash::from_raw(topic))
+            .await
+            .map_err(|e| map_service_error("p2p", e))?;
+        Ok(messages.boxed())
+    }
+
+    pub async fn publish(&self, topic: String, data: Bytes) -> Result<MessageId> {
+        self.client
+            .gossipsub_publish(TopicHash::from_raw(topic), data)
+            .await
+            .map_err(|e| map_service_error("p2p", e))
+    }
+
+    /// Validates the messages of a gossipsub topic, which are only forwarded once they are
+    /// reported as accepted with [`P2p::report_message_validation`].
+    ///
+    /// Peers delivering rejected messages are penalized. The validation ends when the stream
+    /// is dropped.
+    pub async fn validate_messages(
+        &self,
+        topic: String,
+    ) -> Result<BoxStream<'static, Result<GossipsubMessage>>> {
+        let messages = self
+            .client
+            .gossipsub_validate(TopicHash::from_raw(topic))
+            .await
+            .map_err(|e| map_service_error("p2p", e))?;
+        Ok(messages.boxed())
+    }
+
+    pub async fn report_message_validation(
+        &self,
+        message_id: MessageId,
+        acceptance: GossipsubAcceptance,
+    ) -> Result<()> {
+        self.client
+            .gossipsub_report_validation(message_id, acceptance)
+            .await
+            .map_err(|e| map_service_error("p2p", e))
+    }
+
+    /// Streams the network events of the node, only those of the given kinds unless `kinds`
+    /// is empty.
+    pub async fn network_events(
+        &self,
+        kinds: Vec<NetworkEventKind>,
+    ) -> Result<BoxStream<'static, Result<NetworkEvent>>> {
+        let events = self
+            .client
+            .subscribe_network_events(kinds)
+            .await
+            .map_err(|e| map_service_error("p2p", e))?;
+        Ok(events.boxed())
+    }
+
+    /// Returns the bytes received and sent by the node, in total, per protocol and per
+    /// connected peer.
+    pub async fn stats(&self) -> Result<BandwidthStats> {
+        self.client
+            .bandwidth_stats()
+            .await
+            .map_err(|e| map_service_error("p2p", e))
+    }
+
+    /// Lists the identity keys by their peer id, and the key that is active.
+    pub async fn keys(&self) -> Result<(Vec<PeerId>, Option<PeerId>)> {
+        self.client
+            .keys()
+            .await
+            .map_err(|e| map_service_error("p2p", e))
+    }
+
+    pub async fn generate_key(&self) -> Result<PeerId> {
+        self.client
+            .key_generate()
+            .await
+            .map_err(|e| map_service_error("p2p", e))
+    }
+
+    /// Imports a private key in the OpenSSH format or the libp2p protobuf encoding.
+    pub async fn import_key(&self, key: Bytes) -> Result<PeerId> {
+        self.client
+            .key_import(key)
+            .await
+            .map_err(|e| map_service_error("p2p", e))
+    }
+
+    /// Exports a private key, `unencrypted` has to be set if the keystore of the node is
+    /// protected by a passphrase.
+    pub async fn export_key(
+        &self,
+        peer_id: PeerId,
+        format: KeyFormat,
+        unencrypted: bool,
+    ) -> Result<Zeroizing<Vec<u8>>> {
+        self.client
+            .key_export(peer_id, format, unencrypted)
+            .await
+            .map_err(|e| map_service_error("p2p", e))
+    }
+
+    /// Makes the key the identity of the node, from its next start on.
+    pub async fn select_key(&self, peer_id: PeerId) -> Result<()> {
+        self.client
+            .key_select(peer_id)
+            .await
+            .map_err(|e| map_service_error("p2p", e))
+    }
+
     pub async fn peers(&self) -> Result<HashMap<PeerId, Vec<Multiaddr>>> {
         self.client
             .get_peers()
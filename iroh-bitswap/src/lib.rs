@@ -9,7 +9,7 @@ use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 use std::time::{Duration, Instant};
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use anyhow::Result;
 use async_trait::async_trait;
 use cid::Cid;
@@ -29,7 +29,7 @@ use tokio::task::JoinHandle;
 use tracing::{debug, trace, warn};
 
 use self::client::{Client, Config as ClientConfig};
-use self::message::BitswapMessage;
+use self::message::{BitswapMessage, WantType};
 use self::network::Network;
 use self::network::OutEvent;
 use self::protocol::ProtocolConfig;
@@ -67,6 +67,7 @@ pub struct Bitswap<S: Store> {
     idle_timeout: Duration,
     peers: Arc<Mutex<AHashMap<PeerId, PeerState>>>,
     dials: Arc<Mutex<DialMap>>,
+    provider_queries: Arc<Mutex<ProviderQueries>>,
     /// Set to true when dialing should be disabled because we have reached the conn limit.
     pause_dialing: bool,
     client: Client<S>,
@@ -217,6 +218,7 @@ impl<S: Store> Bitswap<S> {
             idle_timeout: config.idle_timeout,
             peers: Default::default(),
             dials: Default::default(),
+            provider_queries: Default::default(),
             pause_dialing: false,
             server,
             client,
@@ -279,6 +281,114 @@ impl<S: Store> Bitswap<S> {
         }
     }
 
+    /// Asks all responsive peers that support HAVE messages whether they have `cid`.
+    ///
+    /// Peers answering with a HAVE, or with the block itself, are sent on the returned
+    /// channel until `limit` providers are found or `timeout` elapses.
+    pub fn find_providers(
+        &self,
+        cid: Cid,
+        limit: usize,
+        timeout: Duration,
+    ) -> mpsc::Receiver<PeerId> {
+        let peers: Vec<PeerId> = self
+            .peers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(peer, state)| match state {
+                PeerState::Responsive(_, protocol) if protocol.supports_have() => Some(*peer),
+                _ => None,
+            })
+            .collect();
+
+        let (sender, receiver) = mpsc::channel(limit.max(1));
+        if peers.is_empty() || limit == 0 {
+            return receiver;
+        }
+
+        let (haves_sender, mut haves) = mpsc::channel(peers.len());
+        self.provider_queries
+            .lock()
+            .unwrap()
+            .entry(cid)
+            .or_default()
+            .push(haves_sender);
+
+        let network = self.network.clone();
+        let client = self.client.clone();
+        let provider_queries = self.provider_queries.clone();
+        tokio::task::spawn(async move {
+            debug!("find_providers {}: asking {} peers", cid, peers.len());
+            let mut want_have = BitswapMessage::new(false);
+            want_have.add_entry(cid, 1, WantType::Have, false);
+
+            let mut found = AHashSet::new();
+            let query = async {
+                let sends = peers
+                    .iter()
+                    .map(|peer| network.send_message(*peer, want_have.clone()));
+                for (peer, res) in peers.iter().zip(futures::future::join_all(sends).await) {
+                    if let Err(err) = res {
+                        debug!("find_providers {}: failed to ask {}: {:?}", cid, peer, err);
+                    }
+                }
+
+                while let Some(peer) = haves.recv().await {
+                    if found.insert(peer)
+                        && (sender.send(peer).await.is_err() || found.len() >= limit)
+                    {
+                        break;
+                    }
+                }
+            };
+            tokio::select! {
+                _ = query => {}
+                _ = sender.closed() => {}
+                _ = tokio::time::sleep(timeout) => {}
+            }
+
+            drop(haves);
+            {
+                let queries = &mut *provider_queries.lock().unwrap();
+                if let Some(senders) = queries.get_mut(&cid) {
+                    senders.retain(|s| !s.is_closed());
+                    if senders.is_empty() {
+                        queries.remove(&cid);
+                    }
+                }
+            }
+
+            // withdraw the want-have, unless a session started looking for the block since
+            if !client.get_wantlist().await.contains(&cid) {
+                let mut cancel = BitswapMessage::new(false);
+                cancel.cancel(cid);
+                let sends = peers
+                    .iter()
+                    .map(|peer| network.send_message(*peer, cancel.clone()));
+                futures::future::join_all(sends).await;
+            }
+            debug!("find_providers {}: done, found {}", cid, found.len());
+        });
+
+        receiver
+    }
+
+    /// Forwards the senders of HAVEs and blocks to the provider queries waiting for them.
+    fn notify_provider_queries(&self, peer: PeerId, message: &BitswapMessage) {
+        let queries = &*self.provider_queries.lock().unwrap();
+        if queries.is_empty() {
+            return;
+        }
+        for cid in message.haves().chain(message.blocks().map(|b| b.cid())) {
+            if let Some(senders) = queries.get(cid) {
+                for sender in senders {
+                    sender.try_send(peer).ok();
+                }
+            }
+        }
+    }
+
     fn peer_connected(&self, peer: PeerId) {
         if let Err(err) = self.peers_connected.try_send(peer) {
             warn!(
@@ -300,6 +410,7 @@ impl<S: Store> Bitswap<S> {
     fn receive_message(&self, peer: PeerId, message: BitswapMessage) {
         inc!(BitswapMetrics::MessagesReceived);
         record!(BitswapMetrics::MessageBytesIn, message.encoded_len() as u64);
+        self.notify_provider_queries(peer, &message);
         // TODO: Handle backpressure properly
         if let Err(err) = self.incoming_messages.try_send((peer, message)) {
             warn!(
@@ -772,6 +883,74 @@ mod tests {
         get_block::<1024>().await;
     }
 
+    #[tokio::test]
+    async fn test_find_providers() {
+        let (peer1_id, trans) = mk_transport();
+        let store1 = TestStore::default();
+        let bs1 = Bitswap::new(peer1_id, store1.clone(), Config::default()).await;
+        let mut swarm1 = Swarm::with_tokio_executor(trans, bs1, peer1_id);
+        let block = create_random_block_v1();
+        store1
+            .store
+            .write()
+            .await
+            .insert(*block.cid(), block.clone());
+
+        Swarm::listen_on(&mut swarm1, "/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+        let addr = loop {
+            if let Some(SwarmEvent::NewListenAddr { address, .. }) = swarm1.next().await {
+                break address;
+            }
+        };
+        let peer1 = tokio::task::spawn(async move {
+            loop {
+                let ev = swarm1.next().await;
+                trace!("peer1: {:?}", ev);
+            }
+        });
+
+        let (peer2_id, trans) = mk_transport();
+        let bs2 = Bitswap::new(peer2_id, TestStore::default(), Config::default()).await;
+        let mut swarm2 = Swarm::with_tokio_executor(trans, bs2, peer2_id);
+        let swarm2_bs = swarm2.behaviour().clone();
+        Swarm::dial(&mut swarm2, addr).unwrap();
+        let peer2 = tokio::task::spawn(async move {
+            loop {
+                match swarm2.next().await {
+                    Some(SwarmEvent::ConnectionEstablished { peer_id, .. }) => {
+                        // simulate identify to inform bitswap about the protocols
+                        swarm2
+                            .behaviour()
+                            .on_identify(&peer_id, &["/ipfs/bitswap/1.2.0".to_string()]);
+                    }
+                    ev => trace!("peer2: {:?}", ev),
+                }
+            }
+        });
+
+        while !matches!(
+            swarm2_bs.get_peer_state(&peer1_id),
+            Some(PeerState::Responsive(_, _))
+        ) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let timeout = Duration::from_secs(5);
+        let mut providers = swarm2_bs.find_providers(*block.cid(), 1, timeout);
+        assert_eq!(providers.recv().await, Some(peer1_id));
+        assert_eq!(providers.recv().await, None);
+
+        let missing = create_random_block_v1();
+        let mut providers = swarm2_bs.find_providers(*missing.cid(), 1, Duration::from_millis(500));
+        assert_eq!(providers.recv().await, None);
+        assert!(swarm2_bs.provider_queries.lock().unwrap().is_empty());
+
+        peer1.abort();
+        peer1.await.ok();
+        peer2.abort();
+        peer2.await.ok();
+    }
+
     async fn get_block<const N: usize>() {
         let (peer1_id, trans) = mk_transport();
         let store1 = TestStore::default();
@@ -33,7 +33,11 @@ use anyhow::{bail, Result};
 use iroh_rpc_client::Config as RpcClientConfig;
 
 pub use iroh_api::Api;
-pub use iroh_p2p::Libp2pConfig;
+pub use iroh_p2p::{
+    open_signed_record, sign_record, signed_record_key, GossipsubMessage, GossipsubValidator,
+    GossipsubValidators, Libp2pConfig, MessageAcceptance, RecordKey, RecordValidator,
+    RecordValidators, TopicScoreParams,
+};
 pub use iroh_unixfs::indexer::IndexerUrl;
 pub use reqwest::Url;
 
@@ -92,6 +96,7 @@ pub struct IrohBuilder {
     p2p: Option<P2pService>,
     http_resolvers: Vec<String>,
     indexer: Option<IndexerUrl>,
+    delegated_routers: Vec<String>,
 }
 
 impl Default for IrohBuilder {
@@ -108,6 +113,7 @@ impl IrohBuilder {
             p2p: None,
             http_resolvers: vec![],
             indexer: Some(IndexerUrl::default()),
+            delegated_routers: vec![],
         }
     }
 
@@ -157,6 +163,15 @@ impl IrohBuilder {
         self
     }
 
+    /// Uses the given delegated routing endpoints to find the providers of content.
+    ///
+    /// Delegated routers answer the `/routing/v1/providers/{cid}` HTTP API, they are
+    /// queried along with the indexer.
+    pub fn delegated_routers(mut self, routers: impl Iterator<Item = Url>) -> Self {
+        self.delegated_routers = routers.map(|u| u.to_string()).collect();
+        self
+    }
+
     /// Builds the iroh system.
     pub async fn build(self) -> Result<Iroh> {
         // TODO: would be good if we can verify the p2p service is correctly hooked up to
@@ -173,18 +188,24 @@ impl IrohBuilder {
             true => None,
             false => Some(self.http_resolvers),
         };
+        let delegated_routers = match self.delegated_routers.is_empty() {
+            true => None,
+            false => Some(self.delegated_routers),
+        };
 
         let rpc_config = RpcClientConfig {
             gateway_addr: None,
             p2p_addr: Some(p2p.addr()),
             store_addr: Some(store.addr()),
             channels: Some(1),
+            retry: None,
         };
         let api_config = iroh_api::config::Config {
             rpc_client: rpc_config,
             metrics: Default::default(),
             http_resolvers,
             indexer_endpoint: self.indexer,
+            delegated_routers,
         };
         let api = Api::new(api_config).await?;
 
@@ -4,7 +4,7 @@ use std::path::PathBuf;
 
 use anyhow::Result;
 use iroh_one::mem_p2p;
-use iroh_p2p::{Config as P2pConfig, Libp2pConfig};
+use iroh_p2p::{Config as P2pConfig, GossipsubValidators, Libp2pConfig, RecordValidators};
 use iroh_rpc_types::p2p::P2pAddr;
 use iroh_rpc_types::store::StoreAddr;
 use iroh_rpc_types::Addr;
@@ -35,11 +35,60 @@ impl P2pService {
     ///
     /// Note that [`Libp2pConfig::default`] binds to the `/ip4/0.0.0.0/tcp/4444` and
     /// `/ip4/0.0.0.0/udp/4445/quic-v1`.
+    ///
+    /// To join a private network set [`Libp2pConfig::swarm_key_path`].  As QUIC does not
+    /// support private networks it is disabled and its listening addresses are skipped.
     // TODO: Provide a way to use an in-memory keystore.
     pub async fn new(
         libp2p_config: Libp2pConfig,
         key_store_path: PathBuf,
         store_service: StoreAddr,
+    ) -> Result<Self> {
+        Self::with_gossipsub_validators(
+            libp2p_config,
+            key_store_path,
+            store_service,
+            GossipsubValidators::default(),
+        )
+        .await
+    }
+
+    /// Starts a new iroh peer-to-peer service which validates gossipsub messages.
+    ///
+    /// Like [`P2pService::new`], but the messages received on the topics of `validators`
+    /// are only forwarded to other peers once their validator accepts them.  With
+    /// [`Libp2pConfig::gossipsub_peer_scoring`] enabled, peers that deliver rejected
+    /// messages are penalized, see [`GossipsubValidators::set_topic_params`] to score the
+    /// peers of a topic differently.
+    pub async fn with_gossipsub_validators(
+        libp2p_config: Libp2pConfig,
+        key_store_path: PathBuf,
+        store_service: StoreAddr,
+        validators: GossipsubValidators,
+    ) -> Result<Self> {
+        Self::with_validators(
+            libp2p_config,
+            key_store_path,
+            store_service,
+            validators,
+            RecordValidators::default(),
+        )
+        .await
+    }
+
+    /// Starts a new iroh peer-to-peer service which validates gossipsub messages and DHT
+    /// records.
+    ///
+    /// Like [`P2pService::with_gossipsub_validators`], but the DHT records under the
+    /// namespaces of `record_validators` are also validated, before they are put into the
+    /// DHT, stored for other peers or returned from lookups.  The `ipns` and `signed`
+    /// namespaces of [`RecordValidators::default`] are only supported if kept.
+    pub async fn with_validators(
+        libp2p_config: Libp2pConfig,
+        key_store_path: PathBuf,
+        store_service: StoreAddr,
+        gossipsub_validators: GossipsubValidators,
+        record_validators: RecordValidators,
     ) -> Result<Self> {
         let addr = Addr::new_mem();
         let mut config = P2pConfig::default_with_rpc(addr.clone());
@@ -47,7 +96,13 @@ impl P2pService {
         config.rpc_client.store_addr = Some(store_service);
         config.libp2p = libp2p_config;
         config.key_store_path = key_store_path;
-        let task = mem_p2p::start(addr.clone(), config).await?;
+        let task = mem_p2p::start_with_validators(
+            addr.clone(),
+            config,
+            gossipsub_validators,
+            record_validators,
+        )
+        .await?;
         Ok(Self { task, addr })
     }
 
@@ -126,4 +181,29 @@ mod tests {
 
         // Dropping the store here, no need to shut it down nicely.
     }
+
+    #[tokio::test]
+    async fn test_private_network() {
+        let dir = testdir!();
+        let store_dir = dir.join("store");
+        let store = RocksStoreService::new(store_dir).await.unwrap();
+        let swarm_key = dir.join("swarm.key");
+        std::fs::write(
+            &swarm_key,
+            format!("/key/swarm/psk/1.0.0/\n/base16/\n{}\n", "ab".repeat(32)),
+        )
+        .unwrap();
+
+        let mut cfg = Libp2pConfig::default();
+        // QUIC can not be used in a private network, it is disabled
+        cfg.listening_multiaddrs = vec![
+            "/ip4/127.0.0.1/tcp/0".parse().unwrap(),
+            "/ip4/127.0.0.1/udp/0/quic-v1".parse().unwrap(),
+        ];
+        cfg.swarm_key_path = Some(swarm_key);
+        let svc = P2pService::new(cfg, dir.clone(), store.addr())
+            .await
+            .unwrap();
+        svc.stop().await.unwrap();
+    }
 }
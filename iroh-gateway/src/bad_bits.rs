@@ -182,6 +182,7 @@ mod tests {
                 p2p_addr: None,
                 store_addr: None,
                 channels: Some(1),
+                retry: None,
             },
         );
         config.redirect_to_subdomain = false;
@@ -197,6 +198,12 @@ mod tests {
                 .map(|u| GatewayUrl::from_str(u).unwrap())
                 .collect(),
             indexer: config.indexer_endpoint.as_ref().map(|p| p.parse().unwrap()),
+            delegated_routers: config
+                .delegated_routers
+                .iter()
+                .flatten()
+                .map(|u| u.parse().unwrap())
+                .collect(),
         };
         let content_loader =
             FullLoader::new(rpc_client.clone(), loader_config).expect("invalid config");
@@ -78,6 +78,8 @@ pub struct Config {
     pub dns_resolver: DnsResolverConfig,
     /// Indexer node to use.
     pub indexer_endpoint: Option<String>,
+    /// Delegated routing endpoints to find providers with.
+    pub delegated_routers: Option<Vec<String>>,
     /// rpc addresses for the gateway & addresses for the rpc client to dial
     pub rpc_client: RpcClientConfig,
     // NOTE: for toml to serialize properly, the "table" values must be serialized at the end, and
@@ -100,6 +102,7 @@ impl Config {
             http_resolvers: None,
             dns_resolver: DnsResolverConfig::default(),
             indexer_endpoint: None,
+            delegated_routers: None,
             use_denylist: false,
             redirect_to_subdomain: false,
         }
@@ -176,6 +179,7 @@ impl Default for Config {
             http_resolvers: None,
             dns_resolver: DnsResolverConfig::default(),
             indexer_endpoint: None,
+            delegated_routers: None,
             use_denylist: false,
             redirect_to_subdomain: false,
         };
@@ -206,6 +210,9 @@ impl Source for Config {
         if let Some(indexer_endpoint) = &self.indexer_endpoint {
             insert_into_config_map(&mut map, "indexer_endpoint", indexer_endpoint.clone());
         }
+        if let Some(delegated_routers) = &self.delegated_routers {
+            insert_into_config_map(&mut map, "delegated_routers", delegated_routers.clone());
+        }
         Ok(map)
     }
 }
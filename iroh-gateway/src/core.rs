@@ -170,6 +170,12 @@ mod tests {
                 .map(|u| u.parse().unwrap())
                 .collect(),
             indexer: config.indexer_endpoint.as_ref().map(|p| p.parse().unwrap()),
+            delegated_routers: config
+                .delegated_routers
+                .iter()
+                .flatten()
+                .map(|u| u.parse().unwrap())
+                .collect(),
         };
         let content_loader =
             FullLoader::new(rpc_client.clone(), loader_config).expect("invalid config");
@@ -264,6 +270,7 @@ mod tests {
                 p2p_addr: None,
                 store_addr: Some(store_client_addr),
                 channels: Some(1),
+                retry: None,
             },
         );
         config.set_default_headers();
@@ -290,6 +297,7 @@ mod tests {
                 p2p_addr: None,
                 store_addr: None,
                 channels: Some(1),
+                retry: None,
             },
         );
         config.set_default_headers();
@@ -321,6 +329,7 @@ mod tests {
                 p2p_addr: None,
                 store_addr: None,
                 channels: Some(1),
+                retry: None,
             },
         );
         config.set_default_headers();
@@ -352,6 +361,7 @@ mod tests {
                 p2p_addr: None,
                 store_addr: Some(store_client_addr),
                 channels: Some(1),
+                retry: None,
             },
         );
         config.set_default_headers();
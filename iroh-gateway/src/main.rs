@@ -65,6 +65,13 @@ async fn main() -> Result<()> {
                 .map(|u| u.parse())
                 .transpose()
                 .context("invalid indexer endpoint")?,
+            delegated_routers: config
+                .delegated_routers
+                .iter()
+                .flatten()
+                .map(|u| u.parse())
+                .collect::<Result<_, _>>()
+                .context("invalid delegated router url")?,
         },
     )?;
     let handler = Core::new(
@@ -17,6 +17,26 @@ pub(crate) struct Metrics {
     skipped_peer_bitswap: Counter,
     skipped_peer_kad: Counter,
     loops: Counter,
+    reprovider_runs: Counter,
+    reprovided_cids: Counter,
+    reprovide_failures: Counter,
+    trimmed_peers: Counter,
+    bytes_in: Counter,
+    bytes_out: Counter,
+    bitswap_bytes_in: Counter,
+    bitswap_bytes_out: Counter,
+    kad_bytes_in: Counter,
+    kad_bytes_out: Counter,
+    gossipsub_bytes_in: Counter,
+    gossipsub_bytes_out: Counter,
+    identify_bytes_in: Counter,
+    identify_bytes_out: Counter,
+    relay_reservations_accepted: Counter,
+    relay_reservations_denied: Counter,
+    relay_reservations_timed_out: Counter,
+    relay_circuits_accepted: Counter,
+    relay_circuits_denied: Counter,
+    relay_circuits_closed: Counter,
 }
 
 impl fmt::Debug for Metrics {
@@ -54,12 +74,151 @@ impl Metrics {
         let loops = Counter::default();
         sub_registry.register(P2PMetrics::LoopCounter.name(), "", Box::new(loops.clone()));
 
+        let reprovider_runs = Counter::default();
+        sub_registry.register(
+            P2PMetrics::ReproviderRuns.name(),
+            "",
+            Box::new(reprovider_runs.clone()),
+        );
+        let reprovided_cids = Counter::default();
+        sub_registry.register(
+            P2PMetrics::ReprovidedCids.name(),
+            "",
+            Box::new(reprovided_cids.clone()),
+        );
+        let reprovide_failures = Counter::default();
+        sub_registry.register(
+            P2PMetrics::ReprovideFailures.name(),
+            "",
+            Box::new(reprovide_failures.clone()),
+        );
+
+        let trimmed_peers = Counter::default();
+        sub_registry.register(
+            P2PMetrics::TrimmedPeers.name(),
+            "",
+            Box::new(trimmed_peers.clone()),
+        );
+
+        let bytes_in = Counter::default();
+        sub_registry.register(P2PMetrics::BytesIn.name(), "", Box::new(bytes_in.clone()));
+        let bytes_out = Counter::default();
+        sub_registry.register(P2PMetrics::BytesOut.name(), "", Box::new(bytes_out.clone()));
+
+        let bitswap_bytes_in = Counter::default();
+        sub_registry.register(
+            P2PMetrics::BitswapBytesIn.name(),
+            "",
+            Box::new(bitswap_bytes_in.clone()),
+        );
+        let bitswap_bytes_out = Counter::default();
+        sub_registry.register(
+            P2PMetrics::BitswapBytesOut.name(),
+            "",
+            Box::new(bitswap_bytes_out.clone()),
+        );
+
+        let kad_bytes_in = Counter::default();
+        sub_registry.register(
+            P2PMetrics::KadBytesIn.name(),
+            "",
+            Box::new(kad_bytes_in.clone()),
+        );
+        let kad_bytes_out = Counter::default();
+        sub_registry.register(
+            P2PMetrics::KadBytesOut.name(),
+            "",
+            Box::new(kad_bytes_out.clone()),
+        );
+
+        let gossipsub_bytes_in = Counter::default();
+        sub_registry.register(
+            P2PMetrics::GossipsubBytesIn.name(),
+            "",
+            Box::new(gossipsub_bytes_in.clone()),
+        );
+        let gossipsub_bytes_out = Counter::default();
+        sub_registry.register(
+            P2PMetrics::GossipsubBytesOut.name(),
+            "",
+            Box::new(gossipsub_bytes_out.clone()),
+        );
+
+        let identify_bytes_in = Counter::default();
+        sub_registry.register(
+            P2PMetrics::IdentifyBytesIn.name(),
+            "",
+            Box::new(identify_bytes_in.clone()),
+        );
+        let identify_bytes_out = Counter::default();
+        sub_registry.register(
+            P2PMetrics::IdentifyBytesOut.name(),
+            "",
+            Box::new(identify_bytes_out.clone()),
+        );
+        let relay_reservations_accepted = Counter::default();
+        sub_registry.register(
+            P2PMetrics::RelayReservationsAccepted.name(),
+            "",
+            Box::new(relay_reservations_accepted.clone()),
+        );
+        let relay_reservations_denied = Counter::default();
+        sub_registry.register(
+            P2PMetrics::RelayReservationsDenied.name(),
+            "",
+            Box::new(relay_reservations_denied.clone()),
+        );
+        let relay_reservations_timed_out = Counter::default();
+        sub_registry.register(
+            P2PMetrics::RelayReservationsTimedOut.name(),
+            "",
+            Box::new(relay_reservations_timed_out.clone()),
+        );
+        let relay_circuits_accepted = Counter::default();
+        sub_registry.register(
+            P2PMetrics::RelayCircuitsAccepted.name(),
+            "",
+            Box::new(relay_circuits_accepted.clone()),
+        );
+        let relay_circuits_denied = Counter::default();
+        sub_registry.register(
+            P2PMetrics::RelayCircuitsDenied.name(),
+            "",
+            Box::new(relay_circuits_denied.clone()),
+        );
+        let relay_circuits_closed = Counter::default();
+        sub_registry.register(
+            P2PMetrics::RelayCircuitsClosed.name(),
+            "",
+            Box::new(relay_circuits_closed.clone()),
+        );
+
         Self {
             bad_peers,
             bad_peers_removed,
             skipped_peer_bitswap,
             skipped_peer_kad,
             loops,
+            reprovider_runs,
+            reprovided_cids,
+            reprovide_failures,
+            trimmed_peers,
+            bytes_in,
+            bytes_out,
+            bitswap_bytes_in,
+            bitswap_bytes_out,
+            kad_bytes_in,
+            kad_bytes_out,
+            gossipsub_bytes_in,
+            gossipsub_bytes_out,
+            identify_bytes_in,
+            identify_bytes_out,
+            relay_reservations_accepted,
+            relay_reservations_denied,
+            relay_reservations_timed_out,
+            relay_circuits_accepted,
+            relay_circuits_denied,
+            relay_circuits_closed,
         }
     }
 }
@@ -79,6 +238,46 @@ impl MetricsRecorder for Metrics {
             self.skipped_peer_kad.inc_by(value);
         } else if m.name() == P2PMetrics::LoopCounter.name() {
             self.loops.inc_by(value);
+        } else if m.name() == P2PMetrics::ReproviderRuns.name() {
+            self.reprovider_runs.inc_by(value);
+        } else if m.name() == P2PMetrics::ReprovidedCids.name() {
+            self.reprovided_cids.inc_by(value);
+        } else if m.name() == P2PMetrics::ReprovideFailures.name() {
+            self.reprovide_failures.inc_by(value);
+        } else if m.name() == P2PMetrics::TrimmedPeers.name() {
+            self.trimmed_peers.inc_by(value);
+        } else if m.name() == P2PMetrics::BytesIn.name() {
+            self.bytes_in.inc_by(value);
+        } else if m.name() == P2PMetrics::BytesOut.name() {
+            self.bytes_out.inc_by(value);
+        } else if m.name() == P2PMetrics::BitswapBytesIn.name() {
+            self.bitswap_bytes_in.inc_by(value);
+        } else if m.name() == P2PMetrics::BitswapBytesOut.name() {
+            self.bitswap_bytes_out.inc_by(value);
+        } else if m.name() == P2PMetrics::KadBytesIn.name() {
+            self.kad_bytes_in.inc_by(value);
+        } else if m.name() == P2PMetrics::KadBytesOut.name() {
+            self.kad_bytes_out.inc_by(value);
+        } else if m.name() == P2PMetrics::GossipsubBytesIn.name() {
+            self.gossipsub_bytes_in.inc_by(value);
+        } else if m.name() == P2PMetrics::GossipsubBytesOut.name() {
+            self.gossipsub_bytes_out.inc_by(value);
+        } else if m.name() == P2PMetrics::IdentifyBytesIn.name() {
+            self.identify_bytes_in.inc_by(value);
+        } else if m.name() == P2PMetrics::IdentifyBytesOut.name() {
+            self.identify_bytes_out.inc_by(value);
+        } else if m.name() == P2PMetrics::RelayReservationsAccepted.name() {
+            self.relay_reservations_accepted.inc_by(value);
+        } else if m.name() == P2PMetrics::RelayReservationsDenied.name() {
+            self.relay_reservations_denied.inc_by(value);
+        } else if m.name() == P2PMetrics::RelayReservationsTimedOut.name() {
+            self.relay_reservations_timed_out.inc_by(value);
+        } else if m.name() == P2PMetrics::RelayCircuitsAccepted.name() {
+            self.relay_circuits_accepted.inc_by(value);
+        } else if m.name() == P2PMetrics::RelayCircuitsDenied.name() {
+            self.relay_circuits_denied.inc_by(value);
+        } else if m.name() == P2PMetrics::RelayCircuitsClosed.name() {
+            self.relay_circuits_closed.inc_by(value);
         } else {
             error!("record (bitswap): unknown metric {}", m.name());
         }
@@ -99,6 +298,26 @@ pub enum P2PMetrics {
     SkippedPeerBitswap,
     SkippedPeerKad,
     LoopCounter,
+    ReproviderRuns,
+    ReprovidedCids,
+    ReprovideFailures,
+    TrimmedPeers,
+    BytesIn,
+    BytesOut,
+    BitswapBytesIn,
+    BitswapBytesOut,
+    KadBytesIn,
+    KadBytesOut,
+    GossipsubBytesIn,
+    GossipsubBytesOut,
+    IdentifyBytesIn,
+    IdentifyBytesOut,
+    RelayReservationsAccepted,
+    RelayReservationsDenied,
+    RelayReservationsTimedOut,
+    RelayCircuitsAccepted,
+    RelayCircuitsDenied,
+    RelayCircuitsClosed,
 }
 
 impl MetricType for P2PMetrics {
@@ -109,6 +328,26 @@ impl MetricType for P2PMetrics {
             P2PMetrics::SkippedPeerBitswap => "skipped_peer_bitswap",
             P2PMetrics::SkippedPeerKad => "skipped_peer_kad",
             P2PMetrics::LoopCounter => "loop_counter",
+            P2PMetrics::ReproviderRuns => "reprovider_runs",
+            P2PMetrics::ReprovidedCids => "reprovided_cids",
+            P2PMetrics::ReprovideFailures => "reprovide_failures",
+            P2PMetrics::TrimmedPeers => "trimmed_peers",
+            P2PMetrics::BytesIn => "bytes_in",
+            P2PMetrics::BytesOut => "bytes_out",
+            P2PMetrics::BitswapBytesIn => "bitswap_bytes_in",
+            P2PMetrics::BitswapBytesOut => "bitswap_bytes_out",
+            P2PMetrics::KadBytesIn => "kad_bytes_in",
+            P2PMetrics::KadBytesOut => "kad_bytes_out",
+            P2PMetrics::GossipsubBytesIn => "gossipsub_bytes_in",
+            P2PMetrics::GossipsubBytesOut => "gossipsub_bytes_out",
+            P2PMetrics::IdentifyBytesIn => "identify_bytes_in",
+            P2PMetrics::IdentifyBytesOut => "identify_bytes_out",
+            P2PMetrics::RelayReservationsAccepted => "relay_reservations_accepted",
+            P2PMetrics::RelayReservationsDenied => "relay_reservations_denied",
+            P2PMetrics::RelayReservationsTimedOut => "relay_reservations_timed_out",
+            P2PMetrics::RelayCircuitsAccepted => "relay_circuits_accepted",
+            P2PMetrics::RelayCircuitsDenied => "relay_circuits_denied",
+            P2PMetrics::RelayCircuitsClosed => "relay_circuits_closed",
         }
     }
 }
@@ -27,6 +27,9 @@ pub struct Args {
     /// Path to the store
     #[clap(long = "store-path")]
     pub store_path: Option<PathBuf>,
+    /// Path to a swarm key file, joins the private network it defines.
+    #[clap(long = "swarm-key")]
+    pub swarm_key: Option<PathBuf>,
     #[clap(long)]
     pub cfg: Option<PathBuf>,
 }
@@ -52,6 +55,12 @@ impl Args {
         if let Some(path) = self.store_path.clone() {
             map.insert("store.path", path.to_str().unwrap_or("").to_string());
         }
+        if let Some(path) = self.swarm_key.clone() {
+            map.insert(
+                "p2p.libp2p.swarm_key_path",
+                path.to_str().unwrap_or("").to_string(),
+            );
+        }
         #[cfg(all(feature = "http-uds-gateway", unix))]
         if let Some(path) = self.gateway_uds_path.clone() {
             map.insert("gateway_uds_path", path.to_str().unwrap_or("").to_string());
@@ -68,6 +68,7 @@ impl Config {
             p2p_addr: None,
             store_addr: None,
             channels: Some(1),
+            retry: None,
         }
     }
 
@@ -117,7 +118,11 @@ fn default_store_config(
 
 fn default_p2p_config(ipfsd: RpcClientConfig, key_store_path: PathBuf) -> iroh_p2p::config::Config {
     iroh_p2p::config::Config {
+        kad_store_path: Some(key_store_path.join("kad")),
+        peerstore_path: Some(key_store_path.join("peers")),
         key_store_path,
+        key_passphrase_env: None,
+        key_passphrase_path: None,
         libp2p: Libp2pConfig::default(),
         rpc_client: ipfsd,
     }
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 #[allow(unused_imports)]
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use clap::Parser;
 use iroh_gateway::{bad_bits::BadBits, core::Core, metrics};
 #[cfg(all(feature = "http-uds-gateway", unix))]
@@ -87,6 +87,14 @@ async fn main() -> Result<()> {
                 .map(|u| u.parse())
                 .collect::<Result<_>>()?,
             indexer: None, // TODO
+            delegated_routers: config
+                .gateway
+                .delegated_routers
+                .iter()
+                .flatten()
+                .map(|u| u.parse())
+                .collect::<Result<_, _>>()
+                .context("invalid delegated router url")?,
         },
     )?;
     let shared_state = Core::make_state(
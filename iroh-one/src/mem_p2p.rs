@@ -1,6 +1,6 @@
 /// A p2p instance listening on a memory rpc channel.
 use iroh_p2p::config::Config;
-use iroh_p2p::{DiskStorage, Keychain, Node};
+use iroh_p2p::{DiskStorage, GossipsubValidators, Keychain, Node, RecordValidators};
 use iroh_rpc_types::p2p::P2pAddr;
 use tokio::task;
 use tokio::task::JoinHandle;
@@ -8,9 +8,38 @@ use tracing::error;
 
 /// Starts a new p2p node, using the given mem rpc channel.
 pub async fn start(rpc_addr: P2pAddr, config: Config) -> anyhow::Result<JoinHandle<()>> {
-    let kc = Keychain::<DiskStorage>::new(config.key_store_path.clone()).await?;
+    start_with_gossipsub_validators(rpc_addr, config, GossipsubValidators::default()).await
+}
+
+/// Starts a new p2p node, which validates the gossipsub messages of some topics with the
+/// given validators.
+pub async fn start_with_gossipsub_validators(
+    rpc_addr: P2pAddr,
+    config: Config,
+    validators: GossipsubValidators,
+) -> anyhow::Result<JoinHandle<()>> {
+    start_with_validators(rpc_addr, config, validators, RecordValidators::default()).await
+}
+
+/// Starts a new p2p node, which validates the gossipsub messages of some topics and the DHT
+/// records of some namespaces with the given validators.
+pub async fn start_with_validators(
+    rpc_addr: P2pAddr,
+    config: Config,
+    gossipsub_validators: GossipsubValidators,
+    record_validators: RecordValidators,
+) -> anyhow::Result<JoinHandle<()>> {
+    let kc = match config.key_passphrase()? {
+        Some(passphrase) => {
+            Keychain::<DiskStorage>::with_passphrase(config.key_store_path.clone(), passphrase)
+                .await?
+        }
+        None => Keychain::<DiskStorage>::new(config.key_store_path.clone()).await?,
+    };
 
     let mut p2p = Node::new(config, rpc_addr, kc).await?;
+    p2p.set_gossipsub_validators(gossipsub_validators);
+    p2p.set_record_validators(record_validators);
 
     // Start services
     let p2p_task = task::spawn(async move {
@@ -0,0 +1,463 @@
+//! Accounting of the bandwidth used per peer and per protocol.
+//!
+//! The muxer of every connection is wrapped to count the bytes read from and written to its
+//! substreams, which excludes the overhead of the transport, encryption and muxer. A
+//! substream is attributed to the protocol proposed in its multistream-select negotiation.
+
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+
+use futures::{ready, AsyncRead, AsyncWrite};
+use iroh_metrics::{core::MRecorder, p2p::P2PMetrics, record};
+use iroh_rpc_types::p2p::{BandwidthProtocol, BandwidthStatsResponse, BandwidthTotals};
+use libp2p::core::muxing::{
+    StreamMuxer, StreamMuxerBox, StreamMuxerEvent, StreamMuxerExt, SubstreamBox,
+};
+use libp2p::PeerId;
+
+const PROTOCOLS: [BandwidthProtocol; 5] = [
+    BandwidthProtocol::Bitswap,
+    BandwidthProtocol::Kademlia,
+    BandwidthProtocol::Gossipsub,
+    BandwidthProtocol::Identify,
+    BandwidthProtocol::Other,
+];
+/// Substreams whose protocol is not known after this many bytes are attributed to
+/// [`BandwidthProtocol::Other`].
+const MAX_NEGOTIATION_LEN: usize = 256;
+const MULTISTREAM_HEADER: &[u8] = b"/multistream/1.0.0\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    inbound: AtomicU64,
+    outbound: AtomicU64,
+}
+
+impl Counters {
+    fn add(&self, direction: Direction, bytes: u64) {
+        let counter = match direction {
+            Direction::Inbound => &self.inbound,
+            Direction::Outbound => &self.outbound,
+        };
+        counter.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn totals(&self) -> BandwidthTotals {
+        BandwidthTotals {
+            inbound: self.inbound.load(Ordering::Relaxed),
+            outbound: self.outbound.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The bandwidth used by the node, shared by all connections.
+#[derive(Debug, Default)]
+pub(crate) struct Bandwidth {
+    total: Counters,
+    protocols: [Counters; PROTOCOLS.len()],
+    /// The counters of the connected peers, shared by their connections.
+    peers: Mutex<HashMap<PeerId, Weak<Counters>>>,
+}
+
+impl Bandwidth {
+    /// Returns the totals since the start of the node, the totals of the peers only cover
+    /// their current connections.
+    pub(crate) fn stats(&self) -> BandwidthStatsResponse {
+        let peers = self
+            .peers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(peer_id, counters)| Some((*peer_id, counters.upgrade()?.totals())))
+            .collect();
+        BandwidthStatsResponse {
+            total: self.total.totals(),
+            protocols: PROTOCOLS
+                .iter()
+                .map(|protocol| (*protocol, self.protocols[*protocol as usize].totals()))
+                .collect(),
+            peers,
+        }
+    }
+
+    fn peer(&self, peer_id: PeerId) -> Arc<Counters> {
+        let mut peers = self.peers.lock().unwrap();
+        if let Some(counters) = peers.get(&peer_id).and_then(Weak::upgrade) {
+            return counters;
+        }
+        peers.retain(|_, counters| counters.strong_count() > 0);
+        let counters = Arc::new(Counters::default());
+        peers.insert(peer_id, Arc::downgrade(&counters));
+        counters
+    }
+
+    fn add_total(&self, direction: Direction, bytes: u64) {
+        self.total.add(direction, bytes);
+        match direction {
+            Direction::Inbound => record!(P2PMetrics::BytesIn, bytes),
+            Direction::Outbound => record!(P2PMetrics::BytesOut, bytes),
+        }
+    }
+
+    fn add_protocol(&self, protocol: BandwidthProtocol, direction: Direction, bytes: u64) {
+        if bytes == 0 {
+            return;
+        }
+        self.protocols[protocol as usize].add(direction, bytes);
+        let metric = match (protocol, direction) {
+            (BandwidthProtocol::Bitswap, Direction::Inbound) => P2PMetrics::BitswapBytesIn,
+            (BandwidthProtocol::Bitswap, Direction::Outbound) => P2PMetrics::BitswapBytesOut,
+            (BandwidthProtocol::Kademlia, Direction::Inbound) => P2PMetrics::KadBytesIn,
+            (BandwidthProtocol::Kademlia, Direction::Outbound) => P2PMetrics::KadBytesOut,
+            (BandwidthProtocol::Gossipsub, Direction::Inbound) => P2PMetrics::GossipsubBytesIn,
+            (BandwidthProtocol::Gossipsub, Direction::Outbound) => P2PMetrics::GossipsubBytesOut,
+            (BandwidthProtocol::Identify, Direction::Inbound) => P2PMetrics::IdentifyBytesIn,
+            (BandwidthProtocol::Identify, Direction::Outbound) => P2PMetrics::IdentifyBytesOut,
+            (BandwidthProtocol::Other, _) => return,
+        };
+        record!(metric, bytes);
+    }
+}
+
+/// Counts the traffic of the substreams of a connection.
+pub(crate) struct CountingMuxer {
+    inner: StreamMuxerBox,
+    peer: Arc<Counters>,
+    bandwidth: Arc<Bandwidth>,
+}
+
+impl CountingMuxer {
+    pub(crate) fn new(inner: StreamMuxerBox, peer_id: PeerId, bandwidth: Arc<Bandwidth>) -> Self {
+        CountingMuxer {
+            inner,
+            peer: bandwidth.peer(peer_id),
+            bandwidth,
+        }
+    }
+
+    fn substream(&self, inner: SubstreamBox, direction: Direction) -> CountingSubstream {
+        CountingSubstream {
+            inner,
+            peer: self.peer.clone(),
+            bandwidth: self.bandwidth.clone(),
+            protocol: Attribution::Negotiating {
+                // the dialer of a substream proposes the protocol
+                proposal: Proposal::new(direction),
+                inbound: 0,
+                outbound: 0,
+            },
+        }
+    }
+}
+
+impl StreamMuxer for CountingMuxer {
+    type Substream = CountingSubstream;
+    type Error = io::Error;
+
+    fn poll_inbound(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+        let substream = ready!(this.inner.poll_inbound_unpin(cx))?;
+        Poll::Ready(Ok(this.substream(substream, Direction::Inbound)))
+    }
+
+    fn poll_outbound(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+        let substream = ready!(this.inner.poll_outbound_unpin(cx))?;
+        Poll::Ready(Ok(this.substream(substream, Direction::Outbound)))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().inner.poll_close_unpin(cx)
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<StreamMuxerEvent, Self::Error>> {
+        self.get_mut().inner.poll_unpin(cx)
+    }
+}
+
+/// The protocol the traffic of a substream is attributed to.
+enum Attribution {
+    /// The protocol is still negotiated, the traffic so far is attributed once it is known.
+    Negotiating {
+        proposal: Proposal,
+        inbound: u64,
+        outbound: u64,
+    },
+    Known(BandwidthProtocol),
+}
+
+/// A substream that counts its traffic.
+pub(crate) struct CountingSubstream {
+    inner: SubstreamBox,
+    peer: Arc<Counters>,
+    bandwidth: Arc<Bandwidth>,
+    protocol: Attribution,
+}
+
+impl CountingSubstream {
+    fn count(&mut self, direction: Direction, data: &[u8]) {
+        let bytes = data.len() as u64;
+        if bytes == 0 {
+            return;
+        }
+        self.peer.add(direction, bytes);
+        self.bandwidth.add_total(direction, bytes);
+        match &mut self.protocol {
+            Attribution::Known(protocol) => {
+                self.bandwidth.add_protocol(*protocol, direction, bytes);
+            }
+            Attribution::Negotiating {
+                proposal,
+                inbound,
+                outbound,
+            } => {
+                match direction {
+                    Direction::Inbound => *inbound += bytes,
+                    Direction::Outbound => *outbound += bytes,
+                }
+                if let Some(protocol) = proposal.feed(direction, data) {
+                    self.bandwidth
+                        .add_protocol(protocol, Direction::Inbound, *inbound);
+                    self.bandwidth
+                        .add_protocol(protocol, Direction::Outbound, *outbound);
+                    self.protocol = Attribution::Known(protocol);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for CountingSubstream {
+    fn drop(&mut self) {
+        if let Attribution::Negotiating {
+            inbound, outbound, ..
+        } = self.protocol
+        {
+            let other = BandwidthProtocol::Other;
+            self.bandwidth
+                .add_protocol(other, Direction::Inbound, inbound);
+            self.bandwidth
+                .add_protocol(other, Direction::Outbound, outbound);
+        }
+    }
+}
+
+impl AsyncRead for CountingSubstream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        this.count(Direction::Inbound, &buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncWrite for CountingSubstream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        this.count(Direction::Outbound, &buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+/// Collects the start of the multistream-select negotiation sent by the dialer of a
+/// substream.
+struct Proposal {
+    direction: Direction,
+    buf: Vec<u8>,
+}
+
+impl Proposal {
+    fn new(direction: Direction) -> Self {
+        Proposal {
+            direction,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Returns the proposed protocol, once known.
+    fn feed(&mut self, direction: Direction, data: &[u8]) -> Option<BandwidthProtocol> {
+        if direction != self.direction {
+            return None;
+        }
+        let len = data.len().min(MAX_NEGOTIATION_LEN - self.buf.len());
+        self.buf.extend_from_slice(&data[..len]);
+        match proposed_protocol(&self.buf) {
+            Some(protocol) => Some(protocol),
+            None if self.buf.len() >= MAX_NEGOTIATION_LEN => Some(BandwidthProtocol::Other),
+            None => None,
+        }
+    }
+}
+
+/// Returns the protocol proposed after the multistream-select header, `None` while the
+/// proposal is incomplete.
+fn proposed_protocol(buf: &[u8]) -> Option<BandwidthProtocol> {
+    let rest = match next_message(buf) {
+        Ok(Some((header, rest))) if header == MULTISTREAM_HEADER => rest,
+        Ok(None) => return None,
+        _ => return Some(BandwidthProtocol::Other),
+    };
+    match next_message(rest) {
+        Ok(Some((name, _))) => Some(protocol_of(name)),
+        Ok(None) => None,
+        Err(()) => Some(BandwidthProtocol::Other),
+    }
+}
+
+/// Splits off the next message of a negotiation, which is prefixed by its length.
+#[allow(clippy::type_complexity)]
+fn next_message(buf: &[u8]) -> Result<Option<(&[u8], &[u8])>, ()> {
+    match unsigned_varint::decode::usize(buf) {
+        Ok((len, rest)) if rest.len() >= len => Ok(Some(rest.split_at(len))),
+        Ok(_) | Err(unsigned_varint::decode::Error::Insufficient) => Ok(None),
+        Err(_) => Err(()),
+    }
+}
+
+fn protocol_of(name: &[u8]) -> BandwidthProtocol {
+    if name.starts_with(b"/ipfs/bitswap") {
+        BandwidthProtocol::Bitswap
+    } else if name.starts_with(b"/ipfs/kad/") || name.starts_with(b"/ipfs/lan/kad/") {
+        BandwidthProtocol::Kademlia
+    } else if name.starts_with(b"/meshsub/") || name.starts_with(b"/floodsub/") {
+        BandwidthProtocol::Gossipsub
+    } else if name.starts_with(b"/ipfs/id/") {
+        BandwidthProtocol::Identify
+    } else {
+        BandwidthProtocol::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn negotiation(protocol: &str) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for message in ["/multistream/1.0.0\n".to_string(), format!("{protocol}\n")] {
+            buf.push(message.len() as u8);
+            buf.extend_from_slice(message.as_bytes());
+        }
+        buf
+    }
+
+    #[test]
+    fn test_proposed_protocol() {
+        for (name, protocol) in [
+            ("/ipfs/bitswap/1.2.0", BandwidthProtocol::Bitswap),
+            ("/ipfs/kad/1.0.0", BandwidthProtocol::Kademlia),
+            ("/meshsub/1.1.0", BandwidthProtocol::Gossipsub),
+            ("/ipfs/id/push/1.0.0", BandwidthProtocol::Identify),
+            ("/ipfs/ping/1.0.0", BandwidthProtocol::Other),
+        ] {
+            assert_eq!(proposed_protocol(&negotiation(name)), Some(protocol));
+        }
+
+        let buf = negotiation("/ipfs/kad/1.0.0");
+        assert_eq!(proposed_protocol(&buf[..buf.len() - 1]), None);
+        assert_eq!(proposed_protocol(&buf[..3]), None);
+        assert_eq!(
+            proposed_protocol(b"\x05hello"),
+            Some(BandwidthProtocol::Other)
+        );
+    }
+
+    fn substream(
+        bandwidth: &Arc<Bandwidth>,
+        peer: &Arc<Counters>,
+        direction: Direction,
+    ) -> CountingSubstream {
+        CountingSubstream {
+            inner: SubstreamBox::new(futures::io::Cursor::new(Vec::new())),
+            peer: peer.clone(),
+            bandwidth: bandwidth.clone(),
+            protocol: Attribution::Negotiating {
+                proposal: Proposal::new(direction),
+                inbound: 0,
+                outbound: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn test_counting() {
+        let bandwidth = Arc::new(Bandwidth::default());
+        let peer_id = PeerId::random();
+        let peer = bandwidth.peer(peer_id);
+
+        // traffic during the negotiation is attributed once the protocol is known
+        let mut outbound_substream = substream(&bandwidth, &peer, Direction::Outbound);
+        let buf = negotiation("/meshsub/1.1.0");
+        outbound_substream.count(Direction::Outbound, &buf[..10]);
+        outbound_substream.count(Direction::Inbound, b"\x13/multistream/1.0.0\n");
+        outbound_substream.count(Direction::Outbound, &buf[10..]);
+        outbound_substream.count(Direction::Inbound, &[0; 100]);
+        outbound_substream.count(Direction::Outbound, &[0; 50]);
+        drop(outbound_substream);
+
+        let totals = BandwidthTotals {
+            inbound: 20 + 100,
+            outbound: buf.len() as u64 + 50,
+        };
+        let stats = bandwidth.stats();
+        assert_eq!(stats.total, totals);
+        assert_eq!(
+            stats.protocols[BandwidthProtocol::Gossipsub as usize],
+            (BandwidthProtocol::Gossipsub, totals)
+        );
+        assert_eq!(stats.peers, vec![(peer_id, totals)]);
+
+        // substreams without a known protocol count as other
+        let mut inbound_substream = substream(&bandwidth, &peer, Direction::Inbound);
+        inbound_substream.count(Direction::Inbound, b"\x13/multi");
+        drop(inbound_substream);
+        let stats = bandwidth.stats();
+        assert_eq!(
+            stats.protocols[BandwidthProtocol::Other as usize].1,
+            BandwidthTotals {
+                inbound: 7,
+                outbound: 0
+            }
+        );
+
+        // peers are only tracked while connected
+        drop(peer);
+        assert!(bandwidth.stats().peers.is_empty());
+    }
+}
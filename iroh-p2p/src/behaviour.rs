@@ -1,3 +1,4 @@
+use std::path::Path;
 use std::time::Duration;
 
 use anyhow::Result;
@@ -9,8 +10,8 @@ use libp2p::core::identity::Keypair;
 use libp2p::core::PeerId;
 use libp2p::gossipsub::{self, MessageAuthenticity};
 use libp2p::identify;
-use libp2p::kad::store::{MemoryStore, MemoryStoreConfig};
-use libp2p::kad::{Kademlia, KademliaConfig};
+use libp2p::kad::store::MemoryStoreConfig;
+use libp2p::kad::{Kademlia, KademliaConfig, KademliaStoreInserts};
 use libp2p::mdns::tokio::Behaviour as Mdns;
 use libp2p::multiaddr::Protocol;
 use libp2p::ping::Behaviour as Ping;
@@ -21,11 +22,21 @@ use libp2p::{autonat, dcutr};
 use tracing::{info, warn};
 
 pub(crate) use self::event::Event;
+pub(crate) use self::kad_mode::{ModalIdentify, ModalKademlia};
+pub(crate) use self::kad_store::KadStore;
+pub(crate) use self::peer_filter::{FilterList, FilterRule, PeerFilter, SharedPeerFilter};
 use self::peer_manager::PeerManager;
+pub(crate) use self::peerstore::{Peerstore, StoredPeer};
 use crate::config::Libp2pConfig;
+use crate::pubsub;
+use crate::relays;
 
 mod event;
+mod kad_mode;
+mod kad_store;
+mod peer_filter;
 mod peer_manager;
+mod peerstore;
 
 pub const PROTOCOL_VERSION: &str = "ipfs/0.1.0";
 pub const AGENT_VERSION: &str = concat!("iroh/", env!("CARGO_PKG_VERSION"));
@@ -35,9 +46,9 @@ pub const AGENT_VERSION: &str = concat!("iroh/", env!("CARGO_PKG_VERSION"));
 #[behaviour(out_event = "Event")]
 pub(crate) struct NodeBehaviour {
     ping: Ping,
-    identify: identify::Behaviour,
+    pub(crate) identify: ModalIdentify,
     pub(crate) bitswap: Toggle<Bitswap<BitswapStore>>,
-    pub(crate) kad: Toggle<Kademlia<MemoryStore>>,
+    pub(crate) kad: Toggle<ModalKademlia>,
     mdns: Toggle<Mdns>,
     pub(crate) autonat: Toggle<autonat::Behaviour>,
     relay: Toggle<relay::v2::relay::Relay>,
@@ -84,10 +95,12 @@ impl NodeBehaviour {
     pub async fn new(
         local_key: &Keypair,
         config: &Libp2pConfig,
+        kad_store_path: Option<&Path>,
         relay_client: Option<relay::v2::client::Client>,
         rpc_client: Client,
+        peer_filter: SharedPeerFilter,
     ) -> Result<Self> {
-        let peer_manager = PeerManager::default();
+        let peer_manager = PeerManager::new(config, peer_filter);
         let pub_key = local_key.public();
         let peer_id = pub_key.to_peer_id();
 
@@ -113,22 +126,29 @@ impl NodeBehaviour {
         }
         .into();
 
-        let kad = if config.kademlia {
+        let kad: Toggle<ModalKademlia> = if config.kademlia {
             info!("init kademlia");
-            // TODO: persist to store
             let mem_store_config = MemoryStoreConfig {
                 // enough for >10gb of unixfs files at the default chunk size
                 max_records: 1024 * 64,
                 max_provided_keys: 1024 * 64,
                 ..Default::default()
             };
-            let store = MemoryStore::with_config(peer_id, mem_store_config);
+            let store = match kad_store_path {
+                Some(path) => {
+                    info!("loading kademlia records from {}", path.display());
+                    KadStore::open(peer_id, mem_store_config, path)?
+                }
+                None => KadStore::memory(peer_id, mem_store_config),
+            };
 
             // TODO: make user configurable
             let mut kad_config = KademliaConfig::default();
             kad_config.set_parallelism(16usize.try_into().unwrap());
             // TODO: potentially lower (this is per query)
             kad_config.set_query_timeout(Duration::from_secs(60));
+            // the node stores the records of other peers once they are validated
+            kad_config.set_record_filtering(KademliaStoreInserts::FilterBoth);
 
             let mut kademlia = Kademlia::with_config(pub_key.to_peer_id(), store, kad_config);
             for multiaddr in &config.bootstrap_peers {
@@ -147,7 +167,7 @@ impl NodeBehaviour {
                 warn!("Kademlia bootstrap failed: {}", e);
             }
 
-            Some(kademlia)
+            Some(ModalKademlia::new(kademlia, config.kademlia_mode))
         } else {
             None
         }
@@ -172,7 +192,7 @@ impl NodeBehaviour {
 
         let relay = if config.relay_server {
             info!("init relay server");
-            let config = relay::v2::relay::Config::default();
+            let config = relays::relay_server_config(config);
             let r = relay::v2::relay::Relay::new(local_key.public().to_peer_id(), config);
             Some(r)
         } else {
@@ -194,17 +214,25 @@ impl NodeBehaviour {
             let config = identify::Config::new(PROTOCOL_VERSION.into(), local_key.public())
                 .with_agent_version(String::from(AGENT_VERSION))
                 .with_cache_size(64 * 1024);
-            identify::Behaviour::new(config)
+            ModalIdentify::new(identify::Behaviour::new(config), kad.as_ref())
         };
 
         let gossipsub = if config.gossipsub {
             info!("init gossipsub");
-            let gossipsub_config = gossipsub::GossipsubConfig::default();
+            // messages are only forwarded once the validators accepted them
+            let gossipsub_config = gossipsub::GossipsubConfigBuilder::default()
+                .validate_messages()
+                .build()
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
             let message_authenticity = MessageAuthenticity::Signed(local_key.clone());
-            Some(
-                gossipsub::Gossipsub::new(message_authenticity, gossipsub_config)
-                    .map_err(|e| anyhow::anyhow!("{}", e))?,
-            )
+            let mut gossipsub = gossipsub::Gossipsub::new(message_authenticity, gossipsub_config)
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            if let Some((params, thresholds)) = pubsub::peer_score_params(config) {
+                gossipsub
+                    .with_peer_score(params, thresholds)
+                    .map_err(|e| anyhow::anyhow!("invalid gossipsub scoring: {}", e))?;
+            }
+            Some(gossipsub)
         } else {
             None
         }
@@ -0,0 +1,597 @@
+//! Kademlia in client or server mode.
+//!
+//! `libp2p-kad` always answers the queries of other peers. In client mode the connection
+//! handlers refuse inbound Kademlia streams, so the node only queries the DHT, and identify
+//! stops announcing the Kademlia protocols so that other peers don't add the node to their
+//! routing tables. Switching modes updates the handlers of the open connections as well.
+
+use std::collections::{HashMap, VecDeque};
+use std::ops::{Deref, DerefMut};
+use std::task::{Context, Poll};
+
+use libp2p::core::connection::ConnectionId;
+use libp2p::core::either::{EitherError, EitherOutput};
+use libp2p::core::transport::ListenerId;
+use libp2p::core::upgrade::{DeniedUpgrade, EitherUpgrade, InboundUpgrade, OutboundUpgrade};
+use libp2p::core::ConnectedPoint;
+use libp2p::identify;
+use libp2p::kad::Kademlia;
+use libp2p::swarm::{
+    ConnectionHandler, ConnectionHandlerEvent, ConnectionHandlerUpgrErr, DialError,
+    IntoConnectionHandler, KeepAlive, NegotiatedSubstream, NetworkBehaviour,
+    NetworkBehaviourAction, NotifyHandler, PollParameters, SubstreamProtocol,
+};
+use libp2p::{Multiaddr, PeerId};
+use tracing::info;
+
+use super::KadStore;
+use crate::config::KademliaMode;
+
+type KadProto = <Kademlia<KadStore> as NetworkBehaviour>::ConnectionHandler;
+type KadHandler = <KadProto as IntoConnectionHandler>::Handler;
+type KadInEvent = <KadHandler as ConnectionHandler>::InEvent;
+
+/// [`Kademlia`] that serves other peers only in server mode.
+///
+/// Dereferences to the wrapped [`Kademlia`].
+pub(crate) struct ModalKademlia {
+    inner: Kademlia<KadStore>,
+    mode: KademliaMode,
+    /// Whether the connections answer the queries of other peers.
+    server: bool,
+    connections: HashMap<ConnectionId, PeerId>,
+    /// Connections whose handlers have to learn the current mode.
+    pending_modes: VecDeque<(PeerId, ConnectionId)>,
+}
+
+impl ModalKademlia {
+    pub fn new(inner: Kademlia<KadStore>, mode: KademliaMode) -> Self {
+        ModalKademlia {
+            inner,
+            mode,
+            // in auto mode the node serves until AutoNAT reports it as private
+            server: mode != KademliaMode::Client,
+            connections: HashMap::new(),
+            pending_modes: VecDeque::new(),
+        }
+    }
+
+    /// Whether the node answers the queries of other peers.
+    pub fn is_server(&self) -> bool {
+        self.server
+    }
+
+    /// The protocols the node serves in server mode.
+    pub fn protocol_names(&self) -> Vec<Vec<u8>> {
+        self.inner
+            .protocol_names()
+            .iter()
+            .map(|name| name.to_vec())
+            .collect()
+    }
+
+    /// Follows the reachability reported by AutoNAT in auto mode.
+    ///
+    /// Returns whether the mode changed.
+    pub fn set_private(&mut self, private: bool) -> bool {
+        if self.mode != KademliaMode::Auto || self.server != private {
+            return false;
+        }
+        info!(
+            "kad: switching to {} mode",
+            if private { "client" } else { "server" }
+        );
+        self.server = !private;
+        self.pending_modes = self
+            .connections
+            .iter()
+            .map(|(connection, peer_id)| (*peer_id, *connection))
+            .collect();
+        true
+    }
+}
+
+impl Deref for ModalKademlia {
+    type Target = Kademlia<KadStore>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for ModalKademlia {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl NetworkBehaviour for ModalKademlia {
+    type ConnectionHandler = ModalHandlerProto;
+    type OutEvent = <Kademlia<KadStore> as NetworkBehaviour>::OutEvent;
+
+    fn new_handler(&mut self) -> Self::ConnectionHandler {
+        ModalHandlerProto {
+            inner: self.inner.new_handler(),
+            server: self.server,
+        }
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.inner.addresses_of_peer(peer_id)
+    }
+
+    fn inject_connection_established(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        endpoint: &ConnectedPoint,
+        failed_addresses: Option<&Vec<Multiaddr>>,
+        other_established: usize,
+    ) {
+        self.connections.insert(*connection_id, *peer_id);
+        // the handler may have been created before the last switch
+        self.pending_modes.push_back((*peer_id, *connection_id));
+        self.inner.inject_connection_established(
+            peer_id,
+            connection_id,
+            endpoint,
+            failed_addresses,
+            other_established,
+        );
+    }
+
+    fn inject_connection_closed(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        endpoint: &ConnectedPoint,
+        handler: <Self::ConnectionHandler as IntoConnectionHandler>::Handler,
+        remaining_established: usize,
+    ) {
+        self.connections.remove(connection_id);
+        self.inner.inject_connection_closed(
+            peer_id,
+            connection_id,
+            endpoint,
+            handler.inner,
+            remaining_established,
+        );
+    }
+
+    fn inject_address_change(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        old: &ConnectedPoint,
+        new: &ConnectedPoint,
+    ) {
+        self.inner
+            .inject_address_change(peer_id, connection_id, old, new);
+    }
+
+    fn inject_event(
+        &mut self,
+        peer_id: PeerId,
+        connection: ConnectionId,
+        event: <<Self::ConnectionHandler as IntoConnectionHandler>::Handler as ConnectionHandler>::OutEvent,
+    ) {
+        self.inner.inject_event(peer_id, connection, event);
+    }
+
+    fn inject_dial_failure(
+        &mut self,
+        peer_id: Option<PeerId>,
+        handler: Self::ConnectionHandler,
+        error: &DialError,
+    ) {
+        self.inner
+            .inject_dial_failure(peer_id, handler.inner, error);
+    }
+
+    fn inject_listen_failure(
+        &mut self,
+        local_addr: &Multiaddr,
+        send_back_addr: &Multiaddr,
+        handler: Self::ConnectionHandler,
+    ) {
+        self.inner
+            .inject_listen_failure(local_addr, send_back_addr, handler.inner);
+    }
+
+    fn inject_new_listener(&mut self, id: ListenerId) {
+        self.inner.inject_new_listener(id);
+    }
+
+    fn inject_new_listen_addr(&mut self, id: ListenerId, addr: &Multiaddr) {
+        self.inner.inject_new_listen_addr(id, addr);
+    }
+
+    fn inject_expired_listen_addr(&mut self, id: ListenerId, addr: &Multiaddr) {
+        self.inner.inject_expired_listen_addr(id, addr);
+    }
+
+    fn inject_listener_error(&mut self, id: ListenerId, err: &(dyn std::error::Error + 'static)) {
+        self.inner.inject_listener_error(id, err);
+    }
+
+    fn inject_listener_closed(&mut self, id: ListenerId, reason: Result<(), &std::io::Error>) {
+        self.inner.inject_listener_closed(id, reason);
+    }
+
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        self.inner.inject_new_external_addr(addr);
+    }
+
+    fn inject_expired_external_addr(&mut self, addr: &Multiaddr) {
+        self.inner.inject_expired_external_addr(addr);
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        params: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
+        if let Some((peer_id, connection)) = self.pending_modes.pop_front() {
+            return Poll::Ready(NetworkBehaviourAction::NotifyHandler {
+                peer_id,
+                handler: NotifyHandler::One(connection),
+                event: ModalInEvent::SetServer(self.server),
+            });
+        }
+
+        let server = self.server;
+        self.inner.poll(cx, params).map(|action| {
+            action.map_handler_and_in(
+                |inner| ModalHandlerProto { inner, server },
+                ModalInEvent::Kad,
+            )
+        })
+    }
+}
+
+pub(crate) struct ModalHandlerProto {
+    inner: KadProto,
+    server: bool,
+}
+
+impl IntoConnectionHandler for ModalHandlerProto {
+    type Handler = ModalHandler;
+
+    fn into_handler(
+        self,
+        remote_peer_id: &PeerId,
+        connected_point: &ConnectedPoint,
+    ) -> Self::Handler {
+        ModalHandler {
+            inner: self.inner.into_handler(remote_peer_id, connected_point),
+            server: self.server,
+        }
+    }
+
+    fn inbound_protocol(&self) -> <Self::Handler as ConnectionHandler>::InboundProtocol {
+        if self.server {
+            EitherUpgrade::A(self.inner.inbound_protocol())
+        } else {
+            EitherUpgrade::B(DeniedUpgrade)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum ModalInEvent {
+    Kad(KadInEvent),
+    /// Switches the handler between client and server mode.
+    SetServer(bool),
+}
+
+/// Connection handler of [`Kademlia`] that denies inbound streams in client mode.
+pub(crate) struct ModalHandler {
+    inner: KadHandler,
+    server: bool,
+}
+
+type KadInbound = <KadHandler as ConnectionHandler>::InboundProtocol;
+
+impl ConnectionHandler for ModalHandler {
+    type InEvent = ModalInEvent;
+    type OutEvent = <KadHandler as ConnectionHandler>::OutEvent;
+    type Error = <KadHandler as ConnectionHandler>::Error;
+    type InboundProtocol = EitherUpgrade<KadInbound, DeniedUpgrade>;
+    type OutboundProtocol = <KadHandler as ConnectionHandler>::OutboundProtocol;
+    type InboundOpenInfo = <KadHandler as ConnectionHandler>::InboundOpenInfo;
+    type OutboundOpenInfo = <KadHandler as ConnectionHandler>::OutboundOpenInfo;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        let protocol = self.inner.listen_protocol();
+        if self.server {
+            protocol.map_upgrade(EitherUpgrade::A)
+        } else {
+            protocol.map_upgrade(|_| EitherUpgrade::B(DeniedUpgrade))
+        }
+    }
+
+    fn inject_fully_negotiated_inbound(
+        &mut self,
+        protocol: <Self::InboundProtocol as InboundUpgrade<NegotiatedSubstream>>::Output,
+        info: Self::InboundOpenInfo,
+    ) {
+        match protocol {
+            EitherOutput::First(protocol) => {
+                self.inner.inject_fully_negotiated_inbound(protocol, info)
+            }
+            EitherOutput::Second(never) => match never {},
+        }
+    }
+
+    fn inject_fully_negotiated_outbound(
+        &mut self,
+        protocol: <Self::OutboundProtocol as OutboundUpgrade<NegotiatedSubstream>>::Output,
+        info: Self::OutboundOpenInfo,
+    ) {
+        self.inner.inject_fully_negotiated_outbound(protocol, info);
+    }
+
+    fn inject_event(&mut self, event: Self::InEvent) {
+        match event {
+            ModalInEvent::Kad(event) => self.inner.inject_event(event),
+            ModalInEvent::SetServer(server) => self.server = server,
+        }
+    }
+
+    fn inject_address_change(&mut self, new_address: &Multiaddr) {
+        self.inner.inject_address_change(new_address);
+    }
+
+    fn inject_dial_upgrade_error(
+        &mut self,
+        info: Self::OutboundOpenInfo,
+        error: ConnectionHandlerUpgrErr<
+            <Self::OutboundProtocol as OutboundUpgrade<NegotiatedSubstream>>::Error,
+        >,
+    ) {
+        self.inner.inject_dial_upgrade_error(info, error);
+    }
+
+    fn inject_listen_upgrade_error(
+        &mut self,
+        info: Self::InboundOpenInfo,
+        error: ConnectionHandlerUpgrErr<
+            <Self::InboundProtocol as InboundUpgrade<NegotiatedSubstream>>::Error,
+        >,
+    ) {
+        let error = error.map_upgrade_err(|e| {
+            e.map_err(|e| match e {
+                EitherError::A(e) => e,
+                EitherError::B(never) => match never {},
+            })
+        });
+        self.inner.inject_listen_upgrade_error(info, error);
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.inner.connection_keep_alive()
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<
+            Self::OutboundProtocol,
+            Self::OutboundOpenInfo,
+            Self::OutEvent,
+            Self::Error,
+        >,
+    > {
+        self.inner.poll(cx)
+    }
+}
+
+type IdentifyProto = <identify::Behaviour as NetworkBehaviour>::ConnectionHandler;
+
+/// [`identify::Behaviour`] that announces the Kademlia protocols only in server mode.
+///
+/// Dereferences to the wrapped [`identify::Behaviour`].
+pub(crate) struct ModalIdentify {
+    inner: identify::Behaviour,
+    kad_protocols: Vec<Vec<u8>>,
+    kad_server: bool,
+}
+
+impl ModalIdentify {
+    pub fn new(inner: identify::Behaviour, kad: Option<&ModalKademlia>) -> Self {
+        ModalIdentify {
+            inner,
+            kad_protocols: kad.map(ModalKademlia::protocol_names).unwrap_or_default(),
+            kad_server: kad.map_or(false, ModalKademlia::is_server),
+        }
+    }
+
+    /// Announces the Kademlia protocols from now on if `server` is set.
+    ///
+    /// Connected peers only learn about it through [`identify::Behaviour::push`].
+    pub fn set_kad_server(&mut self, server: bool) {
+        self.kad_server = server;
+    }
+}
+
+impl Deref for ModalIdentify {
+    type Target = identify::Behaviour;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for ModalIdentify {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl NetworkBehaviour for ModalIdentify {
+    type ConnectionHandler = IdentifyProto;
+    type OutEvent = identify::Event;
+
+    fn new_handler(&mut self) -> Self::ConnectionHandler {
+        self.inner.new_handler()
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.inner.addresses_of_peer(peer_id)
+    }
+
+    fn inject_connection_established(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        endpoint: &ConnectedPoint,
+        failed_addresses: Option<&Vec<Multiaddr>>,
+        other_established: usize,
+    ) {
+        self.inner.inject_connection_established(
+            peer_id,
+            connection_id,
+            endpoint,
+            failed_addresses,
+            other_established,
+        );
+    }
+
+    fn inject_connection_closed(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        endpoint: &ConnectedPoint,
+        handler: <Self::ConnectionHandler as IntoConnectionHandler>::Handler,
+        remaining_established: usize,
+    ) {
+        self.inner.inject_connection_closed(
+            peer_id,
+            connection_id,
+            endpoint,
+            handler,
+            remaining_established,
+        );
+    }
+
+    fn inject_address_change(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        old: &ConnectedPoint,
+        new: &ConnectedPoint,
+    ) {
+        self.inner
+            .inject_address_change(peer_id, connection_id, old, new);
+    }
+
+    fn inject_event(
+        &mut self,
+        peer_id: PeerId,
+        connection: ConnectionId,
+        event: <<Self::ConnectionHandler as IntoConnectionHandler>::Handler as ConnectionHandler>::OutEvent,
+    ) {
+        self.inner.inject_event(peer_id, connection, event);
+    }
+
+    fn inject_dial_failure(
+        &mut self,
+        peer_id: Option<PeerId>,
+        handler: Self::ConnectionHandler,
+        error: &DialError,
+    ) {
+        self.inner.inject_dial_failure(peer_id, handler, error);
+    }
+
+    fn inject_listen_failure(
+        &mut self,
+        local_addr: &Multiaddr,
+        send_back_addr: &Multiaddr,
+        handler: Self::ConnectionHandler,
+    ) {
+        self.inner
+            .inject_listen_failure(local_addr, send_back_addr, handler);
+    }
+
+    fn inject_new_listener(&mut self, id: ListenerId) {
+        self.inner.inject_new_listener(id);
+    }
+
+    fn inject_new_listen_addr(&mut self, id: ListenerId, addr: &Multiaddr) {
+        self.inner.inject_new_listen_addr(id, addr);
+    }
+
+    fn inject_expired_listen_addr(&mut self, id: ListenerId, addr: &Multiaddr) {
+        self.inner.inject_expired_listen_addr(id, addr);
+    }
+
+    fn inject_listener_error(&mut self, id: ListenerId, err: &(dyn std::error::Error + 'static)) {
+        self.inner.inject_listener_error(id, err);
+    }
+
+    fn inject_listener_closed(&mut self, id: ListenerId, reason: Result<(), &std::io::Error>) {
+        self.inner.inject_listener_closed(id, reason);
+    }
+
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        self.inner.inject_new_external_addr(addr);
+    }
+
+    fn inject_expired_external_addr(&mut self, addr: &Multiaddr) {
+        self.inner.inject_expired_external_addr(addr);
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        params: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
+        let mut params = ModalParams {
+            inner: &*params,
+            kad_protocols: &self.kad_protocols,
+            kad_server: self.kad_server,
+        };
+        self.inner.poll(cx, &mut params)
+    }
+}
+
+/// [`PollParameters`] whose supported protocols follow the Kademlia mode.
+struct ModalParams<'a, P> {
+    inner: &'a P,
+    kad_protocols: &'a [Vec<u8>],
+    kad_server: bool,
+}
+
+#[allow(deprecated)]
+impl<'a, P: PollParameters> PollParameters for ModalParams<'a, P> {
+    type SupportedProtocolsIter = std::vec::IntoIter<Vec<u8>>;
+    type ListenedAddressesIter = P::ListenedAddressesIter;
+    type ExternalAddressesIter = P::ExternalAddressesIter;
+
+    fn supported_protocols(&self) -> Self::SupportedProtocolsIter {
+        // the swarm collects the protocols once, from a handler in the mode the node started in
+        let mut protocols: Vec<_> = self
+            .inner
+            .supported_protocols()
+            .filter(|protocol| !self.kad_protocols.contains(protocol))
+            .collect();
+        if self.kad_server {
+            protocols.extend(self.kad_protocols.iter().cloned());
+        }
+        protocols.into_iter()
+    }
+
+    fn listened_addresses(&self) -> Self::ListenedAddressesIter {
+        self.inner.listened_addresses()
+    }
+
+    fn external_addresses(&self) -> Self::ExternalAddressesIter {
+        self.inner.external_addresses()
+    }
+
+    fn local_peer_id(&self) -> &PeerId {
+        self.inner.local_peer_id()
+    }
+}
@@ -0,0 +1,505 @@
+use std::borrow::Cow;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use ahash::AHashSet;
+use anyhow::{Context, Result};
+use libp2p::kad::record::Key;
+use libp2p::kad::store::{self, MemoryStore, MemoryStoreConfig, RecordStore};
+use libp2p::kad::{ProviderRecord, Record};
+use libp2p::{Multiaddr, PeerId};
+use rocksdb::{IteratorMode, Options, DB};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+const CF_RECORDS_V0: &str = "records-v0";
+const CF_PROVIDERS_V0: &str = "providers-v0";
+/// How often expired records are removed.
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Kademlia record store.
+///
+/// All records are kept in a [`MemoryStore`], which enforces the limits. If a path is
+/// given every change is written through to a RocksDB database as well, and the records
+/// that have not expired yet are loaded back into memory on the next start.
+pub struct KadStore {
+    memory: MemoryStore,
+    db: Option<DB>,
+    /// Keys with at least one provider, the memory store can not list them.
+    provider_keys: AHashSet<Key>,
+    next_expiry: Instant,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredRecord {
+    value: Vec<u8>,
+    publisher: Option<PeerId>,
+    /// Unix timestamp in seconds.
+    expires: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredProvider {
+    key: Vec<u8>,
+    provider: PeerId,
+    /// Unix timestamp in seconds.
+    expires: Option<u64>,
+    addresses: Vec<Multiaddr>,
+}
+
+impl KadStore {
+    /// Creates a store that only keeps records in memory.
+    pub fn memory(local_id: PeerId, config: MemoryStoreConfig) -> Self {
+        KadStore {
+            memory: MemoryStore::with_config(local_id, config),
+            db: None,
+            provider_keys: Default::default(),
+            next_expiry: Instant::now() + EXPIRY_INTERVAL,
+        }
+    }
+
+    /// Opens, or creates, the database at `path` and loads all unexpired records from it.
+    pub fn open(local_id: PeerId, config: MemoryStoreConfig, path: &Path) -> Result<Self> {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        let db = DB::open_cf(&options, path, [CF_RECORDS_V0, CF_PROVIDERS_V0])
+            .with_context(|| format!("failed to open kademlia store at {}", path.display()))?;
+
+        let mut memory = MemoryStore::with_config(local_id, config);
+        let mut provider_keys = AHashSet::new();
+        let mut stale = Vec::new();
+
+        let cf = db
+            .cf_handle(CF_RECORDS_V0)
+            .context("missing column family: records")?;
+        for item in db.iterator_cf(&cf, IteratorMode::Start) {
+            let (key, value) = item?;
+            let loaded = decode_record(&key, &value)
+                .and_then(|record| record.context("expired"))
+                .and_then(|record| memory.put(record).map_err(Into::into));
+            if let Err(err) = loaded {
+                debug!("dropping stored kademlia record: {:?}", err);
+                stale.push(key);
+            }
+        }
+        for key in stale.drain(..) {
+            db.delete_cf(&cf, key)?;
+        }
+
+        let cf = db
+            .cf_handle(CF_PROVIDERS_V0)
+            .context("missing column family: providers")?;
+        for item in db.iterator_cf(&cf, IteratorMode::Start) {
+            let (key, value) = item?;
+            let loaded = decode_provider(&value)
+                .and_then(|record| record.context("expired"))
+                .and_then(|record| {
+                    provider_keys.insert(record.key.clone());
+                    memory.add_provider(record).map_err(Into::into)
+                });
+            if let Err(err) = loaded {
+                debug!("dropping stored provider record: {:?}", err);
+                stale.push(key);
+            }
+        }
+        for key in stale.drain(..) {
+            db.delete_cf(&cf, key)?;
+        }
+
+        // providers beyond the limit of a key are not loaded, or evicted by the ones that
+        // are loaded after them
+        provider_keys.retain(|record_key| !memory.providers(record_key).is_empty());
+        for record_key in &provider_keys {
+            let kept = memory.providers(record_key);
+            let prefix = provider_key_prefix(record_key);
+            for item in db.prefix_iterator_cf(&cf, &prefix) {
+                let (key, value) = item?;
+                if !key.starts_with(&prefix) {
+                    break;
+                }
+                let stored: StoredProvider = bincode::deserialize(&value)?;
+                if !kept.iter().any(|p| p.provider == stored.provider) {
+                    stale.push(key);
+                }
+            }
+        }
+        for key in stale {
+            db.delete_cf(&cf, key)?;
+        }
+
+        Ok(KadStore {
+            memory,
+            db: Some(db),
+            provider_keys,
+            next_expiry: Instant::now() + EXPIRY_INTERVAL,
+        })
+    }
+
+    /// Removes expired records and provider records, at most once every [`EXPIRY_INTERVAL`].
+    pub fn expire(&mut self) {
+        let now = Instant::now();
+        if now < self.next_expiry {
+            return;
+        }
+        self.next_expiry = now + EXPIRY_INTERVAL;
+        self.remove_expired(now);
+    }
+
+    fn remove_expired(&mut self, now: Instant) {
+        let records: Vec<Key> = self
+            .memory
+            .records()
+            .filter(|r| r.is_expired(now))
+            .map(|r| r.key.clone())
+            .collect();
+        for key in records {
+            self.remove(&key);
+        }
+
+        let providers: Vec<(Key, PeerId)> = self
+            .provider_keys
+            .iter()
+            .flat_map(|key| self.memory.providers(key))
+            .filter(|p| p.is_expired(now))
+            .map(|p| (p.key, p.provider))
+            .collect();
+        for (key, provider) in providers {
+            self.remove_provider(&key, &provider);
+        }
+    }
+
+    fn write(&self, cf: &str, key: &[u8], value: Option<Vec<u8>>) {
+        let db = match self.db {
+            Some(ref db) => db,
+            None => return,
+        };
+        let res = match db.cf_handle(cf) {
+            Some(cf) => match value {
+                Some(value) => db.put_cf(&cf, key, value),
+                None => db.delete_cf(&cf, key),
+            },
+            None => {
+                warn!("missing column family: {}", cf);
+                return;
+            }
+        };
+        if let Err(err) = res {
+            warn!("failed to persist kademlia store update: {:?}", err);
+        }
+    }
+}
+
+impl RecordStore for KadStore {
+    type RecordsIter<'a> = <MemoryStore as RecordStore>::RecordsIter<'a>;
+    type ProvidedIter<'a> = <MemoryStore as RecordStore>::ProvidedIter<'a>;
+
+    fn get(&self, k: &Key) -> Option<Cow<'_, Record>> {
+        self.memory.get(k)
+    }
+
+    fn put(&mut self, r: Record) -> store::Result<()> {
+        let key = r.key.to_vec();
+        let value = self.db.is_some().then(|| encode_record(&r)).flatten();
+        self.memory.put(r)?;
+        if let Some(value) = value {
+            self.write(CF_RECORDS_V0, &key, Some(value));
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, k: &Key) {
+        self.memory.remove(k);
+        self.write(CF_RECORDS_V0, k.as_ref(), None);
+    }
+
+    fn records(&self) -> Self::RecordsIter<'_> {
+        self.memory.records()
+    }
+
+    fn add_provider(&mut self, record: ProviderRecord) -> store::Result<()> {
+        let key = record.key.clone();
+        if self.db.is_none() {
+            self.memory.add_provider(record)?;
+            self.provider_keys.insert(key);
+            return Ok(());
+        }
+
+        let provider = record.provider;
+        let value = encode_provider(&record);
+        let before = self.memory.providers(&key);
+        self.memory.add_provider(record)?;
+        self.provider_keys.insert(key.clone());
+
+        // Once `max_providers_per_key` is reached the memory store evicts the provider
+        // furthest from the key, which might be the new one.
+        let after = self.memory.providers(&key);
+        for evicted in before
+            .iter()
+            .filter(|p| !after.iter().any(|a| a.provider == p.provider))
+        {
+            self.write(
+                CF_PROVIDERS_V0,
+                &provider_key(&key, &evicted.provider),
+                None,
+            );
+        }
+        if after.iter().any(|a| a.provider == provider) {
+            if let Some(value) = value {
+                self.write(CF_PROVIDERS_V0, &provider_key(&key, &provider), Some(value));
+            }
+        }
+        Ok(())
+    }
+
+    fn providers(&self, key: &Key) -> Vec<ProviderRecord> {
+        self.memory.providers(key)
+    }
+
+    fn provided(&self) -> Self::ProvidedIter<'_> {
+        self.memory.provided()
+    }
+
+    fn remove_provider(&mut self, k: &Key, p: &PeerId) {
+        self.memory.remove_provider(k, p);
+        if self.memory.providers(k).is_empty() {
+            self.provider_keys.remove(k);
+        }
+        self.write(CF_PROVIDERS_V0, &provider_key(k, p), None);
+    }
+}
+
+/// The database key of a provider record, the length prefixed record key followed by the
+/// provider.
+fn provider_key(key: &Key, provider: &PeerId) -> Vec<u8> {
+    let mut out = provider_key_prefix(key);
+    out.extend_from_slice(&provider.to_bytes());
+    out
+}
+
+/// The common prefix of the database keys of all providers of `key`.
+fn provider_key_prefix(key: &Key) -> Vec<u8> {
+    let key = key.as_ref();
+    let mut out = Vec::with_capacity(4 + key.len() + 38);
+    out.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    out.extend_from_slice(key);
+    out
+}
+
+fn encode_record(record: &Record) -> Option<Vec<u8>> {
+    let stored = StoredRecord {
+        value: record.value.clone(),
+        publisher: record.publisher,
+        expires: record.expires.map(to_unix),
+    };
+    bincode::serialize(&stored)
+        .map_err(|err| warn!("failed to encode kademlia record: {:?}", err))
+        .ok()
+}
+
+/// Decodes a stored record, returns `None` if the record has expired.
+fn decode_record(key: &[u8], value: &[u8]) -> Result<Option<Record>> {
+    let stored: StoredRecord = bincode::deserialize(value)?;
+    let expires = match stored.expires.map(from_unix) {
+        Some(None) => return Ok(None),
+        Some(expires) => expires,
+        None => None,
+    };
+    Ok(Some(Record {
+        key: Key::from(key.to_vec()),
+        value: stored.value,
+        publisher: stored.publisher,
+        expires,
+    }))
+}
+
+fn encode_provider(record: &ProviderRecord) -> Option<Vec<u8>> {
+    let stored = StoredProvider {
+        key: record.key.to_vec(),
+        provider: record.provider,
+        expires: record.expires.map(to_unix),
+        addresses: record.addresses.clone(),
+    };
+    bincode::serialize(&stored)
+        .map_err(|err| warn!("failed to encode provider record: {:?}", err))
+        .ok()
+}
+
+/// Decodes a stored provider record, returns `None` if the record has expired.
+fn decode_provider(value: &[u8]) -> Result<Option<ProviderRecord>> {
+    let stored: StoredProvider = bincode::deserialize(value)?;
+    let expires = match stored.expires.map(from_unix) {
+        Some(None) => return Ok(None),
+        Some(expires) => expires,
+        None => None,
+    };
+    Ok(Some(ProviderRecord {
+        key: Key::from(stored.key),
+        provider: stored.provider,
+        expires,
+        addresses: stored.addresses,
+    }))
+}
+
+/// Converts an expiry instant into a unix timestamp, which stays meaningful across restarts.
+fn to_unix(instant: Instant) -> u64 {
+    let now = Instant::now();
+    let at = if instant >= now {
+        SystemTime::now() + (instant - now)
+    } else {
+        SystemTime::now() - (now - instant)
+    };
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Converts a unix timestamp back into an instant, returns `None` if it lies in the past.
+fn from_unix(secs: u64) -> Option<Instant> {
+    let at = UNIX_EPOCH + Duration::from_secs(secs);
+    at.duration_since(SystemTime::now())
+        .ok()
+        .map(|remaining| Instant::now() + remaining)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(k: &str) -> Key {
+        Key::from(k.as_bytes().to_vec())
+    }
+
+    fn config() -> MemoryStoreConfig {
+        MemoryStoreConfig {
+            max_records: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_persist_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let local_id = PeerId::random();
+        let provider = PeerId::random();
+        let expires = Instant::now() + Duration::from_secs(60 * 60);
+
+        {
+            let mut store = KadStore::open(local_id, config(), dir.path()).unwrap();
+            let mut record = Record::new(key("a"), b"hello".to_vec());
+            record.expires = Some(expires);
+            store.put(record).unwrap();
+            store.put(Record::new(key("b"), b"world".to_vec())).unwrap();
+            store.remove(&key("b"));
+
+            // the limits of the memory store apply
+            store.put(Record::new(key("c"), vec![])).unwrap();
+            assert!(store.put(Record::new(key("d"), vec![])).is_err());
+
+            let mut record = ProviderRecord::new(key("a"), provider, vec![]);
+            record.expires = Some(expires);
+            store.add_provider(record).unwrap();
+            store
+                .add_provider(ProviderRecord::new(key("b"), provider, vec![]))
+                .unwrap();
+            store.remove_provider(&key("b"), &provider);
+        }
+
+        let store = KadStore::open(local_id, config(), dir.path()).unwrap();
+        let record = store.get(&key("a")).unwrap();
+        assert_eq!(record.value, b"hello");
+        let remaining = record.expires.unwrap() - Instant::now();
+        assert!(remaining > Duration::from_secs(60 * 59));
+        assert!(store.get(&key("b")).is_none());
+        assert!(store.get(&key("c")).is_some());
+        assert!(store.get(&key("d")).is_none());
+
+        let providers = store.providers(&key("a"));
+        assert_eq!(providers.len(), 1);
+        assert_eq!(providers[0].provider, provider);
+        assert!(store.providers(&key("b")).is_empty());
+    }
+
+    fn stored(store: &KadStore, cf: &str) -> usize {
+        let db = store.db.as_ref().unwrap();
+        let cf = db.cf_handle(cf).unwrap();
+        db.iterator_cf(&cf, IteratorMode::Start).count()
+    }
+
+    #[test]
+    fn test_evicted_providers_are_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = MemoryStoreConfig {
+            max_providers_per_key: 2,
+            ..Default::default()
+        };
+        let mut store = KadStore::open(PeerId::random(), config.clone(), dir.path()).unwrap();
+
+        for _ in 0..10 {
+            store
+                .add_provider(ProviderRecord::new(key("a"), PeerId::random(), vec![]))
+                .unwrap();
+        }
+        let kept: Vec<PeerId> = store
+            .providers(&key("a"))
+            .into_iter()
+            .map(|p| p.provider)
+            .collect();
+        assert_eq!(kept.len(), 2);
+        // only the providers the memory store kept are on disk
+        assert_eq!(stored(&store, CF_PROVIDERS_V0), 2);
+        drop(store);
+
+        let store = KadStore::open(PeerId::random(), config, dir.path()).unwrap();
+        let mut loaded: Vec<PeerId> = store
+            .providers(&key("a"))
+            .into_iter()
+            .map(|p| p.provider)
+            .collect();
+        loaded.sort();
+        let mut kept = kept;
+        kept.sort();
+        assert_eq!(loaded, kept);
+    }
+
+    #[test]
+    fn test_expired_records_are_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = KadStore::open(PeerId::random(), config(), dir.path()).unwrap();
+        let expires = Instant::now() + Duration::from_millis(10);
+
+        let mut record = Record::new(key("a"), b"hello".to_vec());
+        record.expires = Some(expires);
+        store.put(record).unwrap();
+        store.put(Record::new(key("b"), b"world".to_vec())).unwrap();
+        let mut record = ProviderRecord::new(key("a"), PeerId::random(), vec![]);
+        record.expires = Some(expires);
+        store.add_provider(record).unwrap();
+        store
+            .add_provider(ProviderRecord::new(key("b"), PeerId::random(), vec![]))
+            .unwrap();
+
+        store.remove_expired(Instant::now() + Duration::from_secs(1));
+        assert!(store.get(&key("a")).is_none());
+        assert!(store.get(&key("b")).is_some());
+        assert!(store.providers(&key("a")).is_empty());
+        assert_eq!(store.providers(&key("b")).len(), 1);
+        assert_eq!(stored(&store, CF_RECORDS_V0), 1);
+        assert_eq!(stored(&store, CF_PROVIDERS_V0), 1);
+    }
+
+    #[test]
+    fn test_drop_expired_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let local_id = PeerId::random();
+
+        {
+            let mut store = KadStore::open(local_id, config(), dir.path()).unwrap();
+            let mut record = Record::new(key("a"), b"hello".to_vec());
+            record.expires = Some(Instant::now() - Duration::from_secs(10));
+            store.put(record).unwrap();
+        }
+
+        let store = KadStore::open(local_id, config(), dir.path()).unwrap();
+        assert!(store.get(&key("a")).is_none());
+        assert_eq!(store.records().count(), 0);
+    }
+}
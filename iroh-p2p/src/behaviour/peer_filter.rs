@@ -0,0 +1,177 @@
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use ahash::AHashSet;
+use libp2p::{Multiaddr, PeerId};
+
+use crate::config::{AddrFilter, Libp2pConfig};
+
+/// A [`PeerFilter`] shared between the transport, which enforces it on every new
+/// connection, and the peer manager, which updates it at runtime.
+pub type SharedPeerFilter = Arc<RwLock<PeerFilter>>;
+
+/// The lists of a [`PeerFilter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterList {
+    Allow,
+    Deny,
+}
+
+impl fmt::Display for FilterList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterList::Allow => write!(f, "allow"),
+            FilterList::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+/// A single entry of a [`PeerFilter`] list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterRule {
+    Peer(PeerId),
+    Addr(AddrFilter),
+}
+
+impl fmt::Display for FilterRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterRule::Peer(peer_id) => write!(f, "{peer_id}"),
+            FilterRule::Addr(addr) => write!(f, "{addr}"),
+        }
+    }
+}
+
+/// Operator controlled allow and deny lists of peers and address ranges.
+///
+/// A connection is refused if its peer or its remote address is denied. A non-empty allow
+/// list additionally refuses all peers, respectively addresses, that are not on it.
+#[derive(Debug, Default, Clone)]
+pub struct PeerFilter {
+    allow_peers: AHashSet<PeerId>,
+    deny_peers: AHashSet<PeerId>,
+    allow_addrs: Vec<AddrFilter>,
+    deny_addrs: Vec<AddrFilter>,
+}
+
+impl PeerFilter {
+    pub fn new(config: &Libp2pConfig) -> Self {
+        let mut filter = PeerFilter::default();
+        for peer_id in &config.allow_peers {
+            filter.add(FilterList::Allow, FilterRule::Peer(*peer_id));
+        }
+        for peer_id in &config.deny_peers {
+            filter.add(FilterList::Deny, FilterRule::Peer(*peer_id));
+        }
+        for addr in &config.allow_addrs {
+            filter.add(FilterList::Allow, FilterRule::Addr(*addr));
+        }
+        for addr in &config.deny_addrs {
+            filter.add(FilterList::Deny, FilterRule::Addr(*addr));
+        }
+        filter
+    }
+
+    /// Returns `true` if a connection to `peer_id` on `addr` is allowed.
+    pub fn allows(&self, peer_id: &PeerId, addr: &Multiaddr) -> bool {
+        if self.deny_peers.contains(peer_id) || self.deny_addrs.iter().any(|f| f.matches(addr)) {
+            return false;
+        }
+        (self.allow_peers.is_empty() || self.allow_peers.contains(peer_id))
+            && (self.allow_addrs.is_empty() || self.allow_addrs.iter().any(|f| f.matches(addr)))
+    }
+
+    /// Adds the rule to the list, returns `false` if it was already present.
+    pub fn add(&mut self, list: FilterList, rule: FilterRule) -> bool {
+        match (list, rule) {
+            (FilterList::Allow, FilterRule::Peer(peer_id)) => self.allow_peers.insert(peer_id),
+            (FilterList::Deny, FilterRule::Peer(peer_id)) => self.deny_peers.insert(peer_id),
+            (FilterList::Allow, FilterRule::Addr(addr)) => insert(&mut self.allow_addrs, addr),
+            (FilterList::Deny, FilterRule::Addr(addr)) => insert(&mut self.deny_addrs, addr),
+        }
+    }
+
+    /// Removes the rule from the list, returns `false` if it was not present.
+    pub fn remove(&mut self, list: FilterList, rule: FilterRule) -> bool {
+        match (list, rule) {
+            (FilterList::Allow, FilterRule::Peer(peer_id)) => self.allow_peers.remove(&peer_id),
+            (FilterList::Deny, FilterRule::Peer(peer_id)) => self.deny_peers.remove(&peer_id),
+            (FilterList::Allow, FilterRule::Addr(addr)) => remove(&mut self.allow_addrs, addr),
+            (FilterList::Deny, FilterRule::Addr(addr)) => remove(&mut self.deny_addrs, addr),
+        }
+    }
+
+    /// Returns all rules of the list.
+    pub fn rules(&self, list: FilterList) -> Vec<FilterRule> {
+        let (peers, addrs) = match list {
+            FilterList::Allow => (&self.allow_peers, &self.allow_addrs),
+            FilterList::Deny => (&self.deny_peers, &self.deny_addrs),
+        };
+        peers
+            .iter()
+            .map(|peer_id| FilterRule::Peer(*peer_id))
+            .chain(addrs.iter().map(|addr| FilterRule::Addr(*addr)))
+            .collect()
+    }
+}
+
+fn insert(addrs: &mut Vec<AddrFilter>, addr: AddrFilter) -> bool {
+    if addrs.contains(&addr) {
+        return false;
+    }
+    addrs.push(addr);
+    true
+}
+
+fn remove(addrs: &mut Vec<AddrFilter>, addr: AddrFilter) -> bool {
+    let len = addrs.len();
+    addrs.retain(|a| *a != addr);
+    addrs.len() != len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_filter() {
+        let peer_a = PeerId::random();
+        let peer_b = PeerId::random();
+        let lan: Multiaddr = "/ip4/192.168.1.10/tcp/4001".parse().unwrap();
+        let wan: Multiaddr = "/ip4/8.8.8.8/tcp/4001".parse().unwrap();
+        let lan_range = FilterRule::Addr("/ip4/192.168.0.0/ipcidr/16".parse().unwrap());
+
+        let mut filter = PeerFilter::default();
+        assert!(filter.allows(&peer_a, &wan));
+
+        assert!(filter.add(FilterList::Deny, FilterRule::Peer(peer_a)));
+        assert!(!filter.add(FilterList::Deny, FilterRule::Peer(peer_a)));
+        assert!(!filter.allows(&peer_a, &lan));
+        assert!(filter.allows(&peer_b, &lan));
+
+        assert!(filter.add(FilterList::Deny, lan_range));
+        assert!(!filter.allows(&peer_b, &lan));
+        assert!(filter.allows(&peer_b, &wan));
+        assert_eq!(
+            filter.rules(FilterList::Deny),
+            vec![FilterRule::Peer(peer_a), lan_range]
+        );
+
+        assert!(filter.remove(FilterList::Deny, lan_range));
+        assert!(!filter.remove(FilterList::Deny, lan_range));
+        assert!(filter.remove(FilterList::Deny, FilterRule::Peer(peer_a)));
+        assert!(filter.rules(FilterList::Deny).is_empty());
+
+        // non-empty allow lists refuse everything else
+        filter.add(FilterList::Allow, lan_range);
+        assert!(filter.allows(&peer_a, &lan));
+        assert!(!filter.allows(&peer_a, &wan));
+        filter.add(FilterList::Allow, FilterRule::Peer(peer_b));
+        assert!(!filter.allows(&peer_a, &lan));
+        assert!(filter.allows(&peer_b, &lan));
+
+        // deny wins over allow
+        filter.add(FilterList::Deny, FilterRule::Peer(peer_b));
+        assert!(!filter.allows(&peer_b, &lan));
+    }
+}
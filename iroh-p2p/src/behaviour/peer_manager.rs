@@ -1,33 +1,81 @@
 use std::{
+    collections::VecDeque,
     num::NonZeroUsize,
-    task::{Context, Poll},
-    time::Duration,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant, SystemTime},
 };
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use iroh_metrics::{core::MRecorder, inc, p2p::P2PMetrics};
 use libp2p::{
-    core::{connection::ConnectionId, transport::ListenerId, ConnectedPoint},
+    core::{
+        connection::ConnectionId,
+        transport::ListenerId,
+        upgrade::{DeniedUpgrade, InboundUpgrade, OutboundUpgrade},
+        ConnectedPoint,
+    },
     identify::Info as IdentifyInfo,
     ping::Success as PingSuccess,
     swarm::{
-        dummy, ConnectionHandler, DialError, IntoConnectionHandler, NetworkBehaviour,
-        NetworkBehaviourAction, PollParameters,
+        dummy, CloseConnection, ConnectionHandler, ConnectionHandlerEvent,
+        ConnectionHandlerUpgrErr, DialError, IntoConnectionHandler, KeepAlive, NegotiatedSubstream,
+        NetworkBehaviour, NetworkBehaviourAction, PollParameters, SubstreamProtocol,
     },
     Multiaddr, PeerId,
 };
 use lru::LruCache;
+use tracing::debug;
+
+use super::peer_filter::{FilterList, FilterRule, SharedPeerFilter};
+use super::peerstore::StoredPeer;
+use crate::config::Libp2pConfig;
+use crate::peering::peering_peers;
+
+/// Tag protecting the peering peers.
+const PEERING_TAG: &str = "peering";
 
 pub struct PeerManager {
     info: AHashMap<PeerId, Info>,
     bad_peers: LruCache<PeerId, ()>,
+    /// Banned peers and when their ban expires, `None` for permanent bans.
+    banned_peers: AHashMap<PeerId, Option<Instant>>,
+    peer_filter: SharedPeerFilter,
+    connections: AHashMap<ConnectionId, Connection>,
+    /// Connections refused by the peer filter or trimmed, waiting to be closed.
+    pending_closes: VecDeque<(PeerId, CloseConnection)>,
+    waker: Option<Waker>,
+    /// Values of the peers, by tag.
+    tags: AHashMap<PeerId, AHashMap<String, i32>>,
+    /// Peers that are never trimmed, with the tags protecting them.
+    protected: AHashMap<PeerId, AHashSet<String>>,
+    low_water: usize,
+    high_water: usize,
+    grace_period: Duration,
+    last_trim: Option<Instant>,
     supported_protocols: Vec<String>,
+    /// Peers whose connections are kept alive and not counted against the limits.
+    peering: Arc<AHashSet<PeerId>>,
+    /// Addresses of the peers found by the delegated routers.
+    routed_addrs: LruCache<PeerId, Vec<Multiaddr>>,
+    max_conns_in: usize,
+    max_conns_out: usize,
+}
+
+#[derive(Debug)]
+struct Connection {
+    peer_id: PeerId,
+    remote_addr: Multiaddr,
+    established: Instant,
+    inbound: bool,
 }
 
 #[derive(Default, Debug, Clone)]
 pub struct Info {
     pub last_rtt: Option<Duration>,
     pub last_info: Option<IdentifyInfo>,
+    /// When the last connection to the peer closed.
+    pub last_seen: Option<SystemTime>,
 }
 
 impl Info {
@@ -38,25 +86,247 @@ impl Info {
 }
 
 const DEFAULT_BAD_PEER_CAP: Option<NonZeroUsize> = NonZeroUsize::new(10 * 4096);
+const ROUTED_ADDRS_CAP: Option<NonZeroUsize> = NonZeroUsize::new(4096);
 
-impl Default for PeerManager {
-    fn default() -> Self {
-        PeerManager {
+/// Minimum time between two trims, gives the closed connections time to go away.
+const TRIM_SILENCE_PERIOD: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum PeerManagerEvent {}
+
+impl PeerManager {
+    pub fn new(config: &Libp2pConfig, peer_filter: SharedPeerFilter) -> Self {
+        let mut peer_manager = PeerManager {
             info: Default::default(),
             bad_peers: LruCache::new(DEFAULT_BAD_PEER_CAP.unwrap()),
+            banned_peers: Default::default(),
+            peer_filter,
+            connections: Default::default(),
+            pending_closes: Default::default(),
+            waker: None,
+            tags: Default::default(),
+            protected: Default::default(),
+            low_water: config.conn_low_water.min(config.conn_high_water) as usize,
+            high_water: config.conn_high_water as usize,
+            grace_period: Duration::from_secs(config.conn_grace_period_secs),
+            last_trim: None,
             supported_protocols: Default::default(),
+            peering: Arc::new(peering_peers(config).collect()),
+            routed_addrs: LruCache::new(ROUTED_ADDRS_CAP.unwrap()),
+            max_conns_in: config.max_conns_in as usize,
+            max_conns_out: config.max_conns_out as usize,
+        };
+        for peer_id in &config.protected_peers {
+            peer_manager.protect_peer(*peer_id, "config");
         }
+        for peer_id in peering_peers(config) {
+            peer_manager.protect_peer(peer_id, PEERING_TAG);
+        }
+        peer_manager
     }
-}
-
-#[derive(Debug)]
-pub enum PeerManagerEvent {}
 
-impl PeerManager {
     pub fn is_bad_peer(&self, peer_id: &PeerId) -> bool {
         self.bad_peers.contains(peer_id)
     }
 
+    /// Bans the peer for the given duration, durations too long to track ban it permanently.
+    ///
+    /// Only tracks the ban, the swarm is responsible for enforcing it.
+    pub fn ban_peer(&mut self, peer_id: PeerId, duration: Duration) {
+        self.banned_peers
+            .insert(peer_id, Instant::now().checked_add(duration));
+    }
+
+    pub fn is_banned(&self, peer_id: &PeerId) -> bool {
+        self.banned_peers.contains_key(peer_id)
+    }
+
+    /// Removes and returns all peers whose ban has expired.
+    pub fn expired_bans(&mut self) -> Vec<PeerId> {
+        let now = Instant::now();
+        let expired: Vec<_> = self
+            .banned_peers
+            .iter()
+            .filter(|(_, until)| matches!(until, Some(until) if *until <= now))
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        for peer_id in &expired {
+            self.banned_peers.remove(peer_id);
+        }
+        expired
+    }
+
+    /// Adds a rule to the peer filter and closes the connections it refuses.
+    pub fn add_filter_rule(&mut self, list: FilterList, rule: FilterRule) {
+        self.peer_filter.write().unwrap().add(list, rule);
+        self.close_filtered_connections();
+    }
+
+    /// Removes a rule from the peer filter, returns `false` if it was not present.
+    pub fn remove_filter_rule(&mut self, list: FilterList, rule: FilterRule) -> bool {
+        let removed = self.peer_filter.write().unwrap().remove(list, rule);
+        self.close_filtered_connections();
+        removed
+    }
+
+    pub fn filter_rules(&self, list: FilterList) -> Vec<FilterRule> {
+        self.peer_filter.read().unwrap().rules(list)
+    }
+
+    fn close_filtered_connections(&mut self) {
+        let filter = self.peer_filter.read().unwrap();
+        for (connection_id, conn) in &self.connections {
+            if !filter.allows(&conn.peer_id, &conn.remote_addr) {
+                self.pending_closes
+                    .push_back((conn.peer_id, CloseConnection::One(*connection_id)));
+            }
+        }
+        drop(filter);
+        self.wake();
+    }
+
+    /// Sets the value of the peer under `tag`, replacing an earlier value of the same tag.
+    ///
+    /// Peers with a higher total value are trimmed last.
+    pub fn tag_peer(&mut self, peer_id: PeerId, tag: &str, value: i32) {
+        self.tags
+            .entry(peer_id)
+            .or_default()
+            .insert(tag.to_string(), value);
+    }
+
+    pub fn untag_peer(&mut self, peer_id: &PeerId, tag: &str) {
+        if let Some(tags) = self.tags.get_mut(peer_id) {
+            tags.remove(tag);
+            if tags.is_empty() {
+                self.tags.remove(peer_id);
+            }
+        }
+    }
+
+    /// Removes `tag` from all peers.
+    pub fn untag_all(&mut self, tag: &str) {
+        self.tags.retain(|_, tags| {
+            tags.remove(tag);
+            !tags.is_empty()
+        });
+    }
+
+    /// Returns the total value of the peer.
+    pub fn peer_value(&self, peer_id: &PeerId) -> i32 {
+        self.tags
+            .get(peer_id)
+            .map(|tags| tags.values().sum())
+            .unwrap_or_default()
+    }
+
+    /// Keeps the connections to the peer from being trimmed, until all tags protecting it
+    /// are removed.
+    pub fn protect_peer(&mut self, peer_id: PeerId, tag: &str) {
+        self.protected
+            .entry(peer_id)
+            .or_default()
+            .insert(tag.to_string());
+    }
+
+    /// Removes the protection of `tag`, returns `true` if the peer is still protected.
+    pub fn unprotect_peer(&mut self, peer_id: &PeerId, tag: &str) -> bool {
+        if let Some(tags) = self.protected.get_mut(peer_id) {
+            tags.remove(tag);
+            if tags.is_empty() {
+                self.protected.remove(peer_id);
+            }
+        }
+        self.is_protected(peer_id)
+    }
+
+    pub fn is_protected(&self, peer_id: &PeerId) -> bool {
+        self.protected.contains_key(peer_id)
+    }
+
+    /// Disconnects the least valuable peers once there are more connections than the high
+    /// watermark, until the low watermark is reached.
+    ///
+    /// Protected peers and peers that connected within the grace period are kept.
+    pub fn trim_connections(&mut self) {
+        if self.high_water == 0 || self.connections.len() <= self.high_water {
+            return;
+        }
+        let now = Instant::now();
+        if let Some(last_trim) = self.last_trim {
+            if now.duration_since(last_trim) < TRIM_SILENCE_PERIOD {
+                return;
+            }
+        }
+        self.last_trim = Some(now);
+
+        // number of connections and first connection time of each peer
+        let mut peers: AHashMap<PeerId, (usize, Instant)> = AHashMap::new();
+        for conn in self.connections.values() {
+            let (count, first) = peers.entry(conn.peer_id).or_insert((0, conn.established));
+            *count += 1;
+            *first = (*first).min(conn.established);
+        }
+        let mut candidates: Vec<_> = peers
+            .into_iter()
+            .filter(|(peer_id, (_, first))| {
+                !self.is_protected(peer_id) && now.duration_since(*first) >= self.grace_period
+            })
+            .map(|(peer_id, (count, _))| (self.peer_value(&peer_id), peer_id, count))
+            .collect();
+        candidates.sort_by_key(|(value, _, _)| *value);
+
+        let mut excess = self.connections.len() - self.low_water;
+        for (value, peer_id, count) in candidates {
+            if excess == 0 {
+                break;
+            }
+            debug!(
+                "trimming {} connections to {} (value {})",
+                count, peer_id, value
+            );
+            inc!(P2PMetrics::TrimmedPeers);
+            self.pending_closes
+                .push_back((peer_id, CloseConnection::All));
+            excess = excess.saturating_sub(count);
+        }
+        self.wake();
+    }
+
+    /// Closes the connection if the other connections in its direction already reach the
+    /// limit, the peering peers are not counted.
+    ///
+    /// The swarm admits one more connection per peering peer, so that they can always connect.
+    fn enforce_limits(&mut self, peer_id: PeerId, connection_id: ConnectionId, inbound: bool) {
+        if self.peering.is_empty() || self.peering.contains(&peer_id) {
+            return;
+        }
+        let max = if inbound {
+            self.max_conns_in
+        } else {
+            self.max_conns_out
+        };
+        let count = self
+            .connections
+            .values()
+            .filter(|c| c.inbound == inbound && !self.peering.contains(&c.peer_id))
+            .count();
+        if count > max {
+            debug!("closing the connection to {}, over the limit", peer_id);
+            self.pending_closes
+                .push_back((peer_id, CloseConnection::One(connection_id)));
+            self.wake();
+        }
+    }
+
+    fn wake(&mut self) {
+        if !self.pending_closes.is_empty() {
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
     pub fn inject_identify_info(&mut self, peer_id: PeerId, new_info: IdentifyInfo) {
         self.info.entry(peer_id).or_default().last_info = Some(new_info);
     }
@@ -67,39 +337,126 @@ impl PeerManager {
         }
     }
 
+    /// Returns the peers with identify info, as entries for the peerstore.
+    pub fn stored_peers(&self) -> Vec<StoredPeer> {
+        let now = SystemTime::now();
+        let connected: AHashSet<_> = self.connections.values().map(|c| c.peer_id).collect();
+        self.info
+            .iter()
+            .filter_map(|(peer_id, info)| {
+                let identify = info.last_info.as_ref()?;
+                let last_seen = if connected.contains(peer_id) {
+                    now
+                } else {
+                    info.last_seen?
+                };
+                Some(StoredPeer::new(
+                    *peer_id,
+                    identify,
+                    info.last_rtt,
+                    last_seen,
+                ))
+            })
+            .collect()
+    }
+
+    /// Adds the peers loaded from the peerstore, info of the current run is kept.
+    pub fn load_peers(&mut self, peers: &[StoredPeer]) {
+        for peer in peers {
+            if self.info.contains_key(&peer.peer_id) {
+                continue;
+            }
+            match peer.identify_info() {
+                Ok(identify) => {
+                    let info = Info {
+                        last_rtt: peer.rtt,
+                        last_info: Some(identify),
+                        last_seen: Some(peer.last_seen()),
+                    };
+                    self.info.insert(peer.peer_id, info);
+                }
+                Err(err) => debug!("invalid peerstore entry for {}: {:?}", peer.peer_id, err),
+            }
+        }
+    }
+
+    /// Remembers the addresses of a peer found outside of the swarm, so it can be dialed.
+    pub fn add_routed_addrs(&mut self, peer_id: PeerId, addrs: Vec<Multiaddr>) {
+        if !addrs.is_empty() {
+            self.routed_addrs.put(peer_id, addrs);
+        }
+    }
+
     pub fn info_for_peer(&self, peer_id: &PeerId) -> Option<&Info> {
         self.info.get(peer_id)
     }
 
+    /// Returns the remote addresses of the connections to the peer, along with whether the
+    /// connection is inbound.
+    pub fn connection_addrs<'a>(
+        &'a self,
+        peer_id: &'a PeerId,
+    ) -> impl Iterator<Item = (&'a Multiaddr, bool)> + 'a {
+        self.connections
+            .values()
+            .filter(move |conn| conn.peer_id == *peer_id)
+            .map(|conn| (&conn.remote_addr, conn.inbound))
+    }
+
     pub fn supported_protocols(&self) -> Vec<String> {
         self.supported_protocols.clone()
     }
 }
 
 impl NetworkBehaviour for PeerManager {
-    type ConnectionHandler = dummy::ConnectionHandler;
+    type ConnectionHandler = HandlerProto;
     type OutEvent = PeerManagerEvent;
 
     fn new_handler(&mut self) -> Self::ConnectionHandler {
-        dummy::ConnectionHandler
+        HandlerProto {
+            keep_alive: self.peering.clone(),
+        }
     }
 
     fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
-        self.info
+        if self.is_banned(peer_id) {
+            return Vec::new();
+        }
+        let mut addrs = self
+            .info
             .get(peer_id)
             .and_then(|i| i.last_info.as_ref())
             .map(|i| i.listen_addrs.clone())
-            .unwrap_or_default()
+            .unwrap_or_default();
+        if let Some(routed) = self.routed_addrs.get(peer_id) {
+            for addr in routed {
+                if !addrs.contains(addr) {
+                    addrs.push(addr.clone());
+                }
+            }
+        }
+        addrs
     }
 
     fn inject_connection_established(
         &mut self,
         peer_id: &PeerId,
-        _connection_id: &ConnectionId,
-        _endpoint: &ConnectedPoint,
+        connection_id: &ConnectionId,
+        endpoint: &ConnectedPoint,
         failed_addresses: Option<&Vec<Multiaddr>>,
         other_established: usize,
     ) {
+        self.connections.insert(
+            *connection_id,
+            Connection {
+                peer_id: *peer_id,
+                remote_addr: endpoint.get_remote_address().clone(),
+                established: Instant::now(),
+                inbound: endpoint.is_listener(),
+            },
+        );
+        self.enforce_limits(*peer_id, *connection_id, endpoint.is_listener());
+        self.trim_connections();
         if other_established == 0 {
             let p = self.bad_peers.pop(peer_id);
             if p.is_some() {
@@ -122,21 +479,30 @@ impl NetworkBehaviour for PeerManager {
 
     fn inject_connection_closed(
         &mut self,
-        _: &PeerId,
-        _: &ConnectionId,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
         _: &ConnectedPoint,
         _: <Self::ConnectionHandler as IntoConnectionHandler>::Handler,
-        _remaining_established: usize,
+        remaining_established: usize,
     ) {
+        self.connections.remove(connection_id);
+        if remaining_established == 0 {
+            if let Some(info) = self.info.get_mut(peer_id) {
+                info.last_seen = Some(SystemTime::now());
+            }
+        }
     }
 
     fn inject_address_change(
         &mut self,
         _: &PeerId,
-        _: &ConnectionId,
+        connection_id: &ConnectionId,
         _old: &ConnectedPoint,
-        _new: &ConnectedPoint,
+        new: &ConnectedPoint,
     ) {
+        if let Some(conn) = self.connections.get_mut(connection_id) {
+            conn.remote_addr = new.get_remote_address().clone();
+        }
     }
 
     fn inject_event(
@@ -155,7 +521,9 @@ impl NetworkBehaviour for PeerManager {
     ) {
         if let Some(peer_id) = peer_id {
             match error {
-                DialError::ConnectionLimit(_) | DialError::DialPeerConditionFalse(_) => {}
+                DialError::ConnectionLimit(_)
+                | DialError::DialPeerConditionFalse(_)
+                | DialError::Banned => {}
                 _ => {
                     if self.bad_peers.put(peer_id, ()).is_none() {
                         inc!(P2PMetrics::BadPeer);
@@ -191,7 +559,7 @@ impl NetworkBehaviour for PeerManager {
 
     fn poll(
         &mut self,
-        _cx: &mut Context<'_>,
+        cx: &mut Context<'_>,
         params: &mut impl PollParameters,
     ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
         // TODO(ramfox):
@@ -212,6 +580,237 @@ impl NetworkBehaviour for PeerManager {
                 .collect();
         }
 
+        if let Some((peer_id, connection)) = self.pending_closes.pop_front() {
+            return Poll::Ready(NetworkBehaviourAction::CloseConnection {
+                peer_id,
+                connection,
+            });
+        }
+        self.waker = Some(cx.waker().clone());
+
         Poll::Pending
     }
 }
+
+/// Creates the connection handlers of the peer manager.
+pub struct HandlerProto {
+    keep_alive: Arc<AHashSet<PeerId>>,
+}
+
+impl IntoConnectionHandler for HandlerProto {
+    type Handler = Handler;
+
+    fn into_handler(self, remote_peer_id: &PeerId, _: &ConnectedPoint) -> Self::Handler {
+        Handler {
+            inner: dummy::ConnectionHandler,
+            keep_alive: self.keep_alive.contains(remote_peer_id),
+        }
+    }
+
+    fn inbound_protocol(&self) -> <Self::Handler as ConnectionHandler>::InboundProtocol {
+        DeniedUpgrade
+    }
+}
+
+/// Connection handler without protocols, keeping the connections to the peering peers alive.
+pub struct Handler {
+    inner: dummy::ConnectionHandler,
+    keep_alive: bool,
+}
+
+impl ConnectionHandler for Handler {
+    type InEvent = <dummy::ConnectionHandler as ConnectionHandler>::InEvent;
+    type OutEvent = <dummy::ConnectionHandler as ConnectionHandler>::OutEvent;
+    type Error = <dummy::ConnectionHandler as ConnectionHandler>::Error;
+    type InboundProtocol = <dummy::ConnectionHandler as ConnectionHandler>::InboundProtocol;
+    type OutboundProtocol = <dummy::ConnectionHandler as ConnectionHandler>::OutboundProtocol;
+    type InboundOpenInfo = <dummy::ConnectionHandler as ConnectionHandler>::InboundOpenInfo;
+    type OutboundOpenInfo = <dummy::ConnectionHandler as ConnectionHandler>::OutboundOpenInfo;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        self.inner.listen_protocol()
+    }
+
+    fn inject_fully_negotiated_inbound(
+        &mut self,
+        protocol: <Self::InboundProtocol as InboundUpgrade<NegotiatedSubstream>>::Output,
+        info: Self::InboundOpenInfo,
+    ) {
+        self.inner.inject_fully_negotiated_inbound(protocol, info);
+    }
+
+    fn inject_fully_negotiated_outbound(
+        &mut self,
+        protocol: <Self::OutboundProtocol as OutboundUpgrade<NegotiatedSubstream>>::Output,
+        info: Self::OutboundOpenInfo,
+    ) {
+        self.inner.inject_fully_negotiated_outbound(protocol, info);
+    }
+
+    fn inject_event(&mut self, event: Self::InEvent) {
+        self.inner.inject_event(event);
+    }
+
+    fn inject_dial_upgrade_error(
+        &mut self,
+        info: Self::OutboundOpenInfo,
+        error: ConnectionHandlerUpgrErr<
+            <Self::OutboundProtocol as OutboundUpgrade<NegotiatedSubstream>>::Error,
+        >,
+    ) {
+        self.inner.inject_dial_upgrade_error(info, error);
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        if self.keep_alive {
+            KeepAlive::Yes
+        } else {
+            self.inner.connection_keep_alive()
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<
+            Self::OutboundProtocol,
+            Self::OutboundOpenInfo,
+            Self::OutEvent,
+            Self::Error,
+        >,
+    > {
+        self.inner.poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use super::*;
+    use crate::behaviour::PeerFilter;
+
+    fn endpoint() -> ConnectedPoint {
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/4001".parse().unwrap();
+        ConnectedPoint::Listener {
+            local_addr: addr.clone(),
+            send_back_addr: addr,
+        }
+    }
+
+    fn connect(peer_manager: &mut PeerManager, peer_id: &PeerId, id: usize) {
+        let endpoint = endpoint();
+        peer_manager.inject_connection_established(
+            peer_id,
+            &ConnectionId::new(id),
+            &endpoint,
+            None,
+            0,
+        );
+    }
+
+    fn trimmed(peer_manager: &mut PeerManager) -> AHashSet<PeerId> {
+        peer_manager
+            .pending_closes
+            .drain(..)
+            .map(|(peer_id, _)| peer_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_trim_connections() {
+        let peers: Vec<_> = (0..5).map(|_| PeerId::random()).collect();
+        let config = Libp2pConfig {
+            conn_low_water: 2,
+            conn_high_water: 3,
+            conn_grace_period_secs: 0,
+            protected_peers: vec![peers[0]],
+            ..Default::default()
+        };
+        let peer_filter = Arc::new(RwLock::new(PeerFilter::default()));
+        let mut peer_manager = PeerManager::new(&config, peer_filter);
+        peer_manager.tag_peer(peers[1], "test", 5);
+
+        for (id, peer_id) in peers.iter().take(3).enumerate() {
+            connect(&mut peer_manager, peer_id, id);
+        }
+        assert!(trimmed(&mut peer_manager).is_empty());
+
+        // the protected and the tagged peer are kept
+        connect(&mut peer_manager, &peers[3], 3);
+        assert_eq!(
+            trimmed(&mut peer_manager),
+            [peers[2], peers[3]].into_iter().collect()
+        );
+
+        // no trimming during the silence period
+        connect(&mut peer_manager, &peers[4], 4);
+        assert!(trimmed(&mut peer_manager).is_empty());
+
+        assert!(!peer_manager.unprotect_peer(&peers[0], "config"));
+        peer_manager.untag_all("test");
+        assert_eq!(peer_manager.peer_value(&peers[1]), 0);
+    }
+
+    #[test]
+    fn test_ban_peer() {
+        let peer_filter = Arc::new(RwLock::new(PeerFilter::default()));
+        let mut peer_manager = PeerManager::new(&Libp2pConfig::default(), peer_filter);
+        let short = PeerId::random();
+        let forever = PeerId::random();
+
+        peer_manager.ban_peer(short, Duration::ZERO);
+        // too long to track, the ban never expires
+        peer_manager.ban_peer(forever, Duration::MAX);
+        assert!(peer_manager.is_banned(&short));
+        assert!(peer_manager.is_banned(&forever));
+
+        assert_eq!(peer_manager.expired_bans(), vec![short]);
+        assert!(!peer_manager.is_banned(&short));
+        assert!(peer_manager.is_banned(&forever));
+    }
+
+    #[test]
+    fn test_peering() {
+        let peers: Vec<_> = (0..3).map(|_| PeerId::random()).collect();
+        let config = Libp2pConfig {
+            max_conns_in: 1,
+            peering: vec![format!("/p2p/{}", peers[0]).parse().unwrap()],
+            ..Default::default()
+        };
+        let peer_filter = Arc::new(RwLock::new(PeerFilter::default()));
+        let mut peer_manager = PeerManager::new(&config, peer_filter);
+        assert!(peer_manager.is_protected(&peers[0]));
+
+        // the peering peer does not count against the limit
+        connect(&mut peer_manager, &peers[1], 0);
+        connect(&mut peer_manager, &peers[0], 1);
+        assert!(trimmed(&mut peer_manager).is_empty());
+        connect(&mut peer_manager, &peers[2], 2);
+        assert_eq!(trimmed(&mut peer_manager), [peers[2]].into_iter().collect());
+
+        // and its connections are kept alive
+        let handler = peer_manager
+            .new_handler()
+            .into_handler(&peers[0], &endpoint());
+        assert_eq!(handler.connection_keep_alive(), KeepAlive::Yes);
+        let handler = peer_manager
+            .new_handler()
+            .into_handler(&peers[1], &endpoint());
+        assert_eq!(handler.connection_keep_alive(), KeepAlive::No);
+    }
+
+    #[test]
+    fn test_routed_addrs() {
+        let peer_id = PeerId::random();
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/4001".parse().unwrap();
+        let peer_filter = Arc::new(RwLock::new(PeerFilter::default()));
+        let mut peer_manager = PeerManager::new(&Libp2pConfig::default(), peer_filter);
+        assert!(peer_manager.addresses_of_peer(&peer_id).is_empty());
+
+        peer_manager.add_routed_addrs(peer_id, vec![addr.clone()]);
+        assert_eq!(peer_manager.addresses_of_peer(&peer_id), vec![addr]);
+    }
+}
@@ -0,0 +1,175 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use libp2p::identify::Info as IdentifyInfo;
+use libp2p::identity::PublicKey;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// Known peers, persisted so the node can reconnect and seed kademlia after a restart.
+///
+/// The peers are written as a single snapshot, replacing the previous one. Peers that were
+/// not seen within the ttl are dropped, both when saving and when loading.
+#[derive(Debug, Clone)]
+pub struct Peerstore {
+    path: PathBuf,
+    ttl: Duration,
+}
+
+/// The identify info, latency and last-seen time of a peer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredPeer {
+    pub peer_id: PeerId,
+    /// Unix timestamp in seconds.
+    pub last_seen: u64,
+    pub rtt: Option<Duration>,
+    /// Protobuf encoded public key.
+    pub public_key: Vec<u8>,
+    pub protocol_version: String,
+    pub agent_version: String,
+    pub listen_addrs: Vec<Multiaddr>,
+    pub protocols: Vec<String>,
+}
+
+impl StoredPeer {
+    pub fn new(
+        peer_id: PeerId,
+        info: &IdentifyInfo,
+        rtt: Option<Duration>,
+        last_seen: SystemTime,
+    ) -> Self {
+        StoredPeer {
+            peer_id,
+            last_seen: last_seen
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            rtt,
+            public_key: info.public_key.to_protobuf_encoding(),
+            protocol_version: info.protocol_version.clone(),
+            agent_version: info.agent_version.clone(),
+            listen_addrs: info.listen_addrs.clone(),
+            protocols: info.protocols.clone(),
+        }
+    }
+
+    pub fn last_seen(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.last_seen)
+    }
+
+    pub fn identify_info(&self) -> Result<IdentifyInfo> {
+        Ok(IdentifyInfo {
+            public_key: PublicKey::from_protobuf_encoding(&self.public_key)?,
+            protocol_version: self.protocol_version.clone(),
+            agent_version: self.agent_version.clone(),
+            listen_addrs: self.listen_addrs.clone(),
+            protocols: self.protocols.clone(),
+            observed_addr: Multiaddr::empty(),
+        })
+    }
+
+    fn is_expired(&self, ttl: Duration) -> bool {
+        SystemTime::now()
+            .duration_since(self.last_seen())
+            .map(|age| age > ttl)
+            .unwrap_or_default()
+    }
+}
+
+impl Peerstore {
+    pub fn new(path: PathBuf, ttl: Duration) -> Self {
+        Peerstore { path, ttl }
+    }
+
+    /// Loads the stored peers that have not expired, most recently seen first.
+    ///
+    /// A missing file is not an error, it simply holds no peers.
+    pub fn load(&self) -> Result<Vec<StoredPeer>> {
+        let bytes = match std::fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read peerstore {}", self.path.display()))
+            }
+        };
+        let mut peers: Vec<StoredPeer> = bincode::deserialize(&bytes)
+            .with_context(|| format!("invalid peerstore {}", self.path.display()))?;
+        let count = peers.len();
+        peers.retain(|peer| !peer.is_expired(self.ttl));
+        debug!("peerstore: dropped {} expired peers", count - peers.len());
+        peers.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+        Ok(peers)
+    }
+
+    /// Replaces the stored peers with the ones that have not expired.
+    ///
+    /// The snapshot is written to a temporary file first, so an interrupted write never
+    /// leaves a truncated peerstore behind.
+    pub fn save(&self, peers: &[StoredPeer]) -> Result<()> {
+        let peers: Vec<_> = peers
+            .iter()
+            .filter(|peer| !peer.is_expired(self.ttl))
+            .collect();
+        let bytes = bincode::serialize(&peers)?;
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, bytes)
+            .with_context(|| format!("failed to write peerstore {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("failed to write peerstore {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::identity::Keypair;
+
+    use super::*;
+
+    fn stored_peer(last_seen: SystemTime) -> StoredPeer {
+        let public_key = Keypair::generate_ed25519().public();
+        let info = IdentifyInfo {
+            public_key: public_key.clone(),
+            protocol_version: "ipfs/0.1.0".to_string(),
+            agent_version: "iroh/test".to_string(),
+            listen_addrs: vec!["/ip4/127.0.0.1/tcp/4001".parse().unwrap()],
+            protocols: vec!["/ipfs/kad/1.0.0".to_string()],
+            observed_addr: Multiaddr::empty(),
+        };
+        let rtt = Some(Duration::from_millis(20));
+        StoredPeer::new(public_key.to_peer_id(), &info, rtt, last_seen)
+    }
+
+    #[test]
+    fn test_peerstore() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("peers");
+        let peerstore = Peerstore::new(path.clone(), Duration::from_secs(60 * 60));
+        assert!(peerstore.load().unwrap().is_empty());
+
+        let now = SystemTime::now();
+        let old = stored_peer(now - Duration::from_secs(60));
+        let new = stored_peer(now);
+        let expired = stored_peer(now - Duration::from_secs(2 * 60 * 60));
+        peerstore
+            .save(&[old.clone(), expired, new.clone()])
+            .unwrap();
+
+        // most recently seen first
+        let peers = peerstore.load().unwrap();
+        assert_eq!(peers, vec![new.clone(), old]);
+        let info = peers[0].identify_info().unwrap();
+        assert_eq!(info.public_key.to_peer_id(), new.peer_id);
+        assert_eq!(info.listen_addrs, new.listen_addrs);
+
+        // entries also expire when loading
+        let peerstore = Peerstore::new(path, Duration::from_secs(30));
+        assert_eq!(peerstore.load().unwrap(), vec![new]);
+    }
+}
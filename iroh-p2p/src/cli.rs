@@ -1,6 +1,6 @@
 use std::{collections::HashMap, path::PathBuf};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug, Clone)]
 #[clap(author, version, about, long_about = None)]
@@ -9,8 +9,21 @@ pub struct Args {
     metrics: bool,
     #[clap(long = "tracing")]
     tracing: bool,
+    /// Path to a swarm key file, joins the private network it defines.
+    #[clap(long)]
+    swarm_key: Option<PathBuf>,
     #[clap(long)]
     pub cfg: Option<PathBuf>,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Encrypts the stored identity keys with the configured key passphrase and exits.
+    ///
+    /// Keys which are already encrypted are left untouched.
+    EncryptKeys,
 }
 
 impl Args {
@@ -18,6 +31,12 @@ impl Args {
         let mut map = HashMap::new();
         map.insert("metrics.collect".to_string(), self.metrics.to_string());
         map.insert("metrics.tracing".to_string(), self.tracing.to_string());
+        if let Some(path) = &self.swarm_key {
+            map.insert(
+                "p2p.libp2p.swarm_key_path".to_string(),
+                path.to_str().unwrap_or("").to_string(),
+            );
+        }
         map
     }
 }
@@ -1,13 +1,21 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
+use std::str::FromStr;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use config::{ConfigError, Map, Source, Value};
+use ipnet::IpNet;
 use iroh_metrics::config::Config as MetricsConfig;
 use iroh_rpc_client::Config as RpcClientConfig;
 use iroh_rpc_types::p2p::P2pAddr;
-use iroh_util::{insert_into_config_map, iroh_data_root};
-use libp2p::Multiaddr;
+use iroh_util::dns::DnsResolverConfig;
+use iroh_util::{insert_into_config_map, iroh_data_path, iroh_data_root};
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
 use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::keys::Passphrase;
 
 /// CONFIG_FILE_NAME is the name of the optional config file located in the iroh home directory
 pub const CONFIG_FILE_NAME: &str = "p2p.config.toml";
@@ -85,22 +93,335 @@ pub struct Libp2pConfig {
     pub bitswap_client: bool,
     /// Kademlia discovery enabled.
     pub kademlia: bool,
+    /// Whether the node answers Kademlia queries from other peers.
+    pub kademlia_mode: KademliaMode,
+    /// Delegated routing endpoints, serving `/routing/v1/providers/{cid}`, queried for
+    /// providers along with Kademlia.
+    pub delegated_routers: Vec<Url>,
     /// Autonat holepunching enabled.
     pub autonat: bool,
     /// Relay server enabled.
     pub relay_server: bool,
+    /// Maximum number of peers with a reservation on the relay server.
+    pub relay_max_reservations: usize,
+    /// Maximum number of circuits relayed at the same time.
+    pub relay_max_circuits: usize,
+    /// Seconds after which a relayed circuit is closed.
+    pub relay_max_circuit_duration_secs: u64,
+    /// Number of bytes after which a relayed circuit is closed, in each direction.
+    pub relay_max_circuit_bytes: u64,
+    /// Only these peers may reserve a slot on the relay server, unless the list is empty.
+    pub relay_allow_peers: Vec<PeerId>,
     /// Relay client enabled.
     pub relay_client: bool,
+    /// Relays the node listens through while it is not publicly reachable, as multiaddrs
+    /// ending in `/p2p/<peer id>`.
+    ///
+    /// They are preferred over the relays discovered in the network. Without `autonat` the
+    /// reachability is unknown, and the node always listens through them.
+    pub static_relays: Vec<Multiaddr>,
+    /// Number of relays the node keeps a reservation on while it is not publicly reachable.
+    pub relay_reservations: usize,
     /// Gossipsub enabled.
     pub gossipsub: bool,
+    /// Gossipsub peer scoring enabled, peers are penalized for messages the validators
+    /// reject. Disabled by default.
+    pub gossipsub_peer_scoring: bool,
+    /// Score weight of the invalid messages a peer delivered on a topic, must be negative.
+    ///
+    /// Applies to the topics without score parameters of their own, see
+    /// [`GossipsubValidators::set_topic_params`](crate::GossipsubValidators::set_topic_params).
+    pub gossipsub_invalid_message_weight: FiniteF64,
+    /// Factor the count of invalid messages of a peer decays by every second.
+    pub gossipsub_invalid_message_decay: FiniteF64,
+    /// Score below which no gossip is exchanged with a peer.
+    pub gossipsub_gossip_threshold: FiniteF64,
+    /// Score below which messages are not published to a peer.
+    pub gossipsub_publish_threshold: FiniteF64,
+    /// Score below which all messages from a peer are ignored.
+    pub gossipsub_graylist_threshold: FiniteF64,
     pub max_conns_out: u32,
     pub max_conns_in: u32,
     pub max_conns_pending_out: u32,
     pub max_conns_pending_in: u32,
     pub max_conns_per_peer: u32,
+    /// Connections are trimmed down to this number once they exceed `conn_high_water`.
+    pub conn_low_water: u32,
+    /// Number of connections above which the least valuable peers are disconnected,
+    /// `0` disables trimming.
+    pub conn_high_water: u32,
+    /// Seconds a new peer is exempt from trimming.
+    pub conn_grace_period_secs: u64,
+    /// Peers whose connections are never trimmed.
+    pub protected_peers: Vec<PeerId>,
+    /// Peers the node stays connected to, as multiaddrs ending in `/p2p/<peer id>`.
+    ///
+    /// A bare `/p2p/<peer id>` is looked up in the DHT, if `kademlia` is enabled. The
+    /// connections are kept alive, redialed with a backoff when they drop, never trimmed
+    /// and not counted against `max_conns_in` and `max_conns_out`.
+    pub peering: Vec<Multiaddr>,
+    /// Seconds after which peers that were not seen are dropped from the peerstore.
+    pub peerstore_ttl_secs: u64,
     pub notify_handler_buffer_size: usize,
     pub connection_event_buffer_size: usize,
     pub dial_concurrency_factor: u8,
+    /// Which locally stored content is periodically re-announced to the DHT.
+    pub reprovider_strategy: ReproviderStrategy,
+    /// Seconds between two reprovider runs, `0` disables the reprovider.
+    pub reprovider_interval_secs: u64,
+    /// Number of provider records the reprovider announces at once.
+    pub reprovider_batch_size: usize,
+    /// TCP transport enabled.
+    pub tcp: bool,
+    /// Websocket transport enabled.
+    pub websocket: bool,
+    /// QUIC transport enabled.
+    ///
+    /// The `listening_multiaddrs` can only use the enabled transports.
+    pub quic: bool,
+    /// Reuse the listening port for outgoing TCP connections.
+    pub port_reuse: bool,
+    /// Seconds a TCP or websocket connection may take to be established and upgraded.
+    pub connection_timeout_secs: u64,
+    /// The stream muxer offered first on TCP and websocket connections.
+    pub preferred_muxer: Muxer,
+    /// Maximum number of bytes buffered per yamux stream.
+    pub yamux_max_buffer_size: usize,
+    /// Initial yamux receive window per stream, in bytes.
+    pub yamux_receive_window_size: u32,
+    /// Maximum number of frames buffered per mplex substream, `None` for no limit.
+    pub mplex_max_buffer_size: Option<usize>,
+    /// Path to a swarm key file, restricting the node to a private network.
+    ///
+    /// Only peers using the same pre-shared key can connect to the node. The file uses the
+    /// `/key/swarm/psk/1.0.0/` format of go-ipfs. QUIC does not support private networks, it
+    /// is disabled and its `listening_multiaddrs` are skipped.
+    pub swarm_key_path: Option<PathBuf>,
+    /// Only these peers may connect, unless the list is empty.
+    pub allow_peers: Vec<PeerId>,
+    /// Peers that are never connected to.
+    pub deny_peers: Vec<PeerId>,
+    /// Only addresses in these ranges may be connected to, unless the list is empty.
+    pub allow_addrs: Vec<AddrFilter>,
+    /// Address ranges that are never connected to.
+    pub deny_addrs: Vec<AddrFilter>,
+    /// Nameservers resolving the DNS names in multiaddrs.
+    pub dns_nameservers: DnsNameservers,
+    /// Addresses of the nameservers used when `dns_nameservers` is `custom`.
+    pub dns_custom_nameservers: Vec<SocketAddr>,
+    /// Query the nameservers using DNS-over-HTTPS.
+    pub dns_over_https: bool,
+    /// TLS name of the custom nameservers, required for DNS-over-HTTPS.
+    pub dns_tls_name: Option<String>,
+    /// Separate resolvers for particular TLDs, the same mapping the gateway uses for DNSLink.
+    // NOTE: for toml to serialize properly, the "table" values must be serialized at the end, and
+    // so must come at the end of the struct
+    #[serde(default)]
+    pub dns_resolver: DnsResolverConfig,
+}
+
+/// Nameservers for the DNS names in multiaddrs.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DnsNameservers {
+    /// The nameservers of the system, from `/etc/resolv.conf` on unix.
+    System,
+    #[default]
+    Cloudflare,
+    Google,
+    Quad9,
+    /// The nameservers listed in `dns_custom_nameservers`.
+    Custom,
+}
+
+impl fmt::Display for DnsNameservers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsNameservers::System => write!(f, "system"),
+            DnsNameservers::Cloudflare => write!(f, "cloudflare"),
+            DnsNameservers::Google => write!(f, "google"),
+            DnsNameservers::Quad9 => write!(f, "quad9"),
+            DnsNameservers::Custom => write!(f, "custom"),
+        }
+    }
+}
+
+/// Stream muxers available for TCP and websocket connections.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Muxer {
+    #[default]
+    Yamux,
+    Mplex,
+}
+
+impl fmt::Display for Muxer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Muxer::Yamux => write!(f, "yamux"),
+            Muxer::Mplex => write!(f, "mplex"),
+        }
+    }
+}
+
+/// Selects the content announced by the reprovider.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReproviderStrategy {
+    /// Every block in the store.
+    #[default]
+    All,
+    /// Only the pinned roots.
+    Roots,
+    /// The pinned roots and all blocks linked from them.
+    Pinned,
+}
+
+impl fmt::Display for ReproviderStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReproviderStrategy::All => write!(f, "all"),
+            ReproviderStrategy::Roots => write!(f, "roots"),
+            ReproviderStrategy::Pinned => write!(f, "pinned"),
+        }
+    }
+}
+
+/// Roles of the node in the Kademlia DHT.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KademliaMode {
+    /// Only query the DHT, without serving other peers.
+    Client,
+    /// Query the DHT and answer the queries of other peers.
+    #[default]
+    Server,
+    /// Act as a server unless AutoNAT reports the node as private, so with `autonat`
+    /// disabled the node keeps serving.
+    ///
+    /// Switching applies to the open connections too, and is pushed to the connected peers
+    /// through identify.
+    Auto,
+}
+
+impl fmt::Display for KademliaMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KademliaMode::Client => write!(f, "client"),
+            KademliaMode::Server => write!(f, "server"),
+            KademliaMode::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+/// Returns the peer id of an address ending in `/p2p/<peer id>`.
+pub(crate) fn addr_peer_id(addr: &Multiaddr) -> Result<PeerId> {
+    match addr.iter().last() {
+        Some(Protocol::P2p(mh)) => {
+            PeerId::from_multihash(mh).map_err(|_| anyhow::anyhow!("invalid peer id in {}", addr))
+        }
+        _ => bail!("address {} does not end with /p2p/<peer id>", addr),
+    }
+}
+
+/// A finite floating point number, which unlike `f64` has a total equality.
+#[derive(PartialEq, Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(try_from = "f64", into = "f64")]
+pub struct FiniteF64(f64);
+
+// NaN is the only `f64` not equal to itself
+impl Eq for FiniteF64 {}
+
+impl FiniteF64 {
+    pub fn new(value: f64) -> Result<Self> {
+        if !value.is_finite() {
+            bail!("expected a finite number, got {}", value);
+        }
+        Ok(FiniteF64(value))
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl TryFrom<f64> for FiniteF64 {
+    type Error = anyhow::Error;
+
+    fn try_from(value: f64) -> Result<Self> {
+        FiniteF64::new(value)
+    }
+}
+
+impl From<FiniteF64> for f64 {
+    fn from(value: FiniteF64) -> f64 {
+        value.0
+    }
+}
+
+/// A range of IP addresses, written as a multiaddr like `/ip4/10.0.0.0/ipcidr/8`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct AddrFilter(IpNet);
+
+impl AddrFilter {
+    /// Returns `true` if the first IP address of `addr` is in the range.
+    ///
+    /// Addresses without an IP address, like unresolved DNS names, never match.
+    pub fn matches(&self, addr: &Multiaddr) -> bool {
+        addr.iter()
+            .find_map(|p| match p {
+                Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+                Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+                _ => None,
+            })
+            .map_or(false, |ip| self.0.contains(&ip))
+    }
+}
+
+impl FromStr for AddrFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<_> = s.split('/').collect();
+        let (addr, prefix_len) = match parts.as_slice() {
+            ["", "ip4", addr, "ipcidr", len] => (IpAddr::V4(addr.parse()?), len),
+            ["", "ip6", addr, "ipcidr", len] => (IpAddr::V6(addr.parse()?), len),
+            _ => bail!("invalid address range {s}, expected /ip4/<addr>/ipcidr/<prefix length>"),
+        };
+        let net = IpNet::new(addr, prefix_len.parse()?)?;
+        Ok(AddrFilter(net.trunc()))
+    }
+}
+
+impl fmt::Display for AddrFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let proto = match self.0 {
+            IpNet::V4(_) => "ip4",
+            IpNet::V6(_) => "ip6",
+        };
+        write!(
+            f,
+            "/{}/{}/ipcidr/{}",
+            proto,
+            self.0.network(),
+            self.0.prefix_len()
+        )
+    }
+}
+
+impl TryFrom<String> for AddrFilter {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<AddrFilter> for String {
+    fn from(filter: AddrFilter) -> Self {
+        filter.to_string()
+    }
 }
 
 /// Configuration for the [`iroh-p2p`] node.
@@ -117,6 +438,24 @@ pub struct Config {
     /// format compatible with how ssh stores keys.  This points to a directory where these
     /// keypairs are stored.
     pub key_store_path: PathBuf,
+    /// Name of the environment variable holding the passphrase of the stored keys.
+    ///
+    /// With a passphrase, new keys are stored encrypted. Existing keys can be encrypted with
+    /// `iroh-p2p encrypt-keys`.
+    pub key_passphrase_env: Option<String>,
+    /// File holding the passphrase of the stored keys, as an alternative to
+    /// `key_passphrase_env`.
+    pub key_passphrase_path: Option<PathBuf>,
+    /// Directory where the Kademlia records are persisted.
+    ///
+    /// Without it, provider and value records are only kept in memory and are lost when the
+    /// node restarts.
+    pub kad_store_path: Option<PathBuf>,
+    /// File where the addresses, identify info and latency of known peers are persisted.
+    ///
+    /// The stored peers seed kademlia on start up and the most recently seen ones are
+    /// dialed again.
+    pub peerstore_path: Option<PathBuf>,
 }
 
 impl From<ServerConfig> for Config {
@@ -152,6 +491,18 @@ impl Source for Libp2pConfig {
             "max_conns_per_peer",
             self.max_conns_per_peer as i64,
         );
+        insert_into_config_map(&mut map, "conn_low_water", self.conn_low_water as i64);
+        insert_into_config_map(&mut map, "conn_high_water", self.conn_high_water as i64);
+        insert_into_config_map(
+            &mut map,
+            "conn_grace_period_secs",
+            self.conn_grace_period_secs as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "peerstore_ttl_secs",
+            self.peerstore_ttl_secs as i64,
+        );
         insert_into_config_map(
             &mut map,
             "notify_handler_buffer_size",
@@ -167,17 +518,159 @@ impl Source for Libp2pConfig {
             "dial_concurrency_factor",
             self.dial_concurrency_factor as i64,
         );
+        insert_into_config_map(
+            &mut map,
+            "reprovider_strategy",
+            self.reprovider_strategy.to_string(),
+        );
+        insert_into_config_map(
+            &mut map,
+            "reprovider_interval_secs",
+            self.reprovider_interval_secs as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "reprovider_batch_size",
+            self.reprovider_batch_size as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "connection_timeout_secs",
+            self.connection_timeout_secs as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "preferred_muxer",
+            self.preferred_muxer.to_string(),
+        );
+        insert_into_config_map(
+            &mut map,
+            "yamux_max_buffer_size",
+            self.yamux_max_buffer_size as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "yamux_receive_window_size",
+            self.yamux_receive_window_size as i64,
+        );
+        if let Some(size) = self.mplex_max_buffer_size {
+            insert_into_config_map(&mut map, "mplex_max_buffer_size", size as i64);
+        }
+        if let Some(path) = &self.swarm_key_path {
+            insert_into_config_map(&mut map, "swarm_key_path", path.to_str());
+        }
+        insert_into_config_map(
+            &mut map,
+            "dns_nameservers",
+            self.dns_nameservers.to_string(),
+        );
+        let nameservers: Vec<String> = self
+            .dns_custom_nameservers
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        insert_into_config_map(&mut map, "dns_custom_nameservers", nameservers);
+        insert_into_config_map(&mut map, "dns_over_https", self.dns_over_https);
+        if let Some(name) = &self.dns_tls_name {
+            insert_into_config_map(&mut map, "dns_tls_name", name.as_str());
+        }
 
         insert_into_config_map(&mut map, "kademlia", self.kademlia);
+        insert_into_config_map(&mut map, "kademlia_mode", self.kademlia_mode.to_string());
+        let routers: Vec<String> = self
+            .delegated_routers
+            .iter()
+            .map(|r| r.to_string())
+            .collect();
+        insert_into_config_map(&mut map, "delegated_routers", routers);
         insert_into_config_map(&mut map, "autonat", self.autonat);
         insert_into_config_map(&mut map, "bitswap_client", self.bitswap_client);
         insert_into_config_map(&mut map, "bitswap_server", self.bitswap_server);
         insert_into_config_map(&mut map, "mdns", self.mdns);
         insert_into_config_map(&mut map, "relay_server", self.relay_server);
+        insert_into_config_map(
+            &mut map,
+            "relay_max_reservations",
+            self.relay_max_reservations as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "relay_max_circuits",
+            self.relay_max_circuits as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "relay_max_circuit_duration_secs",
+            self.relay_max_circuit_duration_secs as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "relay_max_circuit_bytes",
+            self.relay_max_circuit_bytes as i64,
+        );
+        let peers: Vec<String> = self
+            .relay_allow_peers
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        insert_into_config_map(&mut map, "relay_allow_peers", peers);
         insert_into_config_map(&mut map, "relay_client", self.relay_client);
+        let relays: Vec<String> = self.static_relays.iter().map(|r| r.to_string()).collect();
+        insert_into_config_map(&mut map, "static_relays", relays);
+        insert_into_config_map(
+            &mut map,
+            "relay_reservations",
+            self.relay_reservations as i64,
+        );
         insert_into_config_map(&mut map, "gossipsub", self.gossipsub);
+        insert_into_config_map(
+            &mut map,
+            "gossipsub_peer_scoring",
+            self.gossipsub_peer_scoring,
+        );
+        insert_into_config_map(
+            &mut map,
+            "gossipsub_invalid_message_weight",
+            self.gossipsub_invalid_message_weight.get(),
+        );
+        insert_into_config_map(
+            &mut map,
+            "gossipsub_invalid_message_decay",
+            self.gossipsub_invalid_message_decay.get(),
+        );
+        insert_into_config_map(
+            &mut map,
+            "gossipsub_gossip_threshold",
+            self.gossipsub_gossip_threshold.get(),
+        );
+        insert_into_config_map(
+            &mut map,
+            "gossipsub_publish_threshold",
+            self.gossipsub_publish_threshold.get(),
+        );
+        insert_into_config_map(
+            &mut map,
+            "gossipsub_graylist_threshold",
+            self.gossipsub_graylist_threshold.get(),
+        );
+        insert_into_config_map(&mut map, "tcp", self.tcp);
+        insert_into_config_map(&mut map, "websocket", self.websocket);
+        insert_into_config_map(&mut map, "quic", self.quic);
+        insert_into_config_map(&mut map, "port_reuse", self.port_reuse);
         let peers: Vec<String> = self.bootstrap_peers.iter().map(|b| b.to_string()).collect();
         insert_into_config_map(&mut map, "bootstrap_peers", peers);
+        let peers: Vec<String> = self.protected_peers.iter().map(|p| p.to_string()).collect();
+        insert_into_config_map(&mut map, "protected_peers", peers);
+        let peers: Vec<String> = self.peering.iter().map(|p| p.to_string()).collect();
+        insert_into_config_map(&mut map, "peering", peers);
+        let peers: Vec<String> = self.allow_peers.iter().map(|p| p.to_string()).collect();
+        insert_into_config_map(&mut map, "allow_peers", peers);
+        let peers: Vec<String> = self.deny_peers.iter().map(|p| p.to_string()).collect();
+        insert_into_config_map(&mut map, "deny_peers", peers);
+        let filters: Vec<String> = self.allow_addrs.iter().map(|f| f.to_string()).collect();
+        insert_into_config_map(&mut map, "allow_addrs", filters);
+        let filters: Vec<String> = self.deny_addrs.iter().map(|f| f.to_string()).collect();
+        insert_into_config_map(&mut map, "deny_addrs", filters);
         let addrs: Vec<String> = self
             .listening_multiaddrs
             .iter()
@@ -198,6 +691,18 @@ impl Source for Config {
         insert_into_config_map(&mut map, "libp2p", self.libp2p.collect()?);
         insert_into_config_map(&mut map, "rpc_client", self.rpc_client.collect()?);
         insert_into_config_map(&mut map, "key_store_path", self.key_store_path.to_str());
+        if let Some(name) = &self.key_passphrase_env {
+            insert_into_config_map(&mut map, "key_passphrase_env", name.as_str());
+        }
+        if let Some(path) = &self.key_passphrase_path {
+            insert_into_config_map(&mut map, "key_passphrase_path", path.to_str());
+        }
+        if let Some(path) = &self.kad_store_path {
+            insert_into_config_map(&mut map, "kad_store_path", path.to_str());
+        }
+        if let Some(path) = &self.peerstore_path {
+            insert_into_config_map(&mut map, "peerstore_path", path.to_str());
+        }
         Ok(map)
     }
 }
@@ -217,10 +722,25 @@ impl Default for Libp2pConfig {
             bootstrap_peers,
             mdns: false,
             kademlia: true,
+            kademlia_mode: KademliaMode::Server,
+            delegated_routers: Vec::new(),
             autonat: true,
             relay_server: true,
+            relay_max_reservations: 128,
+            relay_max_circuits: 16,
+            relay_max_circuit_duration_secs: 2 * 60,
+            relay_max_circuit_bytes: 128 * 1024,
+            relay_allow_peers: Vec::new(),
             relay_client: true,
+            static_relays: Vec::new(),
+            relay_reservations: 2,
             gossipsub: true,
+            gossipsub_peer_scoring: false,
+            gossipsub_invalid_message_weight: FiniteF64(-10.0),
+            gossipsub_invalid_message_decay: FiniteF64(0.9),
+            gossipsub_gossip_threshold: FiniteF64(-10.0),
+            gossipsub_publish_threshold: FiniteF64(-50.0),
+            gossipsub_graylist_threshold: FiniteF64(-80.0),
             bitswap_client: true,
             bitswap_server: true,
             max_conns_pending_out: 256,
@@ -228,13 +748,58 @@ impl Default for Libp2pConfig {
             max_conns_in: 256,
             max_conns_out: 512,
             max_conns_per_peer: 8,
+            conn_low_water: 128,
+            conn_high_water: 192,
+            conn_grace_period_secs: 20,
+            protected_peers: Vec::new(),
+            peering: Vec::new(),
+            peerstore_ttl_secs: 7 * 24 * 60 * 60,
             notify_handler_buffer_size: 256,
             connection_event_buffer_size: 256,
             dial_concurrency_factor: 8,
+            reprovider_strategy: ReproviderStrategy::All,
+            reprovider_interval_secs: 12 * 60 * 60,
+            reprovider_batch_size: 64,
+            tcp: true,
+            websocket: true,
+            quic: true,
+            port_reuse: true,
+            connection_timeout_secs: 30,
+            preferred_muxer: Muxer::Yamux,
+            yamux_max_buffer_size: 16 * 1024 * 1024,
+            yamux_receive_window_size: 16 * 1024 * 1024,
+            mplex_max_buffer_size: None,
+            swarm_key_path: None,
+            allow_peers: Vec::new(),
+            deny_peers: Vec::new(),
+            allow_addrs: Vec::new(),
+            deny_addrs: Vec::new(),
+            dns_nameservers: DnsNameservers::Cloudflare,
+            dns_custom_nameservers: Vec::new(),
+            dns_over_https: false,
+            dns_tls_name: None,
+            dns_resolver: DnsResolverConfig::default(),
         }
     }
 }
 
+impl Libp2pConfig {
+    /// Whether the QUIC transport is used, it does not support private networks.
+    pub fn quic_enabled(&self) -> bool {
+        self.quic && self.swarm_key_path.is_none()
+    }
+
+    /// The `listening_multiaddrs` of the enabled transports.
+    pub fn listen_addrs(&self) -> impl Iterator<Item = &Multiaddr> {
+        let quic = self.quic_enabled();
+        self.listening_multiaddrs.iter().filter(move |addr| {
+            quic || !addr
+                .iter()
+                .any(|p| matches!(p, Protocol::Quic | Protocol::QuicV1))
+        })
+    }
+}
+
 impl Config {
     pub fn default_with_rpc(client_addr: P2pAddr) -> Self {
         Self {
@@ -244,6 +809,10 @@ impl Config {
                 ..Default::default()
             },
             key_store_path: iroh_data_root().unwrap(),
+            key_passphrase_env: None,
+            key_passphrase_path: None,
+            kad_store_path: None,
+            peerstore_path: None,
         }
     }
 
@@ -254,12 +823,28 @@ impl Config {
             libp2p: Libp2pConfig::default(),
             rpc_client,
             key_store_path: iroh_data_root().unwrap(),
+            key_passphrase_env: None,
+            key_passphrase_path: None,
+            kad_store_path: Some(iroh_data_path("kad").unwrap()),
+            peerstore_path: Some(iroh_data_path("peers").unwrap()),
         }
     }
 
     pub fn rpc_addr(&self) -> Option<P2pAddr> {
         self.rpc_client.p2p_addr.clone()
     }
+
+    /// Returns where the passphrase of the stored keys comes from, if one is configured.
+    pub fn key_passphrase(&self) -> Result<Option<Passphrase>> {
+        match (&self.key_passphrase_env, &self.key_passphrase_path) {
+            (Some(_), Some(_)) => {
+                bail!("only one of key_passphrase_env and key_passphrase_path can be set")
+            }
+            (Some(name), None) => Ok(Some(Passphrase::Env(name.clone()))),
+            (None, Some(path)) => Ok(Some(Passphrase::File(path.clone()))),
+            (None, None) => Ok(None),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -329,6 +914,31 @@ mod tests {
             Value::new(None, default.max_conns_per_peer as i64),
         );
 
+        expect.insert(
+            "conn_low_water".to_string(),
+            Value::new(None, default.conn_low_water as i64),
+        );
+        expect.insert(
+            "conn_high_water".to_string(),
+            Value::new(None, default.conn_high_water as i64),
+        );
+        expect.insert(
+            "conn_grace_period_secs".to_string(),
+            Value::new(None, default.conn_grace_period_secs as i64),
+        );
+        expect.insert(
+            "protected_peers".to_string(),
+            Value::new(None, Vec::<String>::new()),
+        );
+        expect.insert(
+            "peering".to_string(),
+            Value::new(None, Vec::<String>::new()),
+        );
+        expect.insert(
+            "peerstore_ttl_secs".to_string(),
+            Value::new(None, default.peerstore_ttl_secs as i64),
+        );
+
         expect.insert(
             "notify_handler_buffer_size".to_string(),
             Value::new(None, default.notify_handler_buffer_size as i64),
@@ -342,8 +952,45 @@ mod tests {
             "dial_concurrency_factor".to_string(),
             Value::new(None, default.dial_concurrency_factor as i64),
         );
+        expect.insert(
+            "reprovider_strategy".to_string(),
+            Value::new(None, default.reprovider_strategy.to_string()),
+        );
+        expect.insert(
+            "reprovider_interval_secs".to_string(),
+            Value::new(None, default.reprovider_interval_secs as i64),
+        );
+        expect.insert(
+            "reprovider_batch_size".to_string(),
+            Value::new(None, default.reprovider_batch_size as i64),
+        );
+
+        expect.insert(
+            "connection_timeout_secs".to_string(),
+            Value::new(None, default.connection_timeout_secs as i64),
+        );
+        expect.insert(
+            "preferred_muxer".to_string(),
+            Value::new(None, default.preferred_muxer.to_string()),
+        );
+        expect.insert(
+            "yamux_max_buffer_size".to_string(),
+            Value::new(None, default.yamux_max_buffer_size as i64),
+        );
+        expect.insert(
+            "yamux_receive_window_size".to_string(),
+            Value::new(None, default.yamux_receive_window_size as i64),
+        );
 
         expect.insert("kademlia".to_string(), Value::new(None, default.kademlia));
+        expect.insert(
+            "kademlia_mode".to_string(),
+            Value::new(None, default.kademlia_mode.to_string()),
+        );
+        expect.insert(
+            "delegated_routers".to_string(),
+            Value::new(None, Vec::<String>::new()),
+        );
         expect.insert("autonat".to_string(), Value::new(None, default.autonat));
         expect.insert("mdns".to_string(), Value::new(None, default.mdns));
         expect.insert(
@@ -358,16 +1005,103 @@ mod tests {
             "relay_server".to_string(),
             Value::new(None, default.relay_server),
         );
+        expect.insert(
+            "relay_max_reservations".to_string(),
+            Value::new(None, default.relay_max_reservations as i64),
+        );
+        expect.insert(
+            "relay_max_circuits".to_string(),
+            Value::new(None, default.relay_max_circuits as i64),
+        );
+        expect.insert(
+            "relay_max_circuit_duration_secs".to_string(),
+            Value::new(None, default.relay_max_circuit_duration_secs as i64),
+        );
+        expect.insert(
+            "relay_max_circuit_bytes".to_string(),
+            Value::new(None, default.relay_max_circuit_bytes as i64),
+        );
+        expect.insert(
+            "relay_allow_peers".to_string(),
+            Value::new(None, Vec::<String>::new()),
+        );
         expect.insert(
             "relay_client".to_string(),
             Value::new(None, default.relay_client),
         );
+        expect.insert(
+            "static_relays".to_string(),
+            Value::new(None, Vec::<String>::new()),
+        );
+        expect.insert(
+            "relay_reservations".to_string(),
+            Value::new(None, default.relay_reservations as i64),
+        );
         expect.insert("gossipsub".to_string(), Value::new(None, default.gossipsub));
+        expect.insert(
+            "gossipsub_peer_scoring".to_string(),
+            Value::new(None, default.gossipsub_peer_scoring),
+        );
+        expect.insert(
+            "gossipsub_invalid_message_weight".to_string(),
+            Value::new(None, default.gossipsub_invalid_message_weight.get()),
+        );
+        expect.insert(
+            "gossipsub_invalid_message_decay".to_string(),
+            Value::new(None, default.gossipsub_invalid_message_decay.get()),
+        );
+        expect.insert(
+            "gossipsub_gossip_threshold".to_string(),
+            Value::new(None, default.gossipsub_gossip_threshold.get()),
+        );
+        expect.insert(
+            "gossipsub_publish_threshold".to_string(),
+            Value::new(None, default.gossipsub_publish_threshold.get()),
+        );
+        expect.insert(
+            "gossipsub_graylist_threshold".to_string(),
+            Value::new(None, default.gossipsub_graylist_threshold.get()),
+        );
+        expect.insert("tcp".to_string(), Value::new(None, default.tcp));
+        expect.insert("websocket".to_string(), Value::new(None, default.websocket));
+        expect.insert("quic".to_string(), Value::new(None, default.quic));
+        expect.insert(
+            "port_reuse".to_string(),
+            Value::new(None, default.port_reuse),
+        );
         expect.insert(
             "bootstrap_peers".to_string(),
             Value::new(None, bootstrap_peers),
         );
         expect.insert("listening_multiaddrs".to_string(), Value::new(None, addrs));
+        expect.insert(
+            "allow_peers".to_string(),
+            Value::new(None, Vec::<String>::new()),
+        );
+        expect.insert(
+            "deny_peers".to_string(),
+            Value::new(None, Vec::<String>::new()),
+        );
+        expect.insert(
+            "allow_addrs".to_string(),
+            Value::new(None, Vec::<String>::new()),
+        );
+        expect.insert(
+            "deny_addrs".to_string(),
+            Value::new(None, Vec::<String>::new()),
+        );
+        expect.insert(
+            "dns_nameservers".to_string(),
+            Value::new(None, default.dns_nameservers.to_string()),
+        );
+        expect.insert(
+            "dns_custom_nameservers".to_string(),
+            Value::new(None, Vec::<String>::new()),
+        );
+        expect.insert(
+            "dns_over_https".to_string(),
+            Value::new(None, default.dns_over_https),
+        );
 
         let got = default.collect().unwrap();
         for key in got.keys() {
@@ -390,4 +1124,93 @@ mod tests {
 
         assert_eq!(expect, got);
     }
+
+    #[test]
+    fn test_build_config_with_swarm_key() {
+        let mut expect = Config::default_network();
+        expect.libp2p.quic = false;
+        expect.libp2p.swarm_key_path = Some(PathBuf::from("/etc/iroh/swarm.key"));
+        let got: Config = ConfigBuilder::builder()
+            .add_source(expect.clone())
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(expect, got);
+    }
+
+    #[test]
+    fn test_build_config_with_dns() {
+        let mut expect = Config::default_network();
+        expect.libp2p.dns_nameservers = DnsNameservers::Custom;
+        expect.libp2p.dns_custom_nameservers = vec![
+            "10.0.0.53:53".parse().unwrap(),
+            "[fd00::53]:443".parse().unwrap(),
+        ];
+        expect.libp2p.dns_over_https = true;
+        expect.libp2p.dns_tls_name = Some("dns.internal".to_string());
+        let got: Config = ConfigBuilder::builder()
+            .add_source(expect.clone())
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(expect, got);
+    }
+
+    #[test]
+    fn test_build_config_with_key_passphrase() {
+        let mut expect = Config::default_network();
+        expect.key_passphrase_path = Some(PathBuf::from("/etc/iroh/passphrase"));
+        let got: Config = ConfigBuilder::builder()
+            .add_source(expect.clone())
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(expect, got);
+        assert!(matches!(
+            got.key_passphrase().unwrap(),
+            Some(Passphrase::File(_))
+        ));
+        expect.key_passphrase_env = Some("IROH_KEY_PASSPHRASE".to_string());
+        assert!(expect.key_passphrase().is_err());
+    }
+
+    #[test]
+    fn test_build_config_with_filters() {
+        let mut expect = Config::default_network();
+        expect.libp2p.allow_peers = vec!["QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ"
+            .parse()
+            .unwrap()];
+        expect.libp2p.deny_addrs = vec![
+            "/ip4/10.0.0.0/ipcidr/8".parse().unwrap(),
+            "/ip6/fe80::/ipcidr/10".parse().unwrap(),
+        ];
+        let got: Config = ConfigBuilder::builder()
+            .add_source(expect.clone())
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(expect, got);
+    }
+
+    #[test]
+    fn test_addr_filter() {
+        let filter: AddrFilter = "/ip4/10.1.2.3/ipcidr/16".parse().unwrap();
+        assert_eq!(filter.to_string(), "/ip4/10.1.0.0/ipcidr/16");
+        assert!(filter.matches(&"/ip4/10.1.200.1/tcp/4001".parse().unwrap()));
+        assert!(!filter.matches(&"/ip4/10.2.0.1/tcp/4001".parse().unwrap()));
+        assert!(!filter.matches(&"/ip6/::1/tcp/4001".parse().unwrap()));
+        assert!(!filter.matches(&"/dns4/example.com/tcp/4001".parse().unwrap()));
+
+        assert!("/ip4/10.0.0.0/ipcidr/33".parse::<AddrFilter>().is_err());
+        assert!("/ip4/::1/ipcidr/8".parse::<AddrFilter>().is_err());
+        assert!("/ip4/10.0.0.0/tcp/8".parse::<AddrFilter>().is_err());
+    }
 }
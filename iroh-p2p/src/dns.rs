@@ -0,0 +1,365 @@
+//! Resolution of the DNS names in multiaddrs.
+
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use anyhow::{ensure, Context as _, Result};
+use futures::future::{BoxFuture, FutureExt};
+use iroh_util::dns::DnsResolverConfig;
+use libp2p::core::transport::{Boxed, ListenerId, TransportError, TransportEvent};
+use libp2p::core::Endpoint;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, Transport};
+use tracing::debug;
+use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use trust_dns_resolver::error::ResolveError;
+use trust_dns_resolver::TokioAsyncResolver;
+
+use crate::{DnsNameservers, Libp2pConfig};
+
+/// Returns the configuration of the default resolver for the DNS names in multiaddrs.
+pub(crate) fn resolver_config(config: &Libp2pConfig) -> Result<(ResolverConfig, ResolverOpts)> {
+    let https = config.dns_over_https;
+    let resolver_config = match config.dns_nameservers {
+        DnsNameservers::System => {
+            ensure!(
+                !https,
+                "dns_over_https is not supported with the system nameservers"
+            );
+            return trust_dns_resolver::system_conf::read_system_conf()
+                .context("failed to read the system dns configuration");
+        }
+        DnsNameservers::Cloudflare if https => ResolverConfig::cloudflare_https(),
+        DnsNameservers::Cloudflare => ResolverConfig::cloudflare(),
+        DnsNameservers::Google if https => ResolverConfig::google_https(),
+        DnsNameservers::Google => ResolverConfig::google(),
+        DnsNameservers::Quad9 if https => ResolverConfig::quad9_https(),
+        DnsNameservers::Quad9 => ResolverConfig::quad9(),
+        DnsNameservers::Custom => {
+            ensure!(
+                !config.dns_custom_nameservers.is_empty(),
+                "custom dns nameservers are selected, but dns_custom_nameservers is empty"
+            );
+            let mut nameservers = NameServerConfigGroup::new();
+            for addr in &config.dns_custom_nameservers {
+                let group = if https {
+                    let tls_name = config
+                        .dns_tls_name
+                        .clone()
+                        .context("dns_tls_name is required for DNS-over-HTTPS")?;
+                    NameServerConfigGroup::from_ips_https(&[addr.ip()], addr.port(), tls_name, true)
+                } else {
+                    NameServerConfigGroup::from_ips_clear(&[addr.ip()], addr.port(), true)
+                };
+                nameservers.merge(group);
+            }
+            ResolverConfig::from_parts(None, vec![], nameservers)
+        }
+    };
+    Ok((resolver_config, ResolverOpts::default()))
+}
+
+/// Resolves the DNS names of multiaddrs under a TLD with a separate resolver, all other
+/// addresses are passed on to the inner transport unchanged.
+///
+/// The inner transport is shared with the dials, which only start once the name is
+/// resolved.
+pub(crate) struct TldDnsTransport<O> {
+    inner: Arc<Mutex<Boxed<O>>>,
+    resolvers: HashMap<String, TokioAsyncResolver>,
+}
+
+impl<O: Send + 'static> TldDnsTransport<O> {
+    pub(crate) fn new(inner: Boxed<O>, config: &DnsResolverConfig) -> Result<Self> {
+        let resolvers = config
+            .tld_resolvers()
+            .map(|(tld, config)| {
+                let resolver = TokioAsyncResolver::tokio(config.clone(), ResolverOpts::default())?;
+                Ok((tld.to_string(), resolver))
+            })
+            .collect::<Result<_>>()?;
+        Ok(TldDnsTransport {
+            inner: Arc::new(Mutex::new(inner)),
+            resolvers,
+        })
+    }
+
+    /// Returns the separate resolver for the DNS name the address starts with, if any.
+    fn resolver(&self, addr: &Multiaddr) -> Option<&TokioAsyncResolver> {
+        let name = match addr.iter().next()? {
+            Protocol::Dns(name)
+            | Protocol::Dns4(name)
+            | Protocol::Dns6(name)
+            | Protocol::Dnsaddr(name) => name,
+            _ => return None,
+        };
+        self.resolvers.get(tld(&name)?)
+    }
+
+    fn do_dial(
+        &mut self,
+        addr: Multiaddr,
+        role: Endpoint,
+    ) -> Result<BoxFuture<'static, io::Result<O>>, TransportError<io::Error>> {
+        let resolver = match self.resolver(&addr) {
+            Some(resolver) => resolver.clone(),
+            None => return dial_inner(&self.inner, addr, role),
+        };
+        let inner = self.inner.clone();
+        Ok(async move {
+            let mut last_err = None;
+            for resolved in resolve(&resolver, &addr).await? {
+                debug!("dialing {} resolved from {}", resolved, addr);
+                let res = match dial_inner(&inner, resolved, role) {
+                    Ok(dial) => dial.await,
+                    Err(TransportError::MultiaddrNotSupported(addr)) => Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        format!("unsupported address {addr}"),
+                    )),
+                    Err(TransportError::Other(err)) => Err(err),
+                };
+                match res {
+                    Ok(output) => return Ok(output),
+                    Err(err) => last_err = Some(err),
+                }
+            }
+            Err(last_err.unwrap_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("no addresses found for {addr}"),
+                )
+            }))
+        }
+        .boxed())
+    }
+}
+
+impl<O: Send + 'static> Transport for TldDnsTransport<O> {
+    type Output = O;
+    type Error = io::Error;
+    type ListenerUpgrade = BoxFuture<'static, io::Result<O>>;
+    type Dial = BoxFuture<'static, io::Result<O>>;
+
+    fn listen_on(&mut self, addr: Multiaddr) -> Result<ListenerId, TransportError<Self::Error>> {
+        self.inner.lock().unwrap().listen_on(addr)
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        self.inner.lock().unwrap().remove_listener(id)
+    }
+
+    fn dial(&mut self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        self.do_dial(addr, Endpoint::Dialer)
+    }
+
+    fn dial_as_listener(
+        &mut self,
+        addr: Multiaddr,
+    ) -> Result<Self::Dial, TransportError<Self::Error>> {
+        self.do_dial(addr, Endpoint::Listener)
+    }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.inner
+            .lock()
+            .unwrap()
+            .address_translation(listen, observed)
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        let mut inner = self.inner.lock().unwrap();
+        Pin::new(&mut *inner).poll(cx)
+    }
+}
+
+fn dial_inner<O>(
+    inner: &Mutex<Boxed<O>>,
+    addr: Multiaddr,
+    role: Endpoint,
+) -> Result<BoxFuture<'static, io::Result<O>>, TransportError<io::Error>> {
+    let mut inner = inner.lock().unwrap();
+    match role {
+        Endpoint::Dialer => inner.dial(addr),
+        Endpoint::Listener => inner.dial_as_listener(addr),
+    }
+}
+
+/// Resolves the DNS name the address starts with, keeping the rest of the address.
+///
+/// A `/dnsaddr` name resolves to the addresses in its TXT records which end with the rest of
+/// the address, usually the `/p2p` peer id.
+async fn resolve(resolver: &TokioAsyncResolver, addr: &Multiaddr) -> io::Result<Vec<Multiaddr>> {
+    let mut iter = addr.iter();
+    let first = iter.next();
+    let rest: Multiaddr = iter.collect();
+    let to_io_error = |err: ResolveError| io::Error::new(io::ErrorKind::Other, err);
+
+    let (name, ipv4, ipv6) = match &first {
+        Some(Protocol::Dns(name)) => (name, true, true),
+        Some(Protocol::Dns4(name)) => (name, true, false),
+        Some(Protocol::Dns6(name)) => (name, false, true),
+        Some(Protocol::Dnsaddr(name)) => {
+            let lookup = resolver
+                .txt_lookup(format!("_dnsaddr.{name}"))
+                .await
+                .map_err(to_io_error)?;
+            let addrs = lookup
+                .iter()
+                .filter_map(|txt| {
+                    let txt = txt.to_string();
+                    txt.strip_prefix("dnsaddr=")?.parse::<Multiaddr>().ok()
+                })
+                .filter(|resolved| resolved.ends_with(&rest))
+                .collect();
+            return Ok(addrs);
+        }
+        _ => return Ok(vec![addr.clone()]),
+    };
+    let lookup = resolver
+        .lookup_ip(name.to_string())
+        .await
+        .map_err(to_io_error)?;
+    let addrs = lookup
+        .iter()
+        .filter(|ip| (ip.is_ipv4() && ipv4) || (ip.is_ipv6() && ipv6))
+        .map(|ip| Multiaddr::from(ip).iter().chain(rest.iter()).collect())
+        .collect();
+    Ok(addrs)
+}
+
+/// Returns the top level domain of a DNS name.
+fn tld(name: &str) -> Option<&str> {
+    name.trim_end_matches('.')
+        .rsplit('.')
+        .next()
+        .filter(|tld| !tld.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::core::transport::dummy::DummyTransport;
+
+    use super::*;
+
+    #[test]
+    fn test_tld() {
+        assert_eq!(tld("ipfs.io"), Some("io"));
+        assert_eq!(tld("vitalik.eth."), Some("eth"));
+        assert_eq!(tld("localhost"), Some("localhost"));
+        assert_eq!(tld(""), None);
+    }
+
+    #[test]
+    fn test_resolver_config() {
+        let mut config = Libp2pConfig::default();
+        assert!(resolver_config(&config).is_ok());
+
+        config.dns_nameservers = DnsNameservers::Custom;
+        assert!(resolver_config(&config).is_err());
+        config.dns_custom_nameservers = vec![
+            "10.0.0.53:53".parse().unwrap(),
+            "10.0.1.53:5353".parse().unwrap(),
+        ];
+        let (resolver, _) = resolver_config(&config).unwrap();
+        // udp and tcp for each nameserver
+        assert_eq!(resolver.name_servers().len(), 4);
+
+        config.dns_over_https = true;
+        assert!(resolver_config(&config).is_err());
+        config.dns_tls_name = Some("dns.internal".to_string());
+        let (resolver, _) = resolver_config(&config).unwrap();
+        assert_eq!(resolver.name_servers().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_tld_resolver() {
+        let inner = DummyTransport::<()>::new().boxed();
+        let transport = TldDnsTransport::new(inner, &DnsResolverConfig::default()).unwrap();
+
+        let addr = |s: &str| s.parse::<Multiaddr>().unwrap();
+        assert!(transport
+            .resolver(&addr("/dns4/vitalik.eth/tcp/4001"))
+            .is_some());
+        assert!(transport.resolver(&addr("/dnsaddr/ipfs.eth")).is_some());
+        assert!(transport
+            .resolver(&addr("/dnsaddr/bootstrap.libp2p.io"))
+            .is_none());
+        assert!(transport
+            .resolver(&addr("/ip4/127.0.0.1/tcp/4001"))
+            .is_none());
+    }
+
+    /// Serves a nameserver on localhost, which answers every A query with `ip`.
+    async fn serve_nameserver(ip: std::net::Ipv4Addr) -> std::net::SocketAddr {
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::task::spawn(async move {
+            let mut buf = [0u8; 512];
+            loop {
+                let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+                // skip the labels of the question name, then its type and class
+                let mut end = 12;
+                while end < len && buf[end] != 0 {
+                    end += buf[end] as usize + 1;
+                }
+                end += 5;
+                if end > len {
+                    continue;
+                }
+                let is_a = buf[end - 4..end - 2] == [0, 1];
+
+                let mut response = buf[..2].to_vec();
+                // a recursive answer, with one question and an answer for A queries
+                response.extend_from_slice(&[0x81, 0x80, 0, 1, 0, is_a as u8, 0, 0, 0, 0]);
+                response.extend_from_slice(&buf[12..end]);
+                if is_a {
+                    // the name points to the question, class IN, a ttl of 60s
+                    response.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4]);
+                    response.extend_from_slice(&ip.octets());
+                }
+                socket.send_to(&response, from).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_resolve_with_configured_nameserver() {
+        let nameserver = serve_nameserver([10, 1, 2, 3].into()).await;
+        let addr = |s: &str| s.parse::<Multiaddr>().unwrap();
+
+        // the custom nameservers of the default resolver
+        let mut config = Libp2pConfig {
+            dns_nameservers: DnsNameservers::Custom,
+            dns_custom_nameservers: vec![nameserver],
+            ..Default::default()
+        };
+        let (resolver_config, opts) = resolver_config(&config).unwrap();
+        let resolver = TokioAsyncResolver::tokio(resolver_config.clone(), opts).unwrap();
+        let resolved = resolve(&resolver, &addr("/dns4/peer.example/tcp/4001"))
+            .await
+            .unwrap();
+        assert_eq!(resolved, vec![addr("/ip4/10.1.2.3/tcp/4001")]);
+
+        // a separate resolver for a TLD
+        config.dns_resolver = DnsResolverConfig::empty();
+        config
+            .dns_resolver
+            .set_tld_resolver("test", resolver_config);
+        let inner = DummyTransport::<()>::new().boxed();
+        let transport = TldDnsTransport::new(inner, &config.dns_resolver).unwrap();
+        let dialed = addr("/dns4/peer.test/tcp/4001");
+        let resolver = transport.resolver(&dialed).unwrap();
+        let resolved = resolve(resolver, &dialed).await.unwrap();
+        assert_eq!(resolved, vec![addr("/ip4/10.1.2.3/tcp/4001")]);
+        assert!(transport
+            .resolver(&addr("/dns4/peer.example/tcp/4001"))
+            .is_none());
+    }
+}
@@ -0,0 +1,418 @@
+//! IPNS records, as described in <https://specs.ipfs.tech/ipns/ipns-record/>.
+
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use bytes::Bytes;
+use libipld::cbor::DagCborCodec;
+use libipld::prelude::Codec;
+use libipld::Ipld;
+use libp2p::identity::{Keypair, PublicKey};
+use libp2p::kad::record::Key;
+use libp2p::multihash::Multihash;
+use libp2p::PeerId;
+use lru::LruCache;
+use prost::Message;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// How long a published record is valid for, unless specified otherwise.
+pub const DEFAULT_RECORD_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long a resolved record may be cached, unless specified otherwise.
+pub const DEFAULT_RECORD_TTL: Duration = Duration::from_secs(60 * 60);
+/// Records larger than this must be ignored.
+const MAX_RECORD_SIZE: usize = 10 * 1024;
+const MAX_CACHED_RECORDS: usize = 1024;
+
+const IPNS_KEY_PREFIX: &[u8] = b"/ipns/";
+const SIGNATURE_V2_PREFIX: &[u8] = b"ipns-signature:";
+/// The multihash code used for peer ids that inline the public key.
+const IDENTITY_MULTIHASH: u64 = 0x00;
+
+/// The protobuf wire format of an IPNS record.
+#[derive(Clone, PartialEq, prost::Message)]
+struct IpnsEntry {
+    #[prost(bytes = "vec", optional, tag = "1")]
+    value: Option<Vec<u8>>,
+    #[prost(bytes = "vec", optional, tag = "2")]
+    signature_v1: Option<Vec<u8>>,
+    #[prost(enumeration = "ValidityType", optional, tag = "3")]
+    validity_type: Option<i32>,
+    #[prost(bytes = "vec", optional, tag = "4")]
+    validity: Option<Vec<u8>>,
+    #[prost(uint64, optional, tag = "5")]
+    sequence: Option<u64>,
+    #[prost(uint64, optional, tag = "6")]
+    ttl: Option<u64>,
+    #[prost(bytes = "vec", optional, tag = "7")]
+    pub_key: Option<Vec<u8>>,
+    #[prost(bytes = "vec", optional, tag = "8")]
+    signature_v2: Option<Vec<u8>>,
+    #[prost(bytes = "vec", optional, tag = "9")]
+    data: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+enum ValidityType {
+    /// The record is valid until the end of life timestamp in `validity`.
+    Eol = 0,
+}
+
+/// The content of an IPNS record, whose signature has been verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpnsRecord {
+    /// The path the name points to, e.g. `/ipfs/<cid>`.
+    pub value: Bytes,
+    pub sequence: u64,
+    /// The end of life of the record.
+    pub validity: OffsetDateTime,
+    /// How long the record may be cached.
+    pub ttl: Duration,
+}
+
+impl IpnsRecord {
+    /// Creates a record that is valid for `lifetime`, fails if the end of life is not representable.
+    pub fn new(value: Bytes, sequence: u64, lifetime: Duration, ttl: Duration) -> Result<Self> {
+        let validity = time::Duration::try_from(lifetime)
+            .ok()
+            .and_then(|lifetime| OffsetDateTime::now_utc().checked_add(lifetime))
+            .ok_or_else(|| anyhow!("record lifetime of {:?} is too long", lifetime))?;
+        Ok(IpnsRecord {
+            value,
+            sequence,
+            validity,
+            ttl,
+        })
+    }
+
+    /// Decodes a record and verifies that it was signed by the owner of `name`.
+    ///
+    /// Expired records are returned as well, use [`IpnsRecord::is_expired`] to filter them.
+    pub fn from_bytes(name: &PeerId, bytes: &[u8]) -> Result<Self> {
+        ensure!(
+            bytes.len() <= MAX_RECORD_SIZE,
+            "record exceeds the maximum size of {} bytes",
+            MAX_RECORD_SIZE
+        );
+        let entry = IpnsEntry::decode(bytes).context("invalid ipns record")?;
+        let public_key = public_key(name, &entry)?;
+
+        // only V2 signatures are accepted, V1 signatures do not cover all fields
+        let signature = entry
+            .signature_v2
+            .as_ref()
+            .context("record is missing the V2 signature")?;
+        let data = entry.data.as_ref().context("record is missing data")?;
+        ensure!(
+            public_key.verify(&[SIGNATURE_V2_PREFIX, data].concat(), signature),
+            "invalid record signature"
+        );
+        let record = Self::from_cbor(data)?;
+
+        // the protobuf fields are not covered by the signature, so they have to match the data
+        if let Some(ref value) = entry.value {
+            ensure!(value[..] == record.value[..], "record value mismatch");
+        }
+        if let Some(sequence) = entry.sequence {
+            ensure!(sequence == record.sequence, "record sequence mismatch");
+        }
+
+        Ok(record)
+    }
+
+    /// Encodes the record and signs it with the given key.
+    pub fn sign(&self, keypair: &Keypair) -> Result<Vec<u8>> {
+        let validity = self.validity.format(&Rfc3339)?.into_bytes();
+        let ttl = u64::try_from(self.ttl.as_nanos()).unwrap_or(u64::MAX);
+        let data = DagCborCodec.encode(&Ipld::Map(BTreeMap::from([
+            ("Value".to_string(), Ipld::Bytes(self.value.to_vec())),
+            ("Validity".to_string(), Ipld::Bytes(validity.clone())),
+            (
+                "ValidityType".to_string(),
+                Ipld::Integer(ValidityType::Eol as i128),
+            ),
+            ("Sequence".to_string(), Ipld::Integer(self.sequence.into())),
+            ("TTL".to_string(), Ipld::Integer(ttl.into())),
+        ])))?;
+
+        let signature_v1 = keypair.sign(&[&self.value[..], &validity, b"EOL"].concat())?;
+        let signature_v2 = keypair.sign(&[SIGNATURE_V2_PREFIX, &data].concat())?;
+
+        // keys that are not inlined in the peer id have to be shipped with the record
+        let public_key = keypair.public();
+        let pub_key = if is_inlined(&public_key.to_peer_id()) {
+            None
+        } else {
+            Some(public_key.to_protobuf_encoding())
+        };
+
+        let entry = IpnsEntry {
+            value: Some(self.value.to_vec()),
+            signature_v1: Some(signature_v1),
+            validity_type: Some(ValidityType::Eol as i32),
+            validity: Some(validity),
+            sequence: Some(self.sequence),
+            ttl: Some(ttl),
+            pub_key,
+            signature_v2: Some(signature_v2),
+            data: Some(data),
+        };
+        let bytes = entry.encode_to_vec();
+        ensure!(
+            bytes.len() <= MAX_RECORD_SIZE,
+            "record exceeds the maximum size of {} bytes",
+            MAX_RECORD_SIZE
+        );
+
+        Ok(bytes)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.validity <= OffsetDateTime::now_utc()
+    }
+
+    /// Returns `true` if this record supersedes `other`.
+    pub fn is_newer_than(&self, other: &IpnsRecord) -> bool {
+        (self.sequence, self.validity) > (other.sequence, other.validity)
+    }
+
+    /// How long the record can be used before it has to be resolved again.
+    fn cache_duration(&self) -> Duration {
+        let remaining = self.validity - OffsetDateTime::now_utc();
+        let remaining = Duration::try_from(remaining).unwrap_or_default();
+        remaining.min(self.ttl)
+    }
+
+    fn from_cbor(data: &[u8]) -> Result<Self> {
+        let map = match DagCborCodec.decode::<Ipld>(data)? {
+            Ipld::Map(map) => map,
+            _ => bail!("record data is not a map"),
+        };
+        let field = |name: &str| {
+            map.get(name)
+                .ok_or_else(|| anyhow!("record data is missing the {} field", name))
+        };
+
+        let value = match field("Value")? {
+            Ipld::Bytes(value) => Bytes::from(value.clone()),
+            _ => bail!("invalid record value"),
+        };
+        match field("ValidityType")? {
+            Ipld::Integer(t) if *t == ValidityType::Eol as i128 => {}
+            _ => bail!("unsupported record validity type"),
+        }
+        let validity = match field("Validity")? {
+            Ipld::Bytes(validity) => {
+                let validity = std::str::from_utf8(validity)?;
+                OffsetDateTime::parse(validity, &Rfc3339)
+                    .with_context(|| format!("invalid record validity: {validity}"))?
+            }
+            _ => bail!("invalid record validity"),
+        };
+        let sequence = match field("Sequence")? {
+            Ipld::Integer(sequence) => u64::try_from(*sequence)?,
+            _ => bail!("invalid record sequence"),
+        };
+        let ttl = match field("TTL")? {
+            Ipld::Integer(ttl) => Duration::from_nanos(u64::try_from(*ttl)?),
+            _ => bail!("invalid record ttl"),
+        };
+
+        Ok(IpnsRecord {
+            value,
+            sequence,
+            validity,
+            ttl,
+        })
+    }
+}
+
+/// The DHT key under which the record for `name` is stored.
+pub fn record_key(name: &PeerId) -> Key {
+    Key::from([IPNS_KEY_PREFIX, &name.to_bytes()].concat())
+}
+
+/// The name whose record is stored under `key`.
+pub fn name_from_key(key: &Key) -> Result<PeerId> {
+    let name = key
+        .as_ref()
+        .strip_prefix(IPNS_KEY_PREFIX)
+        .context("not an ipns record key")?;
+    PeerId::from_bytes(name).context("invalid ipns name")
+}
+
+fn is_inlined(peer_id: &PeerId) -> bool {
+    Multihash::from(*peer_id).code() == IDENTITY_MULTIHASH
+}
+
+/// Finds the public key for `name`, either inlined in the peer id or embedded in the record.
+fn public_key(name: &PeerId, entry: &IpnsEntry) -> Result<PublicKey> {
+    let multihash = Multihash::from(*name);
+    if multihash.code() == IDENTITY_MULTIHASH {
+        return PublicKey::from_protobuf_encoding(multihash.digest())
+            .context("invalid public key in name");
+    }
+
+    let public_key = entry
+        .pub_key
+        .as_ref()
+        .context("record is missing the public key")?;
+    let public_key =
+        PublicKey::from_protobuf_encoding(public_key).context("invalid public key in record")?;
+    ensure!(
+        public_key.to_peer_id() == *name,
+        "record public key does not match the name"
+    );
+    Ok(public_key)
+}
+
+/// Keeps resolved records around until their TTL runs out.
+#[derive(Debug)]
+pub struct IpnsCache {
+    records: LruCache<PeerId, (IpnsRecord, Instant)>,
+}
+
+impl Default for IpnsCache {
+    fn default() -> Self {
+        IpnsCache {
+            records: LruCache::new(NonZeroUsize::new(MAX_CACHED_RECORDS).unwrap()),
+        }
+    }
+}
+
+impl IpnsCache {
+    pub fn get(&mut self, name: &PeerId) -> Option<&IpnsRecord> {
+        let expired = matches!(self.records.peek(name), Some((_, valid_until)) if *valid_until <= Instant::now());
+        if expired {
+            self.records.pop(name);
+            return None;
+        }
+        self.records.get(name).map(|(record, _)| record)
+    }
+
+    /// Caches the record, unless an entry with a newer record exists.
+    pub fn put(&mut self, name: PeerId, record: IpnsRecord) {
+        if let Some((cached, _)) = self.records.peek(&name) {
+            if cached.is_newer_than(&record) {
+                return;
+            }
+        }
+        match Instant::now().checked_add(record.cache_duration()) {
+            Some(valid_until) => {
+                self.records.put(name, (record, valid_until));
+            }
+            None => {
+                self.records.pop(&name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(sequence: u64) -> IpnsRecord {
+        IpnsRecord::new(
+            Bytes::from_static(
+                b"/ipfs/bafkreigh2akiscaildcqabsyg3dfr6chu3fgpregiymsck7e7aqa4s52zy",
+            ),
+            sequence,
+            DEFAULT_RECORD_LIFETIME,
+            DEFAULT_RECORD_TTL,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn sign_and_verify_ed25519() {
+        let keypair = Keypair::generate_ed25519();
+        let name = keypair.public().to_peer_id();
+        let record = record(3);
+
+        let bytes = record.sign(&keypair).unwrap();
+        let decoded = IpnsRecord::from_bytes(&name, &bytes).unwrap();
+        assert_eq!(decoded.value, record.value);
+        assert_eq!(decoded.sequence, 3);
+        assert_eq!(decoded.ttl, DEFAULT_RECORD_TTL);
+        assert!(!decoded.is_expired());
+    }
+
+    #[test]
+    fn reject_wrong_name() {
+        let keypair = Keypair::generate_ed25519();
+        let other = Keypair::generate_ed25519().public().to_peer_id();
+
+        let bytes = record(0).sign(&keypair).unwrap();
+        assert!(IpnsRecord::from_bytes(&other, &bytes).is_err());
+    }
+
+    #[test]
+    fn reject_tampered_value() {
+        let keypair = Keypair::generate_ed25519();
+        let name = keypair.public().to_peer_id();
+
+        let bytes = record(0).sign(&keypair).unwrap();
+        let mut entry = IpnsEntry::decode(&bytes[..]).unwrap();
+        entry.value = Some(b"/ipfs/QmSomethingElse".to_vec());
+        assert!(IpnsRecord::from_bytes(&name, &entry.encode_to_vec()).is_err());
+
+        let mut entry = IpnsEntry::decode(&bytes[..]).unwrap();
+        entry.data.as_mut().unwrap()[4] ^= 0xff;
+        assert!(IpnsRecord::from_bytes(&name, &entry.encode_to_vec()).is_err());
+    }
+
+    #[test]
+    fn expired_records() {
+        let keypair = Keypair::generate_ed25519();
+        let name = keypair.public().to_peer_id();
+        let mut record = record(0);
+        record.validity = OffsetDateTime::now_utc() - Duration::from_secs(1);
+
+        let bytes = record.sign(&keypair).unwrap();
+        let decoded = IpnsRecord::from_bytes(&name, &bytes).unwrap();
+        assert!(decoded.is_expired());
+        assert_eq!(decoded.cache_duration(), Duration::ZERO);
+    }
+
+    #[test]
+    fn reject_overflowing_lifetime() {
+        let value = Bytes::from_static(b"/ipfs/QmSomething");
+        assert!(IpnsRecord::new(value.clone(), 0, Duration::MAX, DEFAULT_RECORD_TTL).is_err());
+        // the end of life has to be representable as a date
+        let lifetime = Duration::from_secs(20_000 * 365 * 24 * 60 * 60);
+        assert!(IpnsRecord::new(value, 0, lifetime, DEFAULT_RECORD_TTL).is_err());
+    }
+
+    #[test]
+    fn newer_records() {
+        let older = record(1);
+        let newer = record(2);
+        assert!(newer.is_newer_than(&older));
+        assert!(!older.is_newer_than(&newer));
+
+        let mut longer = older.clone();
+        longer.validity += Duration::from_secs(60);
+        assert!(longer.is_newer_than(&older));
+    }
+
+    #[test]
+    fn cache_keeps_newest_record() {
+        let name = Keypair::generate_ed25519().public().to_peer_id();
+        let mut cache = IpnsCache::default();
+
+        cache.put(name, record(2));
+        cache.put(name, record(1));
+        assert_eq!(cache.get(&name).unwrap().sequence, 2);
+
+        cache.put(name, record(3));
+        assert_eq!(cache.get(&name).unwrap().sequence, 3);
+
+        let mut uncacheable = record(4);
+        uncacheable.ttl = Duration::ZERO;
+        cache.put(name, uncacheable);
+        assert!(cache.get(&name).is_none());
+    }
+}
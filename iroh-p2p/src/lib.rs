@@ -1,15 +1,30 @@
+mod bandwidth;
 mod behaviour;
 pub mod cli;
 pub mod config;
+mod dns;
+mod ipns;
 mod keys;
 pub mod metrics;
 mod node;
+mod peering;
 mod providers;
+mod pubsub;
+mod record;
+mod relays;
+mod reprovider;
 pub mod rpc;
 mod swarm;
 
 pub use self::config::*;
-pub use self::keys::{DiskStorage, Keychain, MemoryStorage};
+pub use self::keys::{DiskStorage, Keychain, MemoryStorage, Passphrase};
 pub use self::node::*;
+pub use self::pubsub::{
+    GossipsubMessage, GossipsubValidator, GossipsubValidators, MessageAcceptance, TopicScoreParams,
+};
+pub use self::record::{
+    open_signed_record, sign_record, signed_record_key, RecordKey, RecordValidator,
+    RecordValidators,
+};
 
 pub(crate) const VERSION: &str = env!("CARGO_PKG_VERSION");
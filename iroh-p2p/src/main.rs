@@ -1,8 +1,9 @@
 use anyhow::{anyhow, Context, Result};
 use clap::Parser;
+use iroh_p2p::cli::{Args, Command};
 use iroh_p2p::config::{Config, CONFIG_FILE_NAME, ENV_PREFIX};
 use iroh_p2p::ServerConfig;
-use iroh_p2p::{cli::Args, metrics, DiskStorage, Keychain, Node};
+use iroh_p2p::{metrics, DiskStorage, Keychain, Node};
 use iroh_util::lock::ProgramLock;
 use iroh_util::{iroh_config_path, make_config};
 use tokio::task;
@@ -41,6 +42,25 @@ fn main() -> Result<()> {
         )
         .context("invalid config")?;
 
+        if let Some(Command::EncryptKeys) = args.command {
+            let network_config = Config::from(network_config);
+            let passphrase = network_config.key_passphrase()?.context(
+                "no key passphrase configured, set key_passphrase_env or key_passphrase_path",
+            )?;
+            let mut kc = Keychain::<DiskStorage>::with_passphrase(
+                network_config.key_store_path.clone(),
+                passphrase,
+            )
+            .await?;
+            let count = kc.encrypt_keys().await?;
+            println!(
+                "Encrypted {} keys in {}",
+                count,
+                network_config.key_store_path.display()
+            );
+            return Ok(());
+        }
+
         let metrics_config =
             metrics::metrics_config_with_compile_time_info(network_config.metrics.clone());
 
@@ -57,7 +77,16 @@ fn main() -> Result<()> {
         }
 
         let network_config = Config::from(network_config);
-        let kc = Keychain::<DiskStorage>::new(network_config.key_store_path.clone()).await?;
+        let kc = match network_config.key_passphrase()? {
+            Some(passphrase) => {
+                Keychain::<DiskStorage>::with_passphrase(
+                    network_config.key_store_path.clone(),
+                    passphrase,
+                )
+                .await?
+            }
+            None => Keychain::<DiskStorage>::new(network_config.key_store_path.clone()).await?,
+        };
         let rpc_addr = network_config
             .rpc_addr()
             .ok_or_else(|| anyhow!("missing p2p rpc addr"))?;
@@ -0,0 +1,218 @@
+//! Peering: connections to the configured `peering` peers, which the node keeps open.
+//!
+//! Like `Peering.Peers` in go-ipfs, a peer that disconnects is redialed, with a backoff
+//! that doubles after every failed dial. The peer manager keeps the connections alive and
+//! never trims them. The addresses of a peer configured as a bare `/p2p/<peer id>` are
+//! looked up in the DHT before every dial.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
+use libp2p::{Multiaddr, PeerId, Swarm};
+use tracing::{debug, info, warn};
+
+use crate::behaviour::NodeBehaviour;
+use crate::config::addr_peer_id;
+use crate::Libp2pConfig;
+
+/// Time before a peer that disconnected is redialed.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+/// Maximum time between two dials of a peer.
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug)]
+pub(crate) struct Peering {
+    peers: HashMap<PeerId, PeeringPeer>,
+}
+
+#[derive(Debug)]
+struct PeeringPeer {
+    /// Known addresses of the peer, without the `/p2p/<peer id>` suffix.
+    addrs: Vec<Multiaddr>,
+    connected: bool,
+    dialing: bool,
+    /// Time until the next dial after a failure.
+    backoff: Duration,
+    next_dial: Instant,
+}
+
+impl Peering {
+    pub(crate) fn new(config: &Libp2pConfig) -> Result<Self> {
+        let mut peers: HashMap<PeerId, PeeringPeer> = HashMap::new();
+        let now = Instant::now();
+        for (peer_id, addr) in peering_addrs(config)? {
+            let peer = peers.entry(peer_id).or_insert_with(|| PeeringPeer {
+                addrs: Vec::new(),
+                connected: false,
+                dialing: false,
+                backoff: INITIAL_BACKOFF,
+                next_dial: now,
+            });
+            if let Some(addr) = addr {
+                peer.addrs.push(addr);
+            }
+        }
+        Ok(Peering { peers })
+    }
+
+    /// Dials the peering peers that are disconnected and not backing off.
+    pub(crate) fn refresh(&mut self, swarm: &mut Swarm<NodeBehaviour>) {
+        for peer_id in self.due(Instant::now()) {
+            let peer = self.peers.get_mut(&peer_id).expect("due peer");
+            if peer.addrs.is_empty() {
+                // the dial uses the addresses Kademlia found, until the lookup completes
+                // it fails and backs off
+                if let Some(kad) = swarm.behaviour_mut().kad.as_mut() {
+                    debug!("peering: looking up {} in the DHT", peer_id);
+                    kad.get_closest_peers(peer_id);
+                }
+            }
+            let opts = DialOpts::peer_id(peer_id)
+                .addresses(peer.addrs.clone())
+                .extend_addresses_through_behaviour()
+                .condition(PeerCondition::Disconnected)
+                .build();
+            debug!("peering: dialing {}", peer_id);
+            match swarm.dial(opts) {
+                Ok(()) => peer.dialing = true,
+                Err(err) => {
+                    warn!("peering: failed to dial {}: {:?}", peer_id, err);
+                    self.on_dial_failure(peer_id);
+                }
+            }
+        }
+    }
+
+    /// Returns the peers to dial.
+    fn due(&self, now: Instant) -> Vec<PeerId> {
+        self.peers
+            .iter()
+            .filter(|(_, peer)| !peer.connected && !peer.dialing && peer.next_dial <= now)
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+
+    pub(crate) fn on_connected(&mut self, peer_id: PeerId) {
+        if let Some(peer) = self.peers.get_mut(&peer_id) {
+            info!("peering: connected to {}", peer_id);
+            peer.connected = true;
+            peer.dialing = false;
+            peer.backoff = INITIAL_BACKOFF;
+        }
+    }
+
+    /// Schedules a dial of the peer once its last connection closed.
+    pub(crate) fn on_disconnected(&mut self, peer_id: PeerId) {
+        if let Some(peer) = self.peers.get_mut(&peer_id) {
+            info!("peering: lost the connection to {}", peer_id);
+            peer.connected = false;
+            peer.next_dial = Instant::now() + peer.backoff;
+        }
+    }
+
+    pub(crate) fn on_dial_failure(&mut self, peer_id: PeerId) {
+        if let Some(peer) = self.peers.get_mut(&peer_id) {
+            if peer.connected {
+                // another connection to the peer is open
+                return;
+            }
+            debug!(
+                "peering: dialing {} failed, retrying in {:?}",
+                peer_id, peer.backoff
+            );
+            peer.dialing = false;
+            peer.next_dial = Instant::now() + peer.backoff;
+            peer.backoff = (peer.backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
+
+/// Returns the peers of the `peering` addresses, with their address if it is not only
+/// `/p2p/<peer id>`.
+fn peering_addrs(config: &Libp2pConfig) -> Result<Vec<(PeerId, Option<Multiaddr>)>> {
+    config
+        .peering
+        .iter()
+        .map(|addr| {
+            let peer_id = addr_peer_id(addr)?;
+            let mut addr = addr.clone();
+            addr.pop();
+            let addr = (!addr.is_empty()).then_some(addr);
+            Ok((peer_id, addr))
+        })
+        .collect()
+}
+
+/// Returns the peer ids of the `peering` peers, skipping invalid addresses.
+pub(crate) fn peering_peers(config: &Libp2pConfig) -> impl Iterator<Item = PeerId> + '_ {
+    config
+        .peering
+        .iter()
+        .filter_map(|addr| addr_peer_id(addr).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peering_addrs() {
+        let peer_id = PeerId::random();
+        let config = Libp2pConfig {
+            peering: vec![
+                format!("/ip4/10.0.0.1/tcp/4001/p2p/{peer_id}")
+                    .parse()
+                    .unwrap(),
+                format!("/p2p/{peer_id}").parse().unwrap(),
+            ],
+            ..Default::default()
+        };
+        let peering = Peering::new(&config).unwrap();
+        assert_eq!(peering.peers.len(), 1);
+        assert_eq!(
+            peering.peers[&peer_id].addrs,
+            vec!["/ip4/10.0.0.1/tcp/4001".parse::<Multiaddr>().unwrap()]
+        );
+        assert_eq!(peering_peers(&config).collect::<Vec<_>>(), vec![peer_id; 2]);
+
+        let config = Libp2pConfig {
+            peering: vec!["/ip4/10.0.0.1/tcp/4001".parse().unwrap()],
+            ..Default::default()
+        };
+        assert!(Peering::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let peer_id = PeerId::random();
+        let config = Libp2pConfig {
+            peering: vec![format!("/p2p/{peer_id}").parse().unwrap()],
+            ..Default::default()
+        };
+        let mut peering = Peering::new(&config).unwrap();
+        let now = Instant::now();
+        assert_eq!(peering.due(now), vec![peer_id]);
+
+        // every failed dial doubles the backoff
+        peering.peers.get_mut(&peer_id).unwrap().dialing = true;
+        assert!(peering.due(now).is_empty());
+        peering.on_dial_failure(peer_id);
+        peering.on_dial_failure(peer_id);
+        assert_eq!(peering.peers[&peer_id].backoff, INITIAL_BACKOFF * 4);
+        assert!(peering.due(Instant::now()).is_empty());
+        assert_eq!(
+            peering.due(Instant::now() + INITIAL_BACKOFF * 2),
+            vec![peer_id]
+        );
+
+        // connecting resets it
+        peering.on_connected(peer_id);
+        assert!(peering.due(Instant::now() + MAX_BACKOFF).is_empty());
+        assert_eq!(peering.peers[&peer_id].backoff, INITIAL_BACKOFF);
+        peering.on_disconnected(peer_id);
+        assert!(peering.due(Instant::now()).is_empty());
+        assert_eq!(peering.due(Instant::now() + INITIAL_BACKOFF), vec![peer_id]);
+    }
+}
@@ -0,0 +1,391 @@
+//! Application level validation of gossipsub messages.
+//!
+//! Gossipsub only forwards a received message once a validator accepted it. Every topic can
+//! have a validator, either registered on the node or by an rpc client, messages on topics
+//! without one are accepted. With peer scoring enabled, rejected messages lower the score of
+//! the peer that delivered them.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use libp2p::gossipsub::{MessageId, PeerScoreParams, PeerScoreThresholds, TopicHash};
+use libp2p::PeerId;
+use tokio::sync::mpsc::{error::TrySendError, Sender};
+use tracing::debug;
+
+use crate::Libp2pConfig;
+
+pub use libp2p::gossipsub::{GossipsubMessage, MessageAcceptance, TopicScoreParams};
+
+/// Maximum number of messages waiting for the verdict of an rpc validator, further messages
+/// are ignored.
+const MAX_PENDING_MESSAGES: usize = 1024;
+/// How long an rpc validator may take to decide on a message, it is ignored afterwards.
+const RPC_VALIDATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Decides whether the gossipsub messages of a topic are delivered and forwarded.
+pub trait GossipsubValidator: Send + Sync + 'static {
+    /// Validates `message`, which was received from `propagation_source`.
+    fn validate(
+        &self,
+        propagation_source: &PeerId,
+        message: &GossipsubMessage,
+    ) -> MessageAcceptance;
+}
+
+impl<F> GossipsubValidator for F
+where
+    F: Fn(&PeerId, &GossipsubMessage) -> MessageAcceptance + Send + Sync + 'static,
+{
+    fn validate(
+        &self,
+        propagation_source: &PeerId,
+        message: &GossipsubMessage,
+    ) -> MessageAcceptance {
+        self(propagation_source, message)
+    }
+}
+
+/// The validators of the gossipsub topics, along with how peers are scored on them.
+#[derive(Clone, Default)]
+pub struct GossipsubValidators {
+    validators: HashMap<TopicHash, Arc<dyn GossipsubValidator>>,
+    topic_params: HashMap<TopicHash, TopicScoreParams>,
+}
+
+impl fmt::Debug for GossipsubValidators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GossipsubValidators")
+            .field("validators", &self.validators.keys())
+            .field("topic_params", &self.topic_params)
+            .finish()
+    }
+}
+
+impl GossipsubValidators {
+    /// Sets the validator of a topic, replacing the previous one.
+    pub fn insert(&mut self, topic: impl Into<String>, validator: impl GossipsubValidator) {
+        self.validators
+            .insert(TopicHash::from_raw(topic), Arc::new(validator));
+    }
+
+    /// Sets the peer scoring parameters of a topic, instead of those derived from the
+    /// `gossipsub_*` fields of [`Libp2pConfig`].
+    ///
+    /// Only used with `gossipsub_peer_scoring` enabled.
+    pub fn set_topic_params(&mut self, topic: impl Into<String>, params: TopicScoreParams) {
+        self.topic_params.insert(TopicHash::from_raw(topic), params);
+    }
+
+    fn get(&self, topic: &TopicHash) -> Option<&dyn GossipsubValidator> {
+        self.validators.get(topic).map(|v| v.as_ref())
+    }
+}
+
+/// A received gossipsub message.
+#[derive(Debug, Clone)]
+pub(crate) struct ReceivedMessage {
+    pub(crate) propagation_source: PeerId,
+    pub(crate) id: MessageId,
+    pub(crate) message: GossipsubMessage,
+}
+
+/// A message awaiting the verdict of an rpc validator.
+#[derive(Debug)]
+struct PendingMessage {
+    message: ReceivedMessage,
+    deadline: Instant,
+}
+
+/// Validation of the received messages, by the validators of the node and those of rpc
+/// clients.
+#[derive(Debug, Default)]
+pub(crate) struct MessageValidation {
+    validators: GossipsubValidators,
+    rpc_validators: HashMap<TopicHash, Sender<ReceivedMessage>>,
+    /// The messages an rpc validator has not decided on yet.
+    pending: HashMap<MessageId, PendingMessage>,
+    /// The scoring parameters of the topics without their own, `None` without peer scoring.
+    default_topic_params: Option<TopicScoreParams>,
+}
+
+impl MessageValidation {
+    pub(crate) fn new(config: &Libp2pConfig) -> Self {
+        MessageValidation {
+            default_topic_params: topic_score_params(config),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn validators_mut(&mut self) -> &mut GossipsubValidators {
+        &mut self.validators
+    }
+
+    /// Returns the scoring parameters of a topic, `None` without peer scoring.
+    pub(crate) fn topic_params(&self, topic: &TopicHash) -> Option<&TopicScoreParams> {
+        let default = self.default_topic_params.as_ref()?;
+        Some(self.validators.topic_params.get(topic).unwrap_or(default))
+    }
+
+    /// Returns the topics with scoring parameters of their own, `None` without peer scoring.
+    pub(crate) fn own_topic_params(
+        &self,
+    ) -> Option<impl Iterator<Item = (&TopicHash, &TopicScoreParams)>> {
+        self.default_topic_params.as_ref()?;
+        Some(self.validators.topic_params.iter())
+    }
+
+    /// Sends the messages of the topic to an rpc client for validation, until the receiver
+    /// is dropped.
+    pub(crate) fn add_rpc_validator(
+        &mut self,
+        topic: TopicHash,
+        sender: Sender<ReceivedMessage>,
+    ) -> Result<()> {
+        if let Some(existing) = self.rpc_validators.get(&topic) {
+            if !existing.is_closed() {
+                bail!("topic {} already has a validator", topic);
+            }
+            self.remove_rpc_validator(&topic);
+        }
+        self.rpc_validators.insert(topic, sender);
+        Ok(())
+    }
+
+    fn remove_rpc_validator(&mut self, topic: &TopicHash) {
+        self.rpc_validators.remove(topic);
+        // without a validator the messages are never forwarded, as if they were ignored
+        self.pending
+            .retain(|_, m| m.message.message.topic != *topic);
+    }
+
+    /// Returns the verdict on a received message, or `None` when it awaits the verdict of an
+    /// rpc validator, see [`MessageValidation::report`].
+    pub(crate) fn validate(
+        &mut self,
+        message: ReceivedMessage,
+    ) -> Option<(ReceivedMessage, MessageAcceptance)> {
+        let topic = message.message.topic.clone();
+        if let Some(sender) = self.rpc_validators.get(&topic) {
+            if self.pending.len() >= MAX_PENDING_MESSAGES {
+                debug!(
+                    "too many messages awaiting validation, ignoring {}",
+                    message.id
+                );
+                return Some((message, MessageAcceptance::Ignore));
+            }
+            match sender.try_send(message.clone()) {
+                Ok(()) => {
+                    let deadline = Instant::now() + RPC_VALIDATION_TIMEOUT;
+                    self.pending
+                        .insert(message.id.clone(), PendingMessage { message, deadline });
+                    return None;
+                }
+                Err(TrySendError::Full(_)) => {
+                    debug!("validator of {} is lagging, ignoring {}", topic, message.id);
+                    return Some((message, MessageAcceptance::Ignore));
+                }
+                Err(TrySendError::Closed(_)) => self.remove_rpc_validator(&topic),
+            }
+        }
+
+        let acceptance = match self.validators.get(&topic) {
+            Some(validator) => validator.validate(&message.propagation_source, &message.message),
+            None => MessageAcceptance::Accept,
+        };
+        Some((message, acceptance))
+    }
+
+    /// Returns the message an rpc validator decided on.
+    pub(crate) fn report(&mut self, id: &MessageId) -> Result<ReceivedMessage> {
+        match self.pending.remove(id) {
+            Some(pending) => Ok(pending.message),
+            None => bail!("no message {} awaits validation", id),
+        }
+    }
+
+    /// Removes the messages rpc validators did not decide on in time, they are ignored.
+    pub(crate) fn expire(&mut self, now: Instant) -> Vec<ReceivedMessage> {
+        let expired: Vec<MessageId> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.deadline <= now)
+            .map(|(id, _)| id.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|id| self.pending.remove(&id))
+            .map(|pending| {
+                debug!(
+                    "the validator of {} timed out, ignoring {}",
+                    pending.message.message.topic, pending.message.id
+                );
+                pending.message
+            })
+            .collect()
+    }
+}
+
+/// Returns the gossipsub peer scoring parameters, if scoring is enabled.
+pub(crate) fn peer_score_params(
+    config: &Libp2pConfig,
+) -> Option<(PeerScoreParams, PeerScoreThresholds)> {
+    if !config.gossipsub_peer_scoring {
+        return None;
+    }
+    let thresholds = PeerScoreThresholds {
+        gossip_threshold: config.gossipsub_gossip_threshold.get(),
+        publish_threshold: config.gossipsub_publish_threshold.get(),
+        graylist_threshold: config.gossipsub_graylist_threshold.get(),
+        ..Default::default()
+    };
+    Some((PeerScoreParams::default(), thresholds))
+}
+
+/// Returns the scoring parameters of the subscribed topics, if scoring is enabled.
+///
+/// Peers are only penalized for invalid messages, not for delivering too few messages, as
+/// most topics have too little traffic for that.
+fn topic_score_params(config: &Libp2pConfig) -> Option<TopicScoreParams> {
+    if !config.gossipsub_peer_scoring {
+        return None;
+    }
+    Some(TopicScoreParams {
+        invalid_message_deliveries_weight: config.gossipsub_invalid_message_weight.get(),
+        invalid_message_deliveries_decay: config.gossipsub_invalid_message_decay.get(),
+        mesh_message_deliveries_weight: 0.0,
+        mesh_failure_penalty_weight: 0.0,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::channel;
+
+    use super::*;
+
+    fn received(topic: &str, data: &[u8]) -> ReceivedMessage {
+        let message = GossipsubMessage {
+            source: None,
+            data: data.to_vec(),
+            sequence_number: None,
+            topic: TopicHash::from_raw(topic),
+        };
+        ReceivedMessage {
+            propagation_source: PeerId::random(),
+            id: MessageId::new(data),
+            message,
+        }
+    }
+
+    #[test]
+    fn test_validators() {
+        let mut validation = MessageValidation::default();
+        validation
+            .validators_mut()
+            .insert("test", |_: &PeerId, message: &GossipsubMessage| {
+                if message.data.is_empty() {
+                    MessageAcceptance::Reject
+                } else {
+                    MessageAcceptance::Accept
+                }
+            });
+
+        let mut verdict = |topic, data| {
+            validation
+                .validate(received(topic, data))
+                .map(|(_, acceptance)| acceptance)
+        };
+        assert!(matches!(
+            verdict("test", b"hello"),
+            Some(MessageAcceptance::Accept)
+        ));
+        assert!(matches!(
+            verdict("test", b""),
+            Some(MessageAcceptance::Reject)
+        ));
+        // topics without a validator accept everything
+        assert!(matches!(
+            verdict("other", b""),
+            Some(MessageAcceptance::Accept)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rpc_validators() {
+        let mut validation = MessageValidation::default();
+        let (s, mut r) = channel(8);
+        validation
+            .add_rpc_validator(TopicHash::from_raw("test"), s.clone())
+            .unwrap();
+        // only one validator per topic
+        assert!(validation
+            .add_rpc_validator(TopicHash::from_raw("test"), s)
+            .is_err());
+
+        assert!(validation.validate(received("test", b"hello")).is_none());
+        let pending = r.recv().await.unwrap();
+        assert_eq!(pending.message.data, b"hello");
+        let message = validation.report(&pending.id).unwrap();
+        assert_eq!(message.message.data, b"hello");
+        assert!(validation.report(&pending.id).is_err());
+
+        // the validator is removed once the client goes away
+        assert!(validation.validate(received("test", b"world")).is_none());
+        drop(r);
+        assert!(validation.validate(received("test", b"again")).is_some());
+        assert!(validation.report(&MessageId::new(b"world")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rpc_validation_timeout() {
+        let mut validation = MessageValidation::default();
+        let (s, _r) = channel(8);
+        validation
+            .add_rpc_validator(TopicHash::from_raw("test"), s)
+            .unwrap();
+
+        assert!(validation.validate(received("test", b"hello")).is_none());
+        assert!(validation.expire(Instant::now()).is_empty());
+        let expired = validation.expire(Instant::now() + RPC_VALIDATION_TIMEOUT);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].message.data, b"hello");
+        // too late to report
+        assert!(validation.report(&expired[0].id).is_err());
+    }
+
+    #[test]
+    fn test_topic_params() {
+        let mut config = Libp2pConfig::default();
+        let params = TopicScoreParams {
+            topic_weight: 2.0,
+            ..Default::default()
+        };
+        let mut validation = MessageValidation::new(&config);
+        validation
+            .validators_mut()
+            .set_topic_params("own", params.clone());
+        // no scoring by default
+        assert!(validation
+            .topic_params(&TopicHash::from_raw("own"))
+            .is_none());
+
+        config.gossipsub_peer_scoring = true;
+        let mut validation = MessageValidation::new(&config);
+        validation.validators_mut().set_topic_params("own", params);
+        let topic_weight = |topic| {
+            validation
+                .topic_params(&TopicHash::from_raw(topic))
+                .map(|params| params.topic_weight)
+        };
+        assert_eq!(topic_weight("own"), Some(2.0));
+        assert_eq!(
+            topic_weight("other"),
+            Some(TopicScoreParams::default().topic_weight)
+        );
+    }
+}
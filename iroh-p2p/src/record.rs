@@ -0,0 +1,515 @@
+//! Validation of the records stored in the DHT.
+//!
+//! Record keys are namespaced by their first path segment, `/<namespace>/...`, and every
+//! namespace has a [`RecordValidator`], either registered on the node or by an rpc client.
+//! Records in namespaces without a validator are rejected.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use bytes::Bytes;
+use libp2p::core::SignedEnvelope;
+use libp2p::identity::Keypair;
+use libp2p::kad::{record::Key, Quorum, K_VALUE};
+use libp2p::PeerId;
+use tokio::sync::mpsc::{error::TrySendError, Sender};
+
+use crate::ipns::{self, IpnsRecord};
+
+pub use libp2p::kad::record::Key as RecordKey;
+
+/// Records larger than this are rejected, they would not fit in a kademlia message.
+const MAX_RECORD_SIZE: usize = 10 * 1024;
+const SIGNED_NAMESPACE: &str = "signed";
+/// Domain separation of the signatures of signed records.
+const SIGNED_RECORD_DOMAIN: &str = "iroh-dht-record";
+/// Maximum number of records waiting for the verdict of an rpc validator, further records
+/// are invalid.
+const MAX_PENDING_RECORDS: usize = 1024;
+/// How long an rpc validator may take to decide on a record, it is invalid afterwards.
+const RPC_VALIDATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Validates the records of a key namespace, before they are put into or returned from the
+/// DHT.
+pub trait RecordValidator: Send + Sync + 'static {
+    /// Checks that `value` is a valid record for `key`.
+    fn validate(&self, key: &Key, value: &[u8]) -> Result<()>;
+
+    /// Returns the index of the best of several valid records for the same key.
+    fn select(&self, _key: &Key, _values: &[Bytes]) -> usize {
+        0
+    }
+}
+
+impl<F> RecordValidator for F
+where
+    F: Fn(&Key, &[u8]) -> Result<()> + Send + Sync + 'static,
+{
+    fn validate(&self, key: &Key, value: &[u8]) -> Result<()> {
+        self(key, value)
+    }
+}
+
+/// The validators of the record namespaces.
+///
+/// By default the `ipns` and `signed` namespaces are supported, see [`sign_record`] for the
+/// latter.
+#[derive(Clone)]
+pub struct RecordValidators {
+    validators: HashMap<String, Arc<dyn RecordValidator>>,
+}
+
+impl Default for RecordValidators {
+    fn default() -> Self {
+        let mut validators = RecordValidators {
+            validators: HashMap::new(),
+        };
+        validators.insert("ipns", IpnsValidator);
+        validators.insert(SIGNED_NAMESPACE, SignedValidator);
+        validators
+    }
+}
+
+impl fmt::Debug for RecordValidators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.validators.keys()).finish()
+    }
+}
+
+impl RecordValidators {
+    /// Sets the validator of a namespace, replacing the previous one.
+    pub fn insert(&mut self, namespace: impl Into<String>, validator: impl RecordValidator) {
+        self.validators
+            .insert(namespace.into(), Arc::new(validator));
+    }
+
+    pub fn validate(&self, key: &Key, value: &[u8]) -> Result<()> {
+        ensure!(
+            value.len() <= MAX_RECORD_SIZE,
+            "record exceeds the maximum size of {} bytes",
+            MAX_RECORD_SIZE
+        );
+        self.get(key)?.validate(key, value)
+    }
+
+    /// Returns the index of the best of several valid records for the same key.
+    pub fn select(&self, key: &Key, values: &[Bytes]) -> Result<usize> {
+        let index = self.get(key)?.select(key, values);
+        ensure!(index < values.len(), "invalid record selected");
+        Ok(index)
+    }
+
+    fn get(&self, key: &Key) -> Result<&dyn RecordValidator> {
+        let namespace = namespace(key).context("record key is not namespaced")?;
+        self.validators
+            .get(namespace)
+            .map(|v| v.as_ref())
+            .ok_or_else(|| anyhow!("no validator for the record namespace {}", namespace))
+    }
+}
+
+/// A record sent to an rpc validator.
+#[derive(Debug, Clone)]
+pub(crate) struct RecordToValidate {
+    pub(crate) id: u64,
+    pub(crate) key: Key,
+    pub(crate) value: Bytes,
+}
+
+/// A record awaiting the verdict of an rpc validator, with what to do once it is validated.
+#[derive(Debug)]
+struct PendingRecord<T> {
+    namespace: String,
+    context: T,
+    deadline: Instant,
+}
+
+/// Validation of the records, by the validators of the node and those of rpc clients.
+///
+/// Every record is validated with a context, which is handed back along with the verdict.
+#[derive(Debug)]
+pub(crate) struct RecordValidation<T> {
+    validators: RecordValidators,
+    rpc_validators: HashMap<String, Sender<RecordToValidate>>,
+    /// The records an rpc validator has not decided on yet.
+    pending: HashMap<u64, PendingRecord<T>>,
+    next_id: u64,
+}
+
+impl<T> Default for RecordValidation<T> {
+    fn default() -> Self {
+        RecordValidation {
+            validators: Default::default(),
+            rpc_validators: Default::default(),
+            pending: Default::default(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<T> RecordValidation<T> {
+    pub(crate) fn validators_mut(&mut self) -> &mut RecordValidators {
+        &mut self.validators
+    }
+
+    /// Sends the records of the namespace to an rpc client for validation, until the
+    /// receiver is dropped. It takes precedence over a validator of the node.
+    pub(crate) fn add_rpc_validator(
+        &mut self,
+        namespace: String,
+        sender: Sender<RecordToValidate>,
+    ) -> Result<()> {
+        if let Some(existing) = self.rpc_validators.get(&namespace) {
+            ensure!(
+                existing.is_closed(),
+                "record namespace {} already has a validator",
+                namespace
+            );
+        }
+        self.rpc_validators.insert(namespace, sender);
+        Ok(())
+    }
+
+    /// Fails if the records under `key` can not be validated, as no validator is registered
+    /// for its namespace.
+    pub(crate) fn ensure_validator(&mut self, key: &Key) -> Result<()> {
+        if self.rpc_validator(key).is_some() {
+            return Ok(());
+        }
+        self.validators.get(key)?;
+        Ok(())
+    }
+
+    /// Returns the verdict on a record, or `None` when it awaits the verdict of an rpc
+    /// validator, see [`RecordValidation::report`].
+    pub(crate) fn validate(
+        &mut self,
+        key: &Key,
+        value: &Bytes,
+        context: T,
+    ) -> Option<(T, Result<()>)> {
+        if value.len() > MAX_RECORD_SIZE {
+            let err = anyhow!("record exceeds the maximum size of {MAX_RECORD_SIZE} bytes");
+            return Some((context, Err(err)));
+        }
+        let (namespace, sender) = match self.rpc_validator(key) {
+            Some((namespace, sender)) => (namespace.to_string(), sender.clone()),
+            None => return Some((context, self.validators.validate(key, value))),
+        };
+        if self.pending.len() >= MAX_PENDING_RECORDS {
+            let err = anyhow!("too many records awaiting validation");
+            return Some((context, Err(err)));
+        }
+        let id = self.next_id;
+        let record = RecordToValidate {
+            id,
+            key: key.clone(),
+            value: value.clone(),
+        };
+        match sender.try_send(record) {
+            Ok(()) => {
+                self.next_id += 1;
+                let deadline = Instant::now() + RPC_VALIDATION_TIMEOUT;
+                self.pending.insert(
+                    id,
+                    PendingRecord {
+                        namespace,
+                        context,
+                        deadline,
+                    },
+                );
+                None
+            }
+            Err(TrySendError::Full(_)) => {
+                let err = anyhow!("the validator of {} is lagging", namespace);
+                Some((context, Err(err)))
+            }
+            Err(TrySendError::Closed(_)) => {
+                // validated by the node again
+                self.rpc_validators.remove(&namespace);
+                Some((context, self.validators.validate(key, value)))
+            }
+        }
+    }
+
+    /// Returns the context of the record an rpc validator decided on, with its verdict.
+    pub(crate) fn report(&mut self, id: u64, valid: bool) -> Result<(T, Result<()>)> {
+        let record = self
+            .pending
+            .remove(&id)
+            .ok_or_else(|| anyhow!("no record {} awaits validation", id))?;
+        let res = if valid {
+            Ok(())
+        } else {
+            Err(anyhow!("rejected by the validator of {}", record.namespace))
+        };
+        Ok((record.context, res))
+    }
+
+    /// Removes the records rpc validators did not decide on in time, they are invalid.
+    pub(crate) fn expire(&mut self, now: Instant) -> Vec<(T, Result<()>)> {
+        let expired: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, record)| record.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|id| self.pending.remove(&id))
+            .map(|record| {
+                let err = anyhow!("the validator of {} timed out", record.namespace);
+                (record.context, Err(err))
+            })
+            .collect()
+    }
+
+    /// Returns the index of the best of several valid records for the same key.
+    ///
+    /// Of the records validated by an rpc client the first one is the best.
+    pub(crate) fn select(&mut self, key: &Key, values: &[Bytes]) -> Result<usize> {
+        if self.rpc_validator(key).is_some() {
+            return Ok(0);
+        }
+        self.validators.select(key, values)
+    }
+
+    /// Returns the open rpc validator of the namespace of `key`.
+    fn rpc_validator(&mut self, key: &Key) -> Option<(&str, &Sender<RecordToValidate>)> {
+        let namespace = namespace(key)?;
+        if self.rpc_validators.get(namespace)?.is_closed() {
+            self.rpc_validators.remove(namespace);
+            return None;
+        }
+        self.rpc_validators
+            .get_key_value(namespace)
+            .map(|(namespace, sender)| (namespace.as_str(), sender))
+    }
+}
+
+/// Returns the namespace of a key of the form `/<namespace>/...`.
+fn namespace(key: &Key) -> Option<&str> {
+    let key = key.as_ref().strip_prefix(b"/")?;
+    let end = key.iter().position(|b| *b == b'/')?;
+    std::str::from_utf8(&key[..end])
+        .ok()
+        .filter(|ns| !ns.is_empty())
+}
+
+/// Returns how many records a query for `quorum` collects.
+pub(crate) fn quorum_size(quorum: Quorum) -> usize {
+    match quorum {
+        Quorum::One => 1,
+        Quorum::Majority => K_VALUE.get() / 2 + 1,
+        Quorum::All => K_VALUE.get(),
+        Quorum::N(n) => n.get().min(K_VALUE.get()),
+    }
+}
+
+/// Accepts IPNS records which are signed by the owner of the name and not expired, the
+/// newest record is the best.
+#[derive(Debug)]
+struct IpnsValidator;
+
+impl RecordValidator for IpnsValidator {
+    fn validate(&self, key: &Key, value: &[u8]) -> Result<()> {
+        let name = ipns::name_from_key(key)?;
+        let record = IpnsRecord::from_bytes(&name, value)?;
+        ensure!(!record.is_expired(), "expired ipns record");
+        Ok(())
+    }
+
+    fn select(&self, key: &Key, values: &[Bytes]) -> usize {
+        let name = match ipns::name_from_key(key) {
+            Ok(name) => name,
+            Err(_) => return 0,
+        };
+        let mut best: Option<(usize, IpnsRecord)> = None;
+        for (i, value) in values.iter().enumerate() {
+            if let Ok(record) = IpnsRecord::from_bytes(&name, value) {
+                if best.as_ref().map_or(true, |(_, b)| record.is_newer_than(b)) {
+                    best = Some((i, record));
+                }
+            }
+        }
+        best.map(|(i, _)| i).unwrap_or_default()
+    }
+}
+
+/// Accepts records under `/signed/<peer id>/...` which are signed by that peer.
+#[derive(Debug)]
+struct SignedValidator;
+
+impl RecordValidator for SignedValidator {
+    fn validate(&self, key: &Key, value: &[u8]) -> Result<()> {
+        open_signed_record(key, value)?;
+        Ok(())
+    }
+}
+
+/// Returns the key of the signed record `name` of `peer_id`.
+pub fn signed_record_key(peer_id: &PeerId, name: &str) -> Key {
+    Key::from(format!("/{SIGNED_NAMESPACE}/{peer_id}/{name}").into_bytes())
+}
+
+/// Signs `value` as the record under `key`, which must be a key of the signing peer as
+/// created by [`signed_record_key`].
+pub fn sign_record(keypair: &Keypair, key: &Key, value: &[u8]) -> Result<Vec<u8>> {
+    ensure!(
+        signer(key)? == keypair.public().to_peer_id(),
+        "the record key belongs to another peer"
+    );
+    let envelope = SignedEnvelope::new(
+        keypair,
+        SIGNED_RECORD_DOMAIN.to_string(),
+        key.to_vec(),
+        value.to_vec(),
+    )?;
+    Ok(envelope.into_protobuf_encoding())
+}
+
+/// Verifies a signed record, returns the value that was signed.
+pub fn open_signed_record(key: &Key, record: &[u8]) -> Result<Vec<u8>> {
+    let signer = signer(key)?;
+    let envelope = SignedEnvelope::from_protobuf_encoding(record).context("invalid record")?;
+    // the key is signed as payload type, which binds the value to it
+    let (value, public_key) = envelope
+        .payload_and_signing_key(SIGNED_RECORD_DOMAIN.to_string(), key.as_ref())
+        .context("invalid record signature")?;
+    ensure!(
+        public_key.to_peer_id() == signer,
+        "record is not signed by {}",
+        signer
+    );
+    Ok(value.to_vec())
+}
+
+/// Returns the peer whose signature a key of the `signed` namespace requires.
+fn signer(key: &Key) -> Result<PeerId> {
+    let key = std::str::from_utf8(key.as_ref()).context("invalid signed record key")?;
+    let peer_id = key
+        .strip_prefix(&format!("/{SIGNED_NAMESPACE}/"))
+        .and_then(|rest| rest.split('/').next())
+        .context("not a signed record key")?;
+    peer_id.parse().context("invalid signed record key")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_namespace() {
+        assert_eq!(namespace(&Key::new(b"/ipns/foo")), Some("ipns"));
+        assert_eq!(namespace(&Key::new(b"/signed/foo/bar")), Some("signed"));
+        assert_eq!(namespace(&Key::new(b"/ipns")), None);
+        assert_eq!(namespace(&Key::new(b"//foo")), None);
+        assert_eq!(namespace(&Key::new(b"ipns/foo")), None);
+    }
+
+    #[test]
+    fn test_signed_records() {
+        let validators = RecordValidators::default();
+        let keypair = Keypair::generate_ed25519();
+        let peer_id = keypair.public().to_peer_id();
+
+        let key = signed_record_key(&peer_id, "status");
+        let value = sign_record(&keypair, &key, b"online").unwrap();
+        validators.validate(&key, &value).unwrap();
+        assert_eq!(open_signed_record(&key, &value).unwrap(), b"online");
+
+        // the signature covers the key
+        let other_key = signed_record_key(&peer_id, "other");
+        assert!(validators.validate(&other_key, &value).is_err());
+        // only the owner of the key can sign
+        let other = Keypair::generate_ed25519();
+        assert!(sign_record(&other, &key, b"online").is_err());
+        let other_key = signed_record_key(&other.public().to_peer_id(), "status");
+        assert!(validators.validate(&other_key, &value).is_err());
+
+        assert!(validators.validate(&key, b"online").is_err());
+        assert!(validators
+            .validate(&Key::new(b"/unknown/foo"), b"online")
+            .is_err());
+    }
+
+    #[test]
+    fn test_ipns_records() {
+        let validators = RecordValidators::default();
+        let keypair = Keypair::generate_ed25519();
+        let key = ipns::record_key(&keypair.public().to_peer_id());
+        let lifetime = Duration::from_secs(60);
+
+        let records: Vec<Bytes> = [1, 3, 2]
+            .into_iter()
+            .map(|sequence| {
+                let record =
+                    IpnsRecord::new("/ipfs/QmSomething".into(), sequence, lifetime, lifetime)
+                        .unwrap();
+                record.sign(&keypair).unwrap().into()
+            })
+            .collect();
+        for record in &records {
+            validators.validate(&key, record).unwrap();
+        }
+        assert_eq!(validators.select(&key, &records).unwrap(), 1);
+
+        let other = ipns::record_key(&Keypair::generate_ed25519().public().to_peer_id());
+        assert!(validators.validate(&other, &records[0]).is_err());
+    }
+
+    #[test]
+    fn test_rpc_validator() {
+        let mut validation = RecordValidation::default();
+        let key = Key::new(b"/app/foo");
+        let value = Bytes::from_static(b"bar");
+        assert!(validation.ensure_validator(&key).is_err());
+        let (_, res) = validation.validate(&key, &value, 1).unwrap();
+        assert!(res.is_err());
+
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(8);
+        validation
+            .add_rpc_validator("app".to_string(), sender.clone())
+            .unwrap();
+        assert!(validation
+            .add_rpc_validator("app".to_string(), sender)
+            .is_err());
+        validation.ensure_validator(&key).unwrap();
+
+        // the verdict of the rpc validator is awaited
+        assert!(validation.validate(&key, &value, 2).is_none());
+        assert!(validation.validate(&key, &value, 3).is_none());
+        let first = receiver.try_recv().unwrap();
+        assert_eq!(
+            (first.key.clone(), first.value),
+            (key.clone(), value.clone())
+        );
+        let second = receiver.try_recv().unwrap();
+        let (context, res) = validation.report(first.id, true).unwrap();
+        assert_eq!(context, 2);
+        assert!(res.is_ok());
+        assert!(validation.report(first.id, true).is_err());
+        let (context, res) = validation.report(second.id, false).unwrap();
+        assert_eq!(context, 3);
+        assert!(res.is_err());
+
+        // records the validator does not decide on in time are invalid
+        assert!(validation.validate(&key, &value, 4).is_none());
+        assert!(validation.expire(Instant::now()).is_empty());
+        let expired = validation.expire(Instant::now() + RPC_VALIDATION_TIMEOUT);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, 4);
+        assert!(expired[0].1.is_err());
+
+        // once the validator is gone, the namespace has none
+        drop(receiver);
+        assert!(validation.ensure_validator(&key).is_err());
+        let (_, res) = validation.validate(&key, &value, 5).unwrap();
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,444 @@
+//! Circuit relays: reservations on relays, which keep the node reachable behind a NAT.
+//!
+//! While AutoNAT reports the node as private, it listens through up to
+//! `relay_reservations` relays, the static relays first and then peers that advertise the
+//! relay v2 hop protocol. The relayed `/p2p-circuit` addresses are advertised as external
+//! addresses until the reservations end.
+//!
+//! With `relay_server`, the node relays circuits for other peers within the configured limits.
+
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use anyhow::{ensure, Result};
+use libp2p::core::transport::ListenerId;
+use libp2p::identify::Info as IdentifyInfo;
+use libp2p::multiaddr::Protocol;
+use libp2p::relay::v2::relay::{self, rate_limiter::RateLimiter};
+use libp2p::swarm::AddressScore;
+use libp2p::{Multiaddr, PeerId, Swarm};
+use tracing::{debug, info, warn};
+
+use crate::behaviour::NodeBehaviour;
+use crate::config::addr_peer_id;
+use crate::Libp2pConfig;
+
+// TODO: expose protocol name on `libp2p::relay`.
+const HOP_PROTOCOL: &str = "/libp2p/circuit/relay/0.2.0/hop";
+/// Maximum number of discovered relays kept as candidates.
+const MAX_CANDIDATES: usize = 32;
+/// Time a relay is not used after its reservation failed or ended.
+const RELAY_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub(crate) struct AutoRelay {
+    /// The static relays and their addresses, ending in `/p2p/<relay>`.
+    static_relays: Vec<(PeerId, Multiaddr)>,
+    /// Relays discovered through identify, with the address they were reached at.
+    candidates: HashMap<PeerId, Multiaddr>,
+    max_reservations: usize,
+    /// Whether AutoNAT reported the node as private.
+    private: bool,
+    /// Listen through the static relays regardless of the reachability.
+    always_static: bool,
+    /// The listeners on relays.
+    reservations: HashMap<ListenerId, Reservation>,
+    /// Relays whose last reservation failed or ended, and when.
+    backoff: HashMap<PeerId, Instant>,
+}
+
+#[derive(Debug)]
+struct Reservation {
+    relay: PeerId,
+    /// The relayed addresses of the node.
+    addrs: Vec<Multiaddr>,
+}
+
+impl AutoRelay {
+    pub(crate) fn new(config: &Libp2pConfig) -> Result<Self> {
+        ensure!(
+            config.relay_client || config.static_relays.is_empty(),
+            "static_relays require the relay client to be enabled"
+        );
+        let static_relays = config
+            .static_relays
+            .iter()
+            .map(|addr| Ok((addr_peer_id(addr)?, addr.clone())))
+            .collect::<Result<_>>()?;
+        let max_reservations = if config.relay_client {
+            config.relay_reservations
+        } else {
+            0
+        };
+        Ok(AutoRelay {
+            static_relays,
+            candidates: Default::default(),
+            max_reservations,
+            private: false,
+            // without AutoNAT the reachability is unknown
+            always_static: !config.autonat,
+            reservations: Default::default(),
+            backoff: Default::default(),
+        })
+    }
+
+    /// Updates the reachability of the node, as reported by AutoNAT.
+    pub(crate) fn set_private(&mut self, private: bool) {
+        self.private = private;
+    }
+
+    /// Adds the peer as relay candidate if it supports the relay v2 hop protocol.
+    ///
+    /// `connections` are the remote addresses of the connections to the peer, and whether
+    /// they are inbound. The relay is reached at the address of an outbound connection, or
+    /// at one of its listen addresses on the IP of an inbound connection, as the others may
+    /// well be private or unreachable.
+    pub(crate) fn add_candidate<'a>(
+        &mut self,
+        peer_id: PeerId,
+        info: &IdentifyInfo,
+        connections: impl IntoIterator<Item = (&'a Multiaddr, bool)>,
+    ) {
+        if !info.protocols.iter().any(|p| p == HOP_PROTOCOL)
+            || self
+                .static_relays
+                .iter()
+                .any(|(relay, _)| *relay == peer_id)
+            || (self.candidates.len() >= MAX_CANDIDATES && !self.candidates.contains_key(&peer_id))
+        {
+            return;
+        }
+        // a relay cannot be reached through another relay
+        let connections: Vec<_> = connections
+            .into_iter()
+            .filter(|(addr, _)| !is_relayed(addr))
+            .collect();
+        let outbound = connections
+            .iter()
+            .find(|(_, inbound)| !inbound)
+            .map(|(addr, _)| without_peer_id((*addr).clone()));
+        let addr = outbound.or_else(|| {
+            let observed: Vec<IpAddr> = connections.iter().filter_map(|(a, _)| ip(a)).collect();
+            info.listen_addrs
+                .iter()
+                .find(|addr| {
+                    !is_relayed(addr) && ip(addr).map_or(false, |ip| observed.contains(&ip))
+                })
+                .cloned()
+        });
+        match addr {
+            Some(addr) => {
+                let addr = addr.with(Protocol::P2p(peer_id.into()));
+                self.candidates.insert(peer_id, addr);
+            }
+            None => debug!("relay: no address to reach {} at", peer_id),
+        }
+    }
+
+    /// Requests reservations until there are enough, or ends all of them once the node no
+    /// longer needs relays.
+    pub(crate) fn refresh(&mut self, swarm: &mut Swarm<NodeBehaviour>) {
+        if !self.private && !self.always_static {
+            for (listener_id, reservation) in self.reservations.drain() {
+                debug!("relay: ending the reservation on {}", reservation.relay);
+                swarm.remove_listener(listener_id);
+                for addr in &reservation.addrs {
+                    swarm.remove_external_address(addr);
+                }
+            }
+            return;
+        }
+
+        let now = Instant::now();
+        self.backoff
+            .retain(|_, since| now.duration_since(*since) < RELAY_BACKOFF);
+        for (relay, addr) in self.select() {
+            let circuit = addr.with(Protocol::P2pCircuit);
+            match swarm.listen_on(circuit.clone()) {
+                Ok(listener_id) => {
+                    info!("relay: requesting a reservation on {}", relay);
+                    let reservation = Reservation {
+                        relay,
+                        addrs: Vec::new(),
+                    };
+                    self.reservations.insert(listener_id, reservation);
+                }
+                Err(err) => {
+                    warn!("relay: failed to listen on {}: {:?}", circuit, err);
+                    self.backoff.insert(relay, now);
+                }
+            }
+        }
+    }
+
+    /// Returns the relays to request a reservation on.
+    fn select(&self) -> Vec<(PeerId, Multiaddr)> {
+        let reserved: HashSet<PeerId> = self.reservations.values().map(|r| r.relay).collect();
+        let candidates = self.candidates.iter().filter(|_| self.private);
+        self.static_relays
+            .iter()
+            .map(|(relay, addr)| (relay, addr))
+            .chain(candidates)
+            .filter(|(relay, _)| !reserved.contains(*relay) && !self.backoff.contains_key(*relay))
+            .take(
+                self.max_reservations
+                    .saturating_sub(self.reservations.len()),
+            )
+            .map(|(relay, addr)| (*relay, addr.clone()))
+            .collect()
+    }
+
+    /// Advertises a new relayed address, if the listener is on a relay.
+    pub(crate) fn on_new_listen_addr(
+        &mut self,
+        listener_id: ListenerId,
+        addr: &Multiaddr,
+        swarm: &mut Swarm<NodeBehaviour>,
+    ) {
+        if let Some(reservation) = self.reservations.get_mut(&listener_id) {
+            info!("relay: listening on {}", addr);
+            swarm.add_external_address(addr.clone(), AddressScore::Infinite);
+            reservation.addrs.push(addr.clone());
+        }
+    }
+
+    /// Forgets a reservation that failed or ended, a new one is requested on the next
+    /// refresh.
+    pub(crate) fn on_listener_closed(
+        &mut self,
+        listener_id: ListenerId,
+        swarm: &mut Swarm<NodeBehaviour>,
+    ) {
+        if let Some(reservation) = self.reservations.remove(&listener_id) {
+            info!("relay: lost the reservation on {}", reservation.relay);
+            for addr in &reservation.addrs {
+                swarm.remove_external_address(addr);
+            }
+            self.backoff.insert(reservation.relay, Instant::now());
+            // discovered again when identify runs on a new connection
+            self.candidates.remove(&reservation.relay);
+        }
+    }
+}
+
+fn is_relayed(addr: &Multiaddr) -> bool {
+    addr.iter().any(|p| p == Protocol::P2pCircuit)
+}
+
+fn ip(addr: &Multiaddr) -> Option<IpAddr> {
+    addr.iter().find_map(|p| match p {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+fn without_peer_id(mut addr: Multiaddr) -> Multiaddr {
+    if let Some(Protocol::P2p(_)) = addr.iter().last() {
+        addr.pop();
+    }
+    addr
+}
+
+/// Returns the configuration of the relay server.
+pub(crate) fn relay_server_config(config: &Libp2pConfig) -> relay::Config {
+    let mut relay_config = relay::Config {
+        max_reservations: config.relay_max_reservations,
+        max_circuits: config.relay_max_circuits,
+        max_circuit_duration: Duration::from_secs(config.relay_max_circuit_duration_secs),
+        max_circuit_bytes: config.relay_max_circuit_bytes,
+        ..Default::default()
+    };
+    if !config.relay_allow_peers.is_empty() {
+        let allow_list = AllowList(config.relay_allow_peers.iter().copied().collect());
+        relay_config
+            .reservation_rate_limiters
+            .push(Box::new(allow_list));
+    }
+    relay_config
+}
+
+/// Denies reservations to the peers that are not on the list.
+struct AllowList(HashSet<PeerId>);
+
+impl RateLimiter for AllowList {
+    fn try_next(&mut self, peer: PeerId, _addr: &Multiaddr, _now: Instant) -> bool {
+        self.0.contains(&peer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relay_addr(peer_id: &PeerId) -> Multiaddr {
+        format!("/ip4/10.0.0.1/tcp/4001/p2p/{peer_id}")
+            .parse()
+            .unwrap()
+    }
+
+    fn identify_info(protocols: &[&str]) -> IdentifyInfo {
+        IdentifyInfo {
+            public_key: libp2p::identity::Keypair::generate_ed25519().public(),
+            protocol_version: "ipfs/0.1.0".to_string(),
+            agent_version: "test".to_string(),
+            listen_addrs: vec![
+                "/ip4/10.0.0.2/tcp/4001/p2p/12D3KooWFma2D63TG9ToSiRsjFkoNm2tTihScTBAEdXxinYk5rwE/p2p-circuit"
+                    .parse()
+                    .unwrap(),
+                "/ip4/10.0.0.2/tcp/4001".parse().unwrap(),
+            ],
+            protocols: protocols.iter().map(|p| p.to_string()).collect(),
+            observed_addr: Multiaddr::empty(),
+        }
+    }
+
+    fn selected(auto_relay: &AutoRelay) -> Vec<PeerId> {
+        auto_relay
+            .select()
+            .into_iter()
+            .map(|(relay, _)| relay)
+            .collect()
+    }
+
+    #[test]
+    fn test_candidate_addr() {
+        let config = Libp2pConfig::default();
+        let mut auto_relay = AutoRelay::new(&config).unwrap();
+        let mut info = identify_info(&[HOP_PROTOCOL]);
+        info.listen_addrs
+            .insert(0, "/ip4/127.0.0.1/tcp/4001".parse().unwrap());
+        let dialed: Multiaddr = "/ip4/1.2.3.4/tcp/4001".parse().unwrap();
+        let inbound: Multiaddr = "/ip4/10.0.0.2/tcp/53012".parse().unwrap();
+
+        // the address of an outbound connection is reachable
+        let outbound_relay = PeerId::random();
+        let with_peer_id = dialed.clone().with(Protocol::P2p(outbound_relay.into()));
+        auto_relay.add_candidate(
+            outbound_relay,
+            &info,
+            [(&inbound, true), (&with_peer_id, false)],
+        );
+        assert_eq!(auto_relay.candidates[&outbound_relay], with_peer_id);
+
+        // an inbound connection picks the listen address on its IP, not its port
+        let inbound_relay = PeerId::random();
+        auto_relay.add_candidate(inbound_relay, &info, [(&inbound, true)]);
+        assert_eq!(
+            auto_relay.candidates[&inbound_relay],
+            format!("/ip4/10.0.0.2/tcp/4001/p2p/{inbound_relay}")
+                .parse::<Multiaddr>()
+                .unwrap()
+        );
+
+        // without a matching address the relay is skipped
+        let unreachable_relay = PeerId::random();
+        let other: Multiaddr = "/ip4/5.6.7.8/tcp/53012".parse().unwrap();
+        auto_relay.add_candidate(unreachable_relay, &info, [(&other, true)]);
+        assert!(!auto_relay.candidates.contains_key(&unreachable_relay));
+    }
+
+    #[test]
+    fn test_select() {
+        let static_relay = PeerId::random();
+        let config = Libp2pConfig {
+            static_relays: vec![relay_addr(&static_relay)],
+            relay_reservations: 2,
+            ..Default::default()
+        };
+        let mut auto_relay = AutoRelay::new(&config).unwrap();
+        let dialed: Multiaddr = "/ip4/10.0.0.2/tcp/4001".parse().unwrap();
+
+        let candidate = PeerId::random();
+        auto_relay.add_candidate(
+            candidate,
+            &identify_info(&[HOP_PROTOCOL]),
+            [(&dialed, false)],
+        );
+        auto_relay.add_candidate(
+            PeerId::random(),
+            &identify_info(&["/ipfs/kad/1.0.0"]),
+            [(&dialed, false)],
+        );
+        assert_eq!(auto_relay.candidates.len(), 1);
+        assert_eq!(
+            auto_relay.candidates[&candidate],
+            format!("/ip4/10.0.0.2/tcp/4001/p2p/{candidate}")
+                .parse::<Multiaddr>()
+                .unwrap()
+        );
+
+        // relays are only needed while the node is private
+        assert!(selected(&auto_relay).is_empty());
+        auto_relay.set_private(true);
+        assert_eq!(selected(&auto_relay), vec![static_relay, candidate]);
+
+        // reserved relays and relays that failed recently are skipped
+        auto_relay.reservations.insert(
+            ListenerId::new(),
+            Reservation {
+                relay: static_relay,
+                addrs: Vec::new(),
+            },
+        );
+        assert_eq!(selected(&auto_relay), vec![candidate]);
+        auto_relay.backoff.insert(candidate, Instant::now());
+        assert!(selected(&auto_relay).is_empty());
+    }
+
+    #[test]
+    fn test_static_relays() {
+        let static_relay = PeerId::random();
+        let mut config = Libp2pConfig {
+            static_relays: vec![relay_addr(&static_relay)],
+            autonat: false,
+            ..Default::default()
+        };
+        let mut auto_relay = AutoRelay::new(&config).unwrap();
+        let dialed: Multiaddr = "/ip4/10.0.0.2/tcp/4001".parse().unwrap();
+        auto_relay.add_candidate(
+            PeerId::random(),
+            &identify_info(&[HOP_PROTOCOL]),
+            [(&dialed, false)],
+        );
+        // without AutoNAT only the static relays are used
+        assert_eq!(selected(&auto_relay), vec![static_relay]);
+
+        config.static_relays = vec!["/ip4/10.0.0.1/tcp/4001".parse().unwrap()];
+        assert!(AutoRelay::new(&config).is_err());
+        config.static_relays = vec![relay_addr(&static_relay)];
+        config.relay_client = false;
+        assert!(AutoRelay::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_relay_server_config() {
+        let allowed = PeerId::random();
+        let mut config = Libp2pConfig {
+            relay_max_reservations: 8,
+            relay_max_circuits: 4,
+            relay_max_circuit_duration_secs: 30,
+            relay_max_circuit_bytes: 1024,
+            ..Default::default()
+        };
+        let relay_config = relay_server_config(&config);
+        assert_eq!(relay_config.max_reservations, 8);
+        assert_eq!(relay_config.max_circuits, 4);
+        assert_eq!(relay_config.max_circuit_duration, Duration::from_secs(30));
+        assert_eq!(relay_config.max_circuit_bytes, 1024);
+        let default_limiters = relay_config.reservation_rate_limiters.len();
+
+        config.relay_allow_peers = vec![allowed];
+        let relay_config = relay_server_config(&config);
+        assert_eq!(
+            relay_config.reservation_rate_limiters.len(),
+            default_limiters + 1
+        );
+
+        let mut allow_list = AllowList([allowed].into_iter().collect());
+        let addr = "/ip4/10.0.0.1/tcp/4001".parse().unwrap();
+        assert!(allow_list.try_next(allowed, &addr, Instant::now()));
+        assert!(!allow_list.try_next(PeerId::random(), &addr, Instant::now()));
+    }
+}
@@ -0,0 +1,533 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use ahash::{AHashMap, AHashSet};
+use anyhow::Result;
+use cid::Cid;
+use iroh_metrics::{core::MRecorder, inc, p2p::P2PMetrics};
+use iroh_rpc_client::{Client, StoreClient};
+use libp2p::kad::{record::Key, store::RecordStore, Kademlia, QueryId};
+use libp2p::PeerId;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::behaviour::KadStore;
+use crate::config::{Libp2pConfig, ReproviderStrategy};
+
+/// Maximum number of newly stored blocks waiting to be announced.
+const QUEUE_LIMIT: usize = 2048;
+
+/// Periodically re-announces locally stored content to the DHT.
+///
+/// Each run lists the content selected by the [`ReproviderStrategy`] from the store, one
+/// batch at a time. The next batch is only fetched once all provider records of the
+/// previous one are published.
+#[derive(Debug)]
+pub struct Reprovider {
+    local_id: PeerId,
+    strategy: ReproviderStrategy,
+    interval: Option<Duration>,
+    batch_size: usize,
+    /// Cids waiting to be announced.
+    queue: VecDeque<Cid>,
+    /// Announcements in progress.
+    running: AHashMap<QueryId, Key>,
+    run: Option<Run>,
+    /// Batches of newly pinned content, listed in the background.
+    pinned: mpsc::Receiver<Vec<Cid>>,
+    pinned_sender: mpsc::Sender<Vec<Cid>>,
+}
+
+#[derive(Debug)]
+struct Run {
+    batches: mpsc::Receiver<Vec<Cid>>,
+    started: Instant,
+    provided: u64,
+    failed: u64,
+}
+
+impl Reprovider {
+    pub fn new(local_id: PeerId, config: &Libp2pConfig) -> Self {
+        let interval = (config.kademlia && config.reprovider_interval_secs > 0)
+            .then(|| Duration::from_secs(config.reprovider_interval_secs));
+        let (pinned_sender, pinned) = mpsc::channel(1);
+        Self {
+            local_id,
+            strategy: config.reprovider_strategy,
+            interval,
+            batch_size: config.reprovider_batch_size.max(1),
+            queue: Default::default(),
+            running: Default::default(),
+            run: None,
+            pinned,
+            pinned_sender,
+        }
+    }
+
+    /// The interval between two runs, `None` if the reprovider is disabled.
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    /// Starts a new run, unless the previous one is still in progress.
+    pub fn start(&mut self, rpc_client: &Client) {
+        if self.run.is_some() {
+            debug!("reprovider: previous run still in progress");
+            return;
+        }
+        let store = match rpc_client.try_store() {
+            Ok(store) => store,
+            Err(err) => {
+                warn!("reprovider: {:?}", err);
+                return;
+            }
+        };
+
+        info!("reprovider: starting run, strategy: {}", self.strategy);
+        inc!(P2PMetrics::ReproviderRuns);
+        let (sender, batches) = mpsc::channel(1);
+        let strategy = self.strategy;
+        let batch_size = self.batch_size;
+        tokio::task::spawn(async move {
+            if let Err(err) = list_cids(store, strategy, batch_size, sender).await {
+                warn!("reprovider: failed to list content: {:?}", err);
+            }
+        });
+        self.run = Some(Run {
+            batches,
+            started: Instant::now(),
+            provided: 0,
+            failed: 0,
+        });
+    }
+
+    /// Queues a newly stored block for announcement, if the strategy covers it.
+    pub fn push(&mut self, cid: Cid) {
+        if self.interval.is_some()
+            && self.strategy == ReproviderStrategy::All
+            && self.queue.len() < QUEUE_LIMIT
+        {
+            self.queue.push_back(cid);
+        }
+    }
+
+    /// Queues newly pinned content for announcement.
+    ///
+    /// With the `roots` strategy only `root` is announced, otherwise all stored blocks it links
+    /// to. Blocks stored before they were pinned are not covered by [`Reprovider::push`].
+    pub fn push_pinned(&mut self, root: Cid, rpc_client: &Client) {
+        if self.interval.is_none() {
+            return;
+        }
+        if self.strategy == ReproviderStrategy::Roots {
+            if self.queue.len() < QUEUE_LIMIT {
+                self.queue.push_back(root);
+            }
+            return;
+        }
+
+        let store = match rpc_client.try_store() {
+            Ok(store) => store,
+            Err(err) => {
+                warn!("reprovider: {:?}", err);
+                return;
+            }
+        };
+        let sender = self.pinned_sender.clone();
+        let batch_size = self.batch_size;
+        tokio::task::spawn(async move {
+            if let Err(err) = list_dag(store, vec![root], batch_size, sender).await {
+                warn!("reprovider: failed to list pinned content: {:?}", err);
+            }
+        });
+    }
+
+    /// Returns `true` if there is room in the queue for newly pinned content.
+    pub fn wants_pinned(&self) -> bool {
+        self.interval.is_some() && self.queue.len() + self.batch_size <= QUEUE_LIMIT
+    }
+
+    /// Waits for the next batch of newly pinned content.
+    pub async fn next_pinned(&mut self) -> Vec<Cid> {
+        // the sender is kept around, so the channel is never closed
+        self.pinned.recv().await.unwrap_or_default()
+    }
+
+    pub fn handle_pinned(&mut self, cids: Vec<Cid>, kad: &mut Kademlia<KadStore>) {
+        self.queue.extend(cids);
+        self.poll(kad);
+    }
+
+    /// Returns `true` once the current run is ready for its next batch.
+    pub fn wants_batch(&self) -> bool {
+        self.run.is_some() && self.queue.is_empty() && self.running.is_empty()
+    }
+
+    /// Waits for the next batch of the current run, `None` once the run is complete.
+    pub async fn next_batch(&mut self) -> Option<Vec<Cid>> {
+        match self.run {
+            Some(ref mut run) => run.batches.recv().await,
+            None => futures::future::pending().await,
+        }
+    }
+
+    pub fn handle_batch(&mut self, batch: Option<Vec<Cid>>, kad: &mut Kademlia<KadStore>) {
+        match batch {
+            Some(cids) => {
+                self.queue.extend(cids);
+                self.poll(kad);
+            }
+            None => {
+                if let Some(run) = self.run.take() {
+                    info!(
+                        "reprovider: run done in {:?}, provided: {}, failed: {}",
+                        run.started.elapsed(),
+                        run.provided,
+                        run.failed
+                    );
+                }
+            }
+        }
+    }
+
+    /// Handles the result of a `StartProviding` query, ignores queries started elsewhere.
+    pub fn handle_start_providing(&mut self, id: QueryId, ok: bool, kad: &mut Kademlia<KadStore>) {
+        if let Some(key) = self.running.remove(&id) {
+            // The local record is only needed for the announcement. Keeping it would count
+            // against the store limits and make kademlia republish the key on its own.
+            kad.stop_providing(&key);
+            if ok {
+                inc!(P2PMetrics::ReprovidedCids);
+            } else {
+                inc!(P2PMetrics::ReprovideFailures);
+            }
+            if let Some(run) = self.run.as_mut() {
+                if ok {
+                    run.provided += 1;
+                } else {
+                    run.failed += 1;
+                }
+            }
+            self.poll(kad);
+        }
+    }
+
+    /// Keeps the local provider record for `key`, because it was provided explicitly.
+    pub fn keep(&mut self, key: &Key) {
+        self.running.retain(|_, k| k != key);
+    }
+
+    /// Starts announcements for the queued cids, up to the batch size.
+    pub fn poll(&mut self, kad: &mut Kademlia<KadStore>) {
+        while self.running.len() < self.batch_size {
+            let cid = match self.queue.pop_front() {
+                Some(cid) => cid,
+                None => break,
+            };
+            let key = Key::new(&cid.hash().to_bytes());
+
+            // explicitly provided keys are republished by kademlia itself
+            let provided = kad
+                .store_mut()
+                .providers(&key)
+                .iter()
+                .any(|record| record.provider == self.local_id);
+            if provided || self.running.values().any(|k| k == &key) {
+                continue;
+            }
+
+            match kad.start_providing(key.clone()) {
+                Ok(id) => {
+                    self.running.insert(id, key);
+                }
+                Err(err) => {
+                    debug!("reprovider: failed to provide {}: {:?}", cid, err);
+                    inc!(P2PMetrics::ReprovideFailures);
+                }
+            }
+        }
+    }
+}
+
+/// Lists the cids selected by `strategy` and sends them in batches of `batch_size`.
+async fn list_cids(
+    store: StoreClient,
+    strategy: ReproviderStrategy,
+    batch_size: usize,
+    sender: mpsc::Sender<Vec<Cid>>,
+) -> Result<()> {
+    match strategy {
+        ReproviderStrategy::All => {
+            let mut from = Some(0);
+            while let Some(start) = from {
+                let (cids, next) = store.list_blocks(start, batch_size as u64).await?;
+                if !cids.is_empty() && sender.send(cids).await.is_err() {
+                    return Ok(());
+                }
+                from = next;
+            }
+        }
+        ReproviderStrategy::Roots => {
+            for batch in store.list_pins().await?.chunks(batch_size) {
+                if sender.send(batch.to_vec()).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        ReproviderStrategy::Pinned => {
+            let roots = store.list_pins().await?;
+            list_dag(store, roots, batch_size, sender).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists the stored blocks reachable from `roots` and sends them in batches of `batch_size`.
+async fn list_dag(
+    store: StoreClient,
+    roots: Vec<Cid>,
+    batch_size: usize,
+    sender: mpsc::Sender<Vec<Cid>>,
+) -> Result<()> {
+    let mut pending = roots;
+    let mut seen = AHashSet::new();
+    let mut batch = Vec::with_capacity(batch_size);
+    while let Some(cid) = pending.pop() {
+        if !seen.insert(cid) {
+            continue;
+        }
+        // links are only known for blocks that are stored locally
+        if let Some(links) = store.get_links(cid).await? {
+            pending.extend(links);
+            batch.push(cid);
+            if batch.len() == batch_size && sender.send(std::mem::take(&mut batch)).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+    if !batch.is_empty() {
+        sender.send(batch).await.ok();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cid::multihash::{Code, MultihashDigest};
+    use iroh_rpc_client::{create_server, Config as RpcClientConfig};
+    use iroh_rpc_types::store::{
+        GetLinksRequest, GetLinksResponse, ListBlocksRequest, ListBlocksResponse, ListPinsRequest,
+        ListPinsResponse, StoreAddr, StoreRequest, StoreService,
+    };
+    use libp2p::kad::store::MemoryStoreConfig;
+
+    fn cid(name: &str) -> Cid {
+        Cid::new_v1(0x55, Code::Sha2_256.digest(name.as_bytes()))
+    }
+
+    /// Serves the listing requests of the reprovider from fixed content.
+    #[derive(Debug, Clone, Default)]
+    struct MockStore {
+        blocks: Vec<Cid>,
+        pins: Vec<Cid>,
+        links: AHashMap<Cid, Vec<Cid>>,
+    }
+
+    impl MockStore {
+        async fn list_blocks(self, req: ListBlocksRequest) -> Result<ListBlocksResponse> {
+            let from = req.from as usize;
+            let to = (from + req.limit as usize).min(self.blocks.len());
+            Ok(ListBlocksResponse {
+                cids: self.blocks[from..to].to_vec(),
+                next: (to < self.blocks.len()).then_some(to as u64),
+            })
+        }
+
+        async fn list_pins(self, _: ListPinsRequest) -> Result<ListPinsResponse> {
+            Ok(ListPinsResponse { cids: self.pins })
+        }
+
+        async fn get_links(self, req: GetLinksRequest) -> Result<GetLinksResponse> {
+            Ok(GetLinksResponse {
+                links: self.links.get(&req.cid).cloned(),
+            })
+        }
+    }
+
+    async fn serve(store: MockStore) -> Client {
+        let addr = StoreAddr::new_mem();
+        let server = create_server::<StoreService>(addr.clone()).await.unwrap();
+        tokio::task::spawn(async move {
+            while let Ok((req, chan)) = server.accept_one().await {
+                let server = server.clone();
+                let store = store.clone();
+                tokio::task::spawn(async move {
+                    match req {
+                        StoreRequest::ListBlocks(req) => {
+                            server
+                                .rpc_map_err(req, chan, store, MockStore::list_blocks)
+                                .await
+                        }
+                        StoreRequest::ListPins(req) => {
+                            server
+                                .rpc_map_err(req, chan, store, MockStore::list_pins)
+                                .await
+                        }
+                        StoreRequest::GetLinks(req) => {
+                            server
+                                .rpc_map_err(req, chan, store, MockStore::get_links)
+                                .await
+                        }
+                        _ => Ok(()),
+                    }
+                });
+            }
+        });
+        Client::new(RpcClientConfig {
+            store_addr: Some(addr),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+    }
+
+    /// A dag `root -> (a -> c, b)` with a missing link from `b`, and an unpinned block.
+    fn mock_store() -> MockStore {
+        let [root, a, b, c, missing, other] = ["root", "a", "b", "c", "missing", "other"].map(cid);
+        MockStore {
+            blocks: vec![root, a, b, c, other],
+            pins: vec![root],
+            links: AHashMap::from([
+                (root, vec![a, b]),
+                (a, vec![c]),
+                (b, vec![missing]),
+                (c, vec![]),
+                (other, vec![]),
+            ]),
+        }
+    }
+
+    async fn list(strategy: ReproviderStrategy, batch_size: usize) -> Vec<Vec<Cid>> {
+        let client = serve(mock_store()).await;
+        let (sender, mut batches) = mpsc::channel(1);
+        let store = client.try_store().unwrap();
+        tokio::task::spawn(list_cids(store, strategy, batch_size, sender));
+        let mut out = Vec::new();
+        while let Some(batch) = batches.recv().await {
+            out.push(batch);
+        }
+        out
+    }
+
+    #[tokio::test]
+    async fn test_list_all() {
+        let batches = list(ReproviderStrategy::All, 2).await;
+        assert_eq!(
+            batches.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        assert_eq!(batches.concat(), mock_store().blocks);
+    }
+
+    #[tokio::test]
+    async fn test_list_roots() {
+        let batches = list(ReproviderStrategy::Roots, 2).await;
+        assert_eq!(batches, vec![vec![cid("root")]]);
+    }
+
+    #[tokio::test]
+    async fn test_list_pinned() {
+        let batches = list(ReproviderStrategy::Pinned, 3).await;
+        assert_eq!(batches.iter().map(Vec::len).collect::<Vec<_>>(), vec![3, 1]);
+        let mut listed = batches.concat();
+        listed.sort();
+        let mut expected = ["root", "a", "b", "c"].map(cid).to_vec();
+        expected.sort();
+        assert_eq!(listed, expected);
+    }
+
+    fn new_reprovider(
+        strategy: ReproviderStrategy,
+        batch_size: usize,
+    ) -> (Reprovider, Kademlia<KadStore>) {
+        let local_id = PeerId::random();
+        let config = Libp2pConfig {
+            reprovider_strategy: strategy,
+            reprovider_batch_size: batch_size,
+            ..Default::default()
+        };
+        let kad = Kademlia::new(
+            local_id,
+            KadStore::memory(local_id, MemoryStoreConfig::default()),
+        );
+        (Reprovider::new(local_id, &config), kad)
+    }
+
+    #[test]
+    fn test_batching() {
+        let (mut reprovider, mut kad) = new_reprovider(ReproviderStrategy::All, 2);
+        for name in ["a", "b", "c", "d", "e"] {
+            reprovider.push(cid(name));
+        }
+
+        reprovider.poll(&mut kad);
+        assert_eq!(reprovider.running.len(), 2);
+        assert_eq!(reprovider.queue.len(), 3);
+
+        let mut announced = 0;
+        while let Some(id) = reprovider.running.keys().next().copied() {
+            reprovider.handle_start_providing(id, true, &mut kad);
+            announced += 1;
+            assert!(reprovider.running.len() <= 2);
+        }
+        assert_eq!(announced, 5);
+        assert!(reprovider.queue.is_empty());
+        // the local records are dropped again once announced
+        assert_eq!(kad.store_mut().provided().count(), 0);
+    }
+
+    #[test]
+    fn test_explicitly_provided() {
+        let (mut reprovider, mut kad) = new_reprovider(ReproviderStrategy::All, 2);
+        let key = Key::new(&cid("a").hash().to_bytes());
+        kad.start_providing(key).unwrap();
+
+        // kademlia republishes explicitly provided keys itself
+        reprovider.push(cid("a"));
+        reprovider.poll(&mut kad);
+        assert!(reprovider.running.is_empty());
+    }
+
+    #[test]
+    fn test_push_strategies() {
+        for (strategy, queued) in [
+            (ReproviderStrategy::All, 1),
+            (ReproviderStrategy::Roots, 0),
+            (ReproviderStrategy::Pinned, 0),
+        ] {
+            let (mut reprovider, _) = new_reprovider(strategy, 2);
+            reprovider.push(cid("a"));
+            assert_eq!(reprovider.queue.len(), queued, "{strategy}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_push_pinned() {
+        let client = serve(mock_store()).await;
+
+        let (mut reprovider, _) = new_reprovider(ReproviderStrategy::Roots, 2);
+        reprovider.push_pinned(cid("root"), &client);
+        assert_eq!(reprovider.queue, [cid("root")]);
+
+        for strategy in [ReproviderStrategy::All, ReproviderStrategy::Pinned] {
+            let (mut reprovider, mut kad) = new_reprovider(strategy, 8);
+            reprovider.push_pinned(cid("root"), &client);
+            assert!(reprovider.wants_pinned());
+            let batch = reprovider.next_pinned().await;
+            assert_eq!(batch.len(), 4, "{strategy}");
+            reprovider.handle_pinned(batch, &mut kad);
+            assert_eq!(reprovider.running.len(), 4, "{strategy}");
+        }
+    }
+}
@@ -18,16 +18,22 @@ use libp2p::gossipsub::{
     MessageId, TopicHash,
 };
 use libp2p::identify::Info as IdentifyInfo;
-use libp2p::kad::record::Key;
+use libp2p::kad::{record::Key, Quorum};
 use libp2p::Multiaddr;
 use libp2p::PeerId;
 use std::collections::{HashMap, HashSet};
 use std::result;
-use tokio::sync::mpsc::{channel, Sender};
+use std::time::Duration;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::oneshot;
 use tracing::{debug, info, trace};
+use zeroize::Zeroizing;
 
 use super::node::DEFAULT_PROVIDER_LIMIT;
+use crate::behaviour::{FilterList, FilterRule};
+use crate::ipns::IpnsRecord;
+use crate::pubsub::{MessageAcceptance, ReceivedMessage};
+use crate::record::RecordToValidate;
 use crate::VERSION;
 
 #[derive(Clone)]
@@ -231,6 +237,56 @@ impl P2p {
         Ok(stream)
     }
 
+    /// Wrap the inner method fetch_provider_bitswap0 to get the signature expected
+    /// by a server_streaming request.
+    #[tracing::instrument(skip(self, req))]
+    fn fetch_provider_bitswap(
+        self,
+        req: FetchProvidersBitswapRequest,
+    ) -> BoxStream<'static, RpcResult<FetchProvidersBitswapResponse>> {
+        async move {
+            let stream = self.fetch_provider_bitswap0(req).await?;
+            Ok(stream.map(|x| x.map_err(RpcError::from)))
+        }
+        .try_flatten_stream()
+        .boxed()
+    }
+
+    /// Implementation of fetch_provider_bitswap
+    async fn fetch_provider_bitswap0(
+        self,
+        req: FetchProvidersBitswapRequest,
+    ) -> anyhow::Result<BoxStream<'static, anyhow::Result<FetchProvidersBitswapResponse>>> {
+        trace!(
+            "context:{} received fetch_provider_bitswap: {}",
+            req.ctx,
+            req.cid
+        );
+        let (s, r) = channel(64);
+
+        let msg = RpcMessage::ProviderRequest {
+            key: ProviderRequestKey::Bitswap(req.ctx, req.cid),
+            response_channel: s,
+            limit: DEFAULT_PROVIDER_LIMIT,
+        };
+
+        self.sender.send(msg).await?;
+        let r = tokio_stream::wrappers::ReceiverStream::new(r);
+
+        let stream = r
+            .map(|providers| {
+                let providers = providers.map_err(|e| anyhow!(e))?;
+                let providers = providers.into_iter().collect();
+
+                anyhow::Ok::<FetchProvidersBitswapResponse>(FetchProvidersBitswapResponse {
+                    providers,
+                })
+            })
+            .boxed();
+
+        Ok(stream)
+    }
+
     #[tracing::instrument(skip(self, req))]
     async fn start_providing(self, req: StartProvidingRequest) -> Result<()> {
         trace!("received StartProviding request: {:?}", req.key);
@@ -261,6 +317,15 @@ impl P2p {
         Ok(())
     }
 
+    #[tracing::instrument(skip(self, req))]
+    async fn announce_pinned(self, req: AnnouncePinnedRequest) -> Result<()> {
+        trace!("received AnnouncePinned request: {:?}", req.cid);
+        self.sender
+            .send(RpcMessage::AnnouncePinned(req.cid))
+            .await?;
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self))]
     async fn get_listening_addrs(
         self,
@@ -325,13 +390,39 @@ impl P2p {
 
     #[tracing::instrument(skip(self, req))]
     async fn peer_disconnect(self, req: DisconnectRequest) -> Result<()> {
-        let peer_id = req.peer_id;
         let (s, r) = oneshot::channel();
-        let msg = RpcMessage::NetDisconnect(s, peer_id);
+        let msg = RpcMessage::NetDisconnect(s, req.peer_id, req.ban);
         self.sender.send(msg).await?;
-        r.await?;
+        r.await?
+    }
 
-        Ok(())
+    #[tracing::instrument(skip(self, req))]
+    async fn add_peer_filter(self, req: AddPeerFilterRequest) -> Result<()> {
+        let rule = filter_rule_from_target(req.target)?;
+        let (s, r) = oneshot::channel();
+        let msg = RpcMessage::AddPeerFilter(s, filter_list(req.list), rule);
+        self.sender.send(msg).await?;
+        r.await?
+    }
+
+    #[tracing::instrument(skip(self, req))]
+    async fn remove_peer_filter(self, req: RemovePeerFilterRequest) -> Result<()> {
+        let rule = filter_rule_from_target(req.target)?;
+        let (s, r) = oneshot::channel();
+        let msg = RpcMessage::RemovePeerFilter(s, filter_list(req.list), rule);
+        self.sender.send(msg).await?;
+        r.await?
+    }
+
+    #[tracing::instrument(skip(self, _req))]
+    async fn peer_filters(self, _req: PeerFiltersRequest) -> Result<PeerFiltersResponse> {
+        let (s, r) = oneshot::channel();
+        self.sender.send(RpcMessage::PeerFilters(s)).await?;
+        let (allow, deny) = r.await?;
+        Ok(PeerFiltersResponse {
+            allow: allow.into_iter().map(filter_target_from_rule).collect(),
+            deny: deny.into_iter().map(filter_target_from_rule).collect(),
+        })
     }
 
     #[tracing::instrument(skip(self, req))]
@@ -531,6 +622,313 @@ impl P2p {
 
         Ok(GossipsubUnsubscribeResponse { was_subscribed })
     }
+
+    /// Wrap the inner method gossipsub_messages0 to get the signature expected
+    /// by a server_streaming request.
+    #[tracing::instrument(skip(self, req))]
+    fn gossipsub_messages(
+        self,
+        req: GossipsubMessagesRequest,
+    ) -> BoxStream<'static, RpcResult<GossipsubMessagesResponse>> {
+        async move {
+            let stream = self.gossipsub_messages0(req).await?;
+            Ok(stream.map(Ok))
+        }
+        .try_flatten_stream()
+        .boxed()
+    }
+
+    /// Implementation of gossipsub_messages
+    async fn gossipsub_messages0(
+        self,
+        req: GossipsubMessagesRequest,
+    ) -> Result<BoxStream<'static, GossipsubMessagesResponse>> {
+        let topic = TopicHash::from_raw(req.topic_hash);
+
+        // the node unsubscribes once the last stream of the topic is dropped
+        let (s, r) = oneshot::channel();
+        let msg = RpcMessage::Gossipsub(GossipsubMessage::Messages(s, topic));
+        self.sender.send(msg).await?;
+        let events = r.await?.context("subscribe error")?;
+
+        let stream = tokio_stream::wrappers::ReceiverStream::new(events)
+            .filter_map(|event| {
+                let message = match event {
+                    crate::NetworkEvent::Gossipsub(crate::GossipsubEvent::Message {
+                        from,
+                        id,
+                        message,
+                    }) => Some(gossipsub_message_response(ReceivedMessage {
+                        propagation_source: from,
+                        id,
+                        message,
+                    })),
+                    _ => None,
+                };
+                futures::future::ready(message)
+            })
+            .boxed();
+
+        Ok(stream)
+    }
+
+    /// Wrap the inner method gossipsub_validate0 to get the signature expected
+    /// by a server_streaming request.
+    #[tracing::instrument(skip(self, req))]
+    fn gossipsub_validate(
+        self,
+        req: GossipsubValidateRequest,
+    ) -> BoxStream<'static, RpcResult<GossipsubMessagesResponse>> {
+        async move {
+            let stream = self.gossipsub_validate0(req).await?;
+            Ok(stream.map(Ok))
+        }
+        .try_flatten_stream()
+        .boxed()
+    }
+
+    /// Implementation of gossipsub_validate
+    async fn gossipsub_validate0(
+        self,
+        req: GossipsubValidateRequest,
+    ) -> Result<BoxStream<'static, GossipsubMessagesResponse>> {
+        let (s, r) = oneshot::channel();
+        let (messages_s, messages_r) = channel(64);
+        let msg = RpcMessage::Gossipsub(GossipsubMessage::AddValidator(
+            s,
+            TopicHash::from_raw(req.topic_hash),
+            messages_s,
+        ));
+        self.sender.send(msg).await?;
+        r.await??;
+
+        let stream = tokio_stream::wrappers::ReceiverStream::new(messages_r)
+            .map(gossipsub_message_response)
+            .boxed();
+        Ok(stream)
+    }
+
+    #[tracing::instrument(skip(self, req))]
+    async fn gossipsub_report_validation(
+        self,
+        req: GossipsubReportValidationRequest,
+    ) -> Result<()> {
+        let (s, r) = oneshot::channel();
+        let acceptance = match req.acceptance {
+            GossipsubAcceptance::Accept => MessageAcceptance::Accept,
+            GossipsubAcceptance::Reject => MessageAcceptance::Reject,
+            GossipsubAcceptance::Ignore => MessageAcceptance::Ignore,
+        };
+        let msg = RpcMessage::Gossipsub(GossipsubMessage::ReportValidation(
+            s,
+            MessageId::new(&req.message_id),
+            acceptance,
+        ));
+        self.sender.send(msg).await?;
+
+        r.await?
+    }
+
+    #[tracing::instrument(skip(self, req))]
+    async fn name_publish(self, req: NamePublishRequest) -> Result<NamePublishResponse> {
+        let (s, r) = oneshot::channel();
+        let msg = RpcMessage::NamePublish {
+            key: req.key,
+            value: req.value,
+            lifetime: req.lifetime,
+            ttl: req.ttl,
+            response_channel: s,
+        };
+        self.sender.send(msg).await?;
+
+        let (name, sequence) = r.await??;
+        Ok(NamePublishResponse { name, sequence })
+    }
+
+    #[tracing::instrument(skip(self, req))]
+    async fn name_resolve(self, req: NameResolveRequest) -> Result<NameResolveResponse> {
+        let (s, r) = oneshot::channel();
+        let msg = RpcMessage::NameResolve {
+            name: req.name,
+            response_channel: s,
+        };
+        self.sender.send(msg).await?;
+
+        let record = r.await??;
+        Ok(NameResolveResponse {
+            value: record.value,
+            sequence: record.sequence,
+        })
+    }
+
+    #[tracing::instrument(skip(self, req))]
+    async fn put_record(self, req: PutRecordRequest) -> Result<()> {
+        let (s, r) = oneshot::channel();
+        let msg = RpcMessage::PutRecord {
+            key: Key::from(req.key.to_vec()),
+            value: req.value,
+            quorum: kad_quorum(req.quorum),
+            response_channel: s,
+        };
+        self.sender.send(msg).await?;
+
+        r.await?
+    }
+
+    #[tracing::instrument(skip(self, req))]
+    async fn get_record(self, req: GetRecordRequest) -> Result<GetRecordResponse> {
+        let (s, r) = oneshot::channel();
+        let msg = RpcMessage::GetRecord {
+            key: Key::from(req.key.to_vec()),
+            quorum: kad_quorum(req.quorum),
+            response_channel: s,
+        };
+        self.sender.send(msg).await?;
+
+        let records = r.await??;
+        Ok(GetRecordResponse { records })
+    }
+
+    /// Wrap the inner method record_validate0 to get the signature expected
+    /// by a server_streaming request.
+    #[tracing::instrument(skip(self, req))]
+    fn record_validate(
+        self,
+        req: RecordValidateRequest,
+    ) -> BoxStream<'static, RpcResult<RecordValidateResponse>> {
+        async move {
+            let stream = self.record_validate0(req).await?;
+            Ok(stream.map(Ok))
+        }
+        .try_flatten_stream()
+        .boxed()
+    }
+
+    /// Implementation of record_validate
+    async fn record_validate0(
+        self,
+        req: RecordValidateRequest,
+    ) -> Result<BoxStream<'static, RecordValidateResponse>> {
+        let (s, r) = oneshot::channel();
+        let (records_s, records_r) = channel(64);
+        let msg = RpcMessage::AddRecordValidator(s, req.namespace, records_s);
+        self.sender.send(msg).await?;
+        r.await??;
+
+        let stream = tokio_stream::wrappers::ReceiverStream::new(records_r)
+            .map(|record| RecordValidateResponse {
+                id: record.id,
+                key: record.key.to_vec().into(),
+                value: record.value,
+            })
+            .boxed();
+        Ok(stream)
+    }
+
+    #[tracing::instrument(skip(self, req))]
+    async fn record_report_validation(self, req: RecordReportValidationRequest) -> Result<()> {
+        let (s, r) = oneshot::channel();
+        let msg = RpcMessage::ReportRecordValidation(s, req.id, req.valid);
+        self.sender.send(msg).await?;
+
+        r.await?
+    }
+
+    /// Wrap the inner method subscribe_network_events0 to get the signature expected
+    /// by a server_streaming request.
+    #[tracing::instrument(skip(self, req))]
+    fn subscribe_network_events(
+        self,
+        req: SubscribeNetworkEventsRequest,
+    ) -> BoxStream<'static, RpcResult<SubscribeNetworkEventsResponse>> {
+        async move {
+            let stream = self.subscribe_network_events0(req).await?;
+            Ok(stream.map(Ok))
+        }
+        .try_flatten_stream()
+        .boxed()
+    }
+
+    /// Implementation of subscribe_network_events
+    async fn subscribe_network_events0(
+        self,
+        req: SubscribeNetworkEventsRequest,
+    ) -> Result<BoxStream<'static, SubscribeNetworkEventsResponse>> {
+        let (s, r) = oneshot::channel();
+        let kinds = req.kinds.into_iter().collect();
+        self.sender
+            .send(RpcMessage::NetworkEvents(s, kinds))
+            .await?;
+        let events = tokio_stream::wrappers::ReceiverStream::new(r.await?);
+
+        // the node only sends the events of the requested kinds
+        let confirmation = SubscribeNetworkEventsResponse { event: None };
+        let stream = futures::stream::once(async { confirmation })
+            .chain(events.filter_map(|event| {
+                let event = network_event_from_node(event)
+                    .map(|event| SubscribeNetworkEventsResponse { event: Some(event) });
+                futures::future::ready(event)
+            }))
+            .boxed();
+
+        Ok(stream)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn bandwidth_stats(self, _: BandwidthStatsRequest) -> Result<BandwidthStatsResponse> {
+        let (s, r) = oneshot::channel();
+        self.sender.send(RpcMessage::BandwidthStats(s)).await?;
+        let stats = r.await?;
+        Ok(stats)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn keys(self, _: KeysRequest) -> Result<KeysResponse> {
+        let (s, r) = oneshot::channel();
+        self.sender
+            .send(RpcMessage::Keys(KeysMessage::List(s)))
+            .await?;
+        let (keys, active) = r.await??;
+        Ok(KeysResponse { keys, active })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn key_generate(self, _: KeyGenerateRequest) -> Result<KeyGenerateResponse> {
+        let (s, r) = oneshot::channel();
+        self.sender
+            .send(RpcMessage::Keys(KeysMessage::Generate(s)))
+            .await?;
+        let peer_id = r.await??;
+        Ok(KeyGenerateResponse { peer_id })
+    }
+
+    #[tracing::instrument(skip(self, req))]
+    async fn key_import(self, req: KeyImportRequest) -> Result<KeyImportResponse> {
+        let (s, r) = oneshot::channel();
+        self.sender
+            .send(RpcMessage::Keys(KeysMessage::Import(s, req.key)))
+            .await?;
+        let peer_id = r.await??;
+        Ok(KeyImportResponse { peer_id })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn key_export(self, req: KeyExportRequest) -> Result<KeyExportResponse> {
+        let (s, r) = oneshot::channel();
+        let msg = KeysMessage::Export(s, req.peer_id, req.format, req.unencrypted);
+        self.sender.send(RpcMessage::Keys(msg)).await?;
+        let key = r.await??;
+        Ok(KeyExportResponse { key })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn key_select(self, req: KeySelectRequest) -> Result<()> {
+        let (s, r) = oneshot::channel();
+        self.sender
+            .send(RpcMessage::Keys(KeysMessage::Select(s, req.peer_id)))
+            .await?;
+        r.await?
+    }
 }
 
 /// dispatch a single request from the server 
@@ -551,21 +949,42 @@ async fn dispatch(s: P2pServer, req: P2pRequest, chan: ServerSocket<P2pService>,
         GossipsubSubscribe(req) => s.rpc_map_err(req, chan, target, P2p::gossipsub_subscribe).await,
         GossipsubTopics(req) => s.rpc_map_err(req, chan, target, P2p::gossipsub_topics).await,
         GossipsubUnsubscribe(req) => s.rpc_map_err(req, chan, target, P2p::gossipsub_unsubscribe).await,
+        GossipsubMessages(req) => s.server_streaming(req, chan, target, P2p::gossipsub_messages).await,
+        GossipsubValidate(req) => s.server_streaming(req, chan, target, P2p::gossipsub_validate).await,
+        GossipsubReportValidation(req) => s.rpc_map_err(req, chan, target, P2p::gossipsub_report_validation).await,
         StopSessionBitswap(req) => s.rpc_map_err(req, chan, target, P2p::stop_session_bitswap).await,
         StartProviding(req) => s.rpc_map_err(req, chan, target, P2p::start_providing).await,
         StopProviding(req) => s.rpc_map_err(req, chan, target, P2p::stop_providing).await,
+        AnnouncePinned(req) => s.rpc_map_err(req, chan, target, P2p::announce_pinned).await,
         LocalPeerId(req) => s.rpc_map_err(req, chan, target, P2p::local_peer_id).await,
         NotifyNewBlocksBitswap(req) => s.rpc_map_err(req, chan, target, P2p::notify_new_blocks_bitswap).await,
         GetListeningAddrs(req) => s.rpc_map_err(req, chan, target, P2p::get_listening_addrs).await,
         GetPeers(req) => s.rpc_map_err(req, chan, target, P2p::get_peers).await,
         PeerConnect(req) => s.rpc_map_err(req, chan, target, P2p::peer_connect).await,
         PeerDisconnect(req) => s.rpc_map_err(req, chan, target, P2p::peer_disconnect).await,
+        AddPeerFilter(req) => s.rpc_map_err(req, chan, target, P2p::add_peer_filter).await,
+        RemovePeerFilter(req) => s.rpc_map_err(req, chan, target, P2p::remove_peer_filter).await,
+        PeerFilters(req) => s.rpc_map_err(req, chan, target, P2p::peer_filters).await,
         PeerConnectByPeerId(req) => s.rpc_map_err(req, chan, target, P2p::peer_connect_by_peer_id).await,
         Lookup(req) => s.rpc_map_err(req, chan, target, P2p::lookup).await,
         LookupLocal(req) => s.rpc_map_err(req, chan, target, P2p::lookup_local).await,
         ExternalAddrs(req) => s.rpc_map_err(req, chan, target, P2p::external_addrs).await,
         Listeners(req) => s.rpc_map_err(req, chan, target, P2p::listeners).await,
         FetchProviderDht(req) => s.server_streaming(req, chan, target, P2p::fetch_provider_dht).await,
+        FetchProviderBitswap(req) => s.server_streaming(req, chan, target, P2p::fetch_provider_bitswap).await,
+        NamePublish(req) => s.rpc_map_err(req, chan, target, P2p::name_publish).await,
+        NameResolve(req) => s.rpc_map_err(req, chan, target, P2p::name_resolve).await,
+        PutRecord(req) => s.rpc_map_err(req, chan, target, P2p::put_record).await,
+        GetRecord(req) => s.rpc_map_err(req, chan, target, P2p::get_record).await,
+        RecordValidate(req) => s.server_streaming(req, chan, target, P2p::record_validate).await,
+        RecordReportValidation(req) => s.rpc_map_err(req, chan, target, P2p::record_report_validation).await,
+        SubscribeNetworkEvents(req) => s.server_streaming(req, chan, target, P2p::subscribe_network_events).await,
+        BandwidthStats(req) => s.rpc_map_err(req, chan, target, P2p::bandwidth_stats).await,
+        Keys(req) => s.rpc_map_err(req, chan, target, P2p::keys).await,
+        KeyGenerate(req) => s.rpc_map_err(req, chan, target, P2p::key_generate).await,
+        KeyImport(req) => s.rpc_map_err(req, chan, target, P2p::key_import).await,
+        KeyExport(req) => s.rpc_map_err(req, chan, target, P2p::key_export).await,
+        KeySelect(req) => s.rpc_map_err(req, chan, target, P2p::key_select).await,
     }
 }
 
@@ -585,6 +1004,58 @@ pub(crate) async fn new(addr: P2pAddr, p2p: P2p) -> Result<()> {
     }
 }
 
+fn kad_quorum(quorum: RecordQuorum) -> Quorum {
+    match quorum {
+        RecordQuorum::One => Quorum::One,
+        RecordQuorum::Majority => Quorum::Majority,
+        RecordQuorum::All => Quorum::All,
+        RecordQuorum::N(n) => Quorum::N(n),
+    }
+}
+
+fn gossipsub_message_response(message: ReceivedMessage) -> GossipsubMessagesResponse {
+    GossipsubMessagesResponse {
+        source: message.message.source,
+        from: message.propagation_source,
+        topic_hash: message.message.topic.into_string(),
+        data: message.message.data.into(),
+        message_id: message.id.0.into(),
+    }
+}
+
+/// Converts the events of the node that are of interest to rpc clients.
+fn network_event_from_node(event: crate::NetworkEvent) -> Option<NetworkEvent> {
+    use crate::GossipsubEvent;
+
+    let event = match event {
+        crate::NetworkEvent::PeerConnected(peer_id) => NetworkEvent::PeerConnected(peer_id),
+        crate::NetworkEvent::PeerDisconnected(peer_id) => NetworkEvent::PeerDisconnected(peer_id),
+        crate::NetworkEvent::Gossipsub(GossipsubEvent::Subscribed { peer_id, topic }) => {
+            NetworkEvent::GossipsubSubscribed {
+                peer_id,
+                topic: topic.into_string(),
+            }
+        }
+        crate::NetworkEvent::Gossipsub(GossipsubEvent::Unsubscribed { peer_id, topic }) => {
+            NetworkEvent::GossipsubUnsubscribed {
+                peer_id,
+                topic: topic.into_string(),
+            }
+        }
+        crate::NetworkEvent::Gossipsub(GossipsubEvent::Message { from, id, message }) => {
+            NetworkEvent::GossipsubMessage {
+                from,
+                source: message.source,
+                id: id.0.into(),
+                topic: message.topic.into_string(),
+                data: message.data.into(),
+            }
+        }
+        crate::NetworkEvent::CancelLookupQuery(_) => return None,
+    };
+    Some(event)
+}
+
 fn peer_info_from_identify_info(i: IdentifyInfo) -> LookupResponse {
     LookupResponse {
         peer_id: i.public_key.to_peer_id(),
@@ -593,6 +1064,29 @@ fn peer_info_from_identify_info(i: IdentifyInfo) -> LookupResponse {
         listen_addrs: i.listen_addrs,
         protocols: i.protocols,
         observed_addrs: vec![i.observed_addr],
+        reachability: None,
+        confirmed_addrs: Vec::new(),
+    }
+}
+
+fn filter_list(list: PeerFilterList) -> FilterList {
+    match list {
+        PeerFilterList::Allow => FilterList::Allow,
+        PeerFilterList::Deny => FilterList::Deny,
+    }
+}
+
+fn filter_rule_from_target(target: PeerFilterTarget) -> Result<FilterRule> {
+    match target {
+        PeerFilterTarget::Peer(peer_id) => Ok(FilterRule::Peer(peer_id)),
+        PeerFilterTarget::Addr(addr) => Ok(FilterRule::Addr(addr.parse()?)),
+    }
+}
+
+fn filter_target_from_rule(rule: FilterRule) -> PeerFilterTarget {
+    match rule {
+        FilterRule::Peer(peer_id) => PeerFilterTarget::Peer(peer_id),
+        FilterRule::Addr(addr) => PeerFilterTarget::Addr(addr.to_string()),
     }
 }
 
@@ -604,6 +1098,8 @@ fn peer_info_from_lookup(l: Lookup) -> LookupResponse {
         listen_addrs: l.listen_addrs,
         protocols: l.protocols,
         observed_addrs: l.observed_addrs,
+        reachability: l.reachability,
+        confirmed_addrs: l.confirmed_addrs,
     }
 }
 
@@ -641,21 +1137,76 @@ pub enum RpcMessage {
     },
     StartProviding(oneshot::Sender<Result<libp2p::kad::QueryId>>, Key),
     StopProviding(oneshot::Sender<Result<()>>, Key),
+    AnnouncePinned(Cid),
     NetListeningAddrs(oneshot::Sender<(PeerId, Vec<Multiaddr>)>),
     NetPeers(oneshot::Sender<HashMap<PeerId, Vec<Multiaddr>>>),
     NetConnectByPeerId(oneshot::Sender<Result<()>>, PeerId),
     NetConnect(oneshot::Sender<Result<()>>, PeerId, Vec<Multiaddr>),
-    NetDisconnect(oneshot::Sender<()>, PeerId),
+    NetDisconnect(oneshot::Sender<Result<()>>, PeerId, Option<Duration>),
+    AddPeerFilter(oneshot::Sender<Result<()>>, FilterList, FilterRule),
+    RemovePeerFilter(oneshot::Sender<Result<()>>, FilterList, FilterRule),
+    PeerFilters(oneshot::Sender<(Vec<FilterRule>, Vec<FilterRule>)>),
     Gossipsub(GossipsubMessage),
+    Keys(KeysMessage),
     FindPeerOnDHT(oneshot::Sender<Result<()>>, PeerId),
     LookupPeerInfo(oneshot::Sender<Option<IdentifyInfo>>, PeerId),
     ListenForIdentify(oneshot::Sender<Result<IdentifyInfo>>, PeerId),
     CancelListenForIdentify(oneshot::Sender<()>, PeerId),
     AddressesOfPeer(oneshot::Sender<Vec<Multiaddr>>, PeerId),
     LookupLocalPeerInfo(oneshot::Sender<Lookup>),
+    NamePublish {
+        /// The keychain key to sign with, the node identity if `None`.
+        key: Option<PeerId>,
+        value: Bytes,
+        lifetime: Duration,
+        ttl: Duration,
+        response_channel: oneshot::Sender<Result<(PeerId, u64)>>,
+    },
+    NameResolve {
+        name: PeerId,
+        response_channel: oneshot::Sender<Result<IpnsRecord>>,
+    },
+    PutRecord {
+        key: Key,
+        value: Bytes,
+        quorum: Quorum,
+        response_channel: oneshot::Sender<Result<()>>,
+    },
+    GetRecord {
+        key: Key,
+        quorum: Quorum,
+        response_channel: oneshot::Sender<Result<Vec<Bytes>>>,
+    },
+    AddRecordValidator(
+        oneshot::Sender<Result<()>>,
+        String,
+        Sender<RecordToValidate>,
+    ),
+    ReportRecordValidation(oneshot::Sender<Result<()>>, u64, bool),
+    /// Subscribes to the network events of the given kinds, all if there are none.
+    NetworkEvents(
+        oneshot::Sender<Receiver<crate::NetworkEvent>>,
+        HashSet<NetworkEventKind>,
+    ),
+    BandwidthStats(oneshot::Sender<BandwidthStatsResponse>),
     Shutdown,
 }
 
+/// Key management messages, handled by the p2p node.
+#[derive(Debug)]
+pub enum KeysMessage {
+    List(oneshot::Sender<Result<(Vec<PeerId>, Option<PeerId>)>>),
+    Generate(oneshot::Sender<Result<PeerId>>),
+    Import(oneshot::Sender<Result<PeerId>>, Bytes),
+    Export(
+        oneshot::Sender<Result<Zeroizing<Vec<u8>>>>,
+        PeerId,
+        KeyFormat,
+        bool,
+    ),
+    Select(oneshot::Sender<Result<()>>, PeerId),
+}
+
 #[derive(Debug)]
 pub enum GossipsubMessage {
     AddExplicitPeer(oneshot::Sender<()>, PeerId),
@@ -670,5 +1221,17 @@ pub enum GossipsubMessage {
     RemoveExplicitPeer(oneshot::Sender<()>, PeerId),
     Subscribe(oneshot::Sender<Result<bool, SubscriptionError>>, TopicHash),
     Topics(oneshot::Sender<Vec<TopicHash>>),
+    /// Subscribes to the topic for a message stream, the subscription is held until the
+    /// last stream of the topic is dropped.
+    Messages(
+        oneshot::Sender<Result<Receiver<crate::NetworkEvent>, SubscriptionError>>,
+        TopicHash,
+    ),
     Unsubscribe(oneshot::Sender<Result<bool, PublishError>>, TopicHash),
+    AddValidator(
+        oneshot::Sender<Result<()>>,
+        TopicHash,
+        Sender<ReceivedMessage>,
+    ),
+    ReportValidation(oneshot::Sender<Result<()>>, MessageId, MessageAcceptance),
 }
@@ -1,48 +1,100 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{ensure, Context, Result};
+use futures::future::{self, Either, FutureExt, TryFutureExt};
 use iroh_rpc_client::Client;
 use libp2p::{
     core::{
         self,
         muxing::StreamMuxerBox,
-        transport::{Boxed, OrTransport},
+        transport::{Boxed, OptionalTransport, OrTransport},
+        upgrade::SelectUpgrade,
     },
-    dns,
     identity::Keypair,
-    mplex, noise, quic,
+    mplex, noise,
+    pnet::{PnetConfig, PnetError, PreSharedKey},
+    quic,
     swarm::{derive_prelude::EitherOutput, ConnectionLimits, Executor, SwarmBuilder},
     tcp, websocket,
     yamux::{self, WindowUpdateMode},
     PeerId, Swarm, Transport,
 };
 
-use crate::{behaviour::NodeBehaviour, Libp2pConfig};
+use tracing::info;
+
+use crate::bandwidth::{Bandwidth, CountingMuxer};
+use crate::behaviour::{NodeBehaviour, PeerFilter, SharedPeerFilter};
+use crate::dns::{self, TldDnsTransport};
+use crate::peering::peering_peers;
+use crate::{Libp2pConfig, Muxer};
 
 /// Builds the transport stack that LibP2P will communicate over.
+///
+/// The traffic of all connections is counted in the returned [`Bandwidth`].
 async fn build_transport(
     keypair: &Keypair,
     config: &Libp2pConfig,
-) -> (
+    peer_filter: SharedPeerFilter,
+) -> Result<(
     Boxed<(PeerId, StreamMuxerBox)>,
     Option<libp2p::relay::v2::client::Client>,
-) {
-    // TODO: make transports configurable
-
-    let port_reuse = true;
-    let connection_timeout = Duration::from_secs(30);
+    Arc<Bandwidth>,
+)> {
+    ensure!(
+        config.tcp || config.websocket || config.quic_enabled(),
+        "at least one of the tcp, websocket and quic transports must be enabled"
+    );
+    let psk = match config.swarm_key_path {
+        Some(ref path) => {
+            if config.quic {
+                info!("quic does not support private networks, disabling it");
+            }
+            Some(load_swarm_key(path).await?)
+        }
+        None => None,
+    };
+    let connection_timeout = Duration::from_secs(config.connection_timeout_secs);
 
     // TCP
-    let tcp_config = tcp::Config::default().port_reuse(port_reuse);
-    let tcp_transport = tcp::tokio::Transport::new(tcp_config.clone());
+    let tcp_config = tcp::Config::default().port_reuse(config.port_reuse);
+    let tcp_transport = if config.tcp {
+        OptionalTransport::some(tcp::tokio::Transport::new(tcp_config.clone()))
+    } else {
+        OptionalTransport::none()
+    };
 
     // Websockets
-    let ws_tcp = websocket::WsConfig::new(tcp::tokio::Transport::new(tcp_config));
+    let ws_tcp = if config.websocket {
+        OptionalTransport::some(websocket::WsConfig::new(tcp::tokio::Transport::new(
+            tcp_config,
+        )))
+    } else {
+        OptionalTransport::none()
+    };
     let tcp_ws_transport = tcp_transport.or_transport(ws_tcp);
 
     // Quic
-    let quic_config = quic::Config::new(keypair);
-    let quic_transport = quic::tokio::Transport::new(quic_config);
+    let quic_transport = if config.quic_enabled() {
+        let quic_config = quic::Config::new(keypair);
+        OptionalTransport::some(quic::tokio::Transport::new(quic_config))
+    } else {
+        OptionalTransport::none()
+    };
+
+    // Enable Relay if enabled
+    let (relay_transport, relay_client) = if config.relay_client {
+        let (relay_transport, relay_client) =
+            libp2p::relay::v2::client::Client::new_transport_and_behaviour(
+                keypair.public().to_peer_id(),
+            );
+        (OptionalTransport::some(relay_transport), Some(relay_client))
+    } else {
+        (OptionalTransport::none(), None)
+    };
 
     // Noise config for TCP & Websockets
     let auth_config = {
@@ -54,77 +106,127 @@ async fn build_transport(
     };
 
     // Stream muxer config for TCP & Websockets
-    let muxer_config = {
-        let mut mplex_config = mplex::MplexConfig::new();
-        mplex_config.set_max_buffer_size(usize::MAX);
-
-        let mut yamux_config = yamux::YamuxConfig::default();
-        yamux_config.set_max_buffer_size(16 * 1024 * 1024); // TODO: configurable
-        yamux_config.set_receive_window_size(16 * 1024 * 1024); // TODO: configurable
-        yamux_config.set_window_update_mode(WindowUpdateMode::on_receive());
-        core::upgrade::SelectUpgrade::new(yamux_config, mplex_config)
-    };
-
-    // Enable Relay if enabled
-    let (tcp_ws_transport, relay_client) = if config.relay_client {
-        let (relay_transport, relay_client) =
-            libp2p::relay::v2::client::Client::new_transport_and_behaviour(
-                keypair.public().to_peer_id(),
-            );
-
-        let transport = OrTransport::new(relay_transport, tcp_ws_transport);
-        let transport = transport
-            .upgrade(core::upgrade::Version::V1Lazy)
-            .authenticate(auth_config)
-            .multiplex(muxer_config)
+    let mut mplex_config = mplex::MplexConfig::new();
+    mplex_config.set_max_buffer_size(config.mplex_max_buffer_size.unwrap_or(usize::MAX));
+
+    let mut yamux_config = yamux::YamuxConfig::default();
+    yamux_config.set_max_buffer_size(config.yamux_max_buffer_size);
+    yamux_config.set_receive_window_size(config.yamux_receive_window_size);
+    yamux_config.set_window_update_mode(WindowUpdateMode::on_receive());
+
+    // Private network handshake, before any other protocol is negotiated
+    let tcp_ws_transport = OrTransport::new(relay_transport, tcp_ws_transport)
+        .and_then(move |socket, _| match psk {
+            Some(psk) => PnetConfig::new(psk)
+                .handshake(socket)
+                .map_ok(Either::Left)
+                .left_future(),
+            None => future::ok::<_, PnetError>(Either::Right(socket)).right_future(),
+        })
+        .upgrade(core::upgrade::Version::V1Lazy)
+        .authenticate(auth_config);
+    // the muxer listed first is preferred during negotiation
+    let tcp_ws_transport = match config.preferred_muxer {
+        Muxer::Yamux => tcp_ws_transport
+            .multiplex(SelectUpgrade::new(yamux_config, mplex_config))
             .timeout(connection_timeout)
-            .boxed();
-
-        (transport, Some(relay_client))
-    } else {
-        let tcp_transport = tcp_ws_transport
-            .upgrade(core::upgrade::Version::V1Lazy)
-            .authenticate(auth_config)
-            .multiplex(muxer_config)
-            .boxed();
-
-        (tcp_transport, None)
+            .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+            .boxed(),
+        Muxer::Mplex => tcp_ws_transport
+            .multiplex(SelectUpgrade::new(mplex_config, yamux_config))
+            .timeout(connection_timeout)
+            .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+            .boxed(),
     };
 
     // Merge in Quick
     let transport = OrTransport::new(quic_transport, tcp_ws_transport)
         .map(|o, _| match o {
             EitherOutput::First((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
-            EitherOutput::Second((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
+            EitherOutput::Second(output) => output,
         })
         .boxed();
 
-    // Setup dns resolution
+    // Count the traffic of every connection, per peer and per protocol
+    let bandwidth = Arc::new(Bandwidth::default());
+    let transport = {
+        let bandwidth = bandwidth.clone();
+        transport
+            .map(move |(peer_id, muxer), _| {
+                let muxer = CountingMuxer::new(muxer, peer_id, bandwidth.clone());
+                (peer_id, StreamMuxerBox::new(muxer))
+            })
+            .boxed()
+    };
 
-    let dns_cfg = dns::ResolverConfig::cloudflare();
-    let dns_opts = dns::ResolverOpts::default();
-    let transport = dns::TokioDnsConfig::custom(transport, dns_cfg, dns_opts)
-        .unwrap()
+    // Enforce the peer filter on all new connections, after dns resolution
+    let transport = transport
+        .and_then(move |(peer_id, muxer), endpoint| {
+            let allowed = peer_filter
+                .read()
+                .unwrap()
+                .allows(&peer_id, endpoint.get_remote_address());
+            async move {
+                if allowed {
+                    Ok((peer_id, muxer))
+                } else {
+                    Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!("connection to {peer_id} is blocked"),
+                    ))
+                }
+            }
+        })
         .boxed();
 
-    (transport, relay_client)
+    // Setup dns resolution, names under a TLD with a separate resolver are resolved first
+    let (dns_cfg, dns_opts) = dns::resolver_config(config)?;
+    let transport = libp2p::dns::TokioDnsConfig::custom(transport, dns_cfg, dns_opts)?.boxed();
+    let transport = TldDnsTransport::new(transport, &config.dns_resolver)?.boxed();
+
+    Ok((transport, relay_client, bandwidth))
+}
+
+/// Reads the pre-shared key of a private network from a swarm key file.
+async fn load_swarm_key(path: &Path) -> Result<PreSharedKey> {
+    let key = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("failed to read swarm key {}", path.display()))?;
+    let psk = key
+        .parse()
+        .with_context(|| format!("invalid swarm key {}", path.display()))?;
+    Ok(psk)
 }
 
 pub(crate) async fn build_swarm(
     config: &Libp2pConfig,
+    kad_store_path: Option<&Path>,
     keypair: &Keypair,
     rpc_client: Client,
-) -> Result<Swarm<NodeBehaviour>> {
+) -> Result<(Swarm<NodeBehaviour>, Arc<Bandwidth>)> {
     let peer_id = keypair.public().to_peer_id();
 
-    let (transport, relay_client) = build_transport(keypair, config).await;
-    let behaviour = NodeBehaviour::new(keypair, config, relay_client, rpc_client).await?;
-
+    let peer_filter = Arc::new(RwLock::new(PeerFilter::new(config)));
+    let (transport, relay_client, bandwidth) =
+        build_transport(keypair, config, peer_filter.clone()).await?;
+    let behaviour = NodeBehaviour::new(
+        keypair,
+        config,
+        kad_store_path,
+        relay_client,
+        rpc_client,
+        peer_filter,
+    )
+    .await?;
+
+    // the peering peers are not counted against the limits, the peer manager enforces them
+    // for the other peers
+    let peering = peering_peers(config).collect::<HashSet<_>>().len() as u32;
     let limits = ConnectionLimits::default()
         .with_max_pending_incoming(Some(config.max_conns_pending_in))
-        .with_max_pending_outgoing(Some(config.max_conns_pending_out))
-        .with_max_established_incoming(Some(config.max_conns_in))
-        .with_max_established_outgoing(Some(config.max_conns_out))
+        .with_max_pending_outgoing(Some(config.max_conns_pending_out + peering))
+        .with_max_established_incoming(Some(config.max_conns_in + peering))
+        .with_max_established_outgoing(Some(config.max_conns_out + peering))
         .with_max_established_per_peer(Some(config.max_conns_per_peer));
     let swarm = SwarmBuilder::with_executor(transport, behaviour, peer_id, Tokio)
         .connection_limits(limits)
@@ -133,7 +235,7 @@ pub(crate) async fn build_swarm(
         .dial_concurrency_factor(config.dial_concurrency_factor.try_into().unwrap())
         .build();
 
-    Ok(swarm)
+    Ok((swarm, bandwidth))
 }
 
 struct Tokio;
@@ -1,73 +1,30 @@
 use std::collections::HashMap;
-use std::net::{IpAddr, Ipv4Addr};
 
 use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+pub use iroh_util::dns::DnsResolverConfig as Config;
+use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
 use trust_dns_resolver::{AsyncResolver, TokioAsyncResolver};
 
 use crate::resolver::Path;
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
-pub struct Config {
-    /// Mapping from TLD to the specific instance of resolver
-    tld_resolvers: Option<HashMap<String, ResolverConfig>>,
-}
-
-impl Config {
-    pub fn empty() -> Self {
-        Config {
-            tld_resolvers: None,
-        }
-    }
-}
-
-impl Default for Config {
-    fn default() -> Self {
-        Config {
-            /// Documentation on .eth TLD lives on https://eth.link/
-            tld_resolvers: Some(HashMap::from_iter(vec![(
-                "eth".to_string(),
-                ResolverConfig::from_parts(
-                    None,
-                    vec![],
-                    NameServerConfigGroup::from_ips_https(
-                        &[
-                            IpAddr::V4(Ipv4Addr::new(104, 18, 165, 219)),
-                            IpAddr::V4(Ipv4Addr::new(104, 18, 166, 219)),
-                        ],
-                        443,
-                        "resolver.cloudflare-eth.com".to_string(),
-                        true,
-                    ),
-                ),
-            )])),
-        }
-    }
-}
-
 #[derive(Debug)]
 pub struct DnsResolver {
     default_resolver: TokioAsyncResolver,
-    tld_resolvers: Option<HashMap<String, TokioAsyncResolver>>,
+    tld_resolvers: HashMap<String, TokioAsyncResolver>,
 }
 
 impl DnsResolver {
     /// Creates resolver from its config
     pub fn from_config(dns_resolver_config: Config) -> DnsResolver {
         let tld_resolvers = dns_resolver_config
-            .tld_resolvers
-            .map(|dns_resolver_config| {
-                dns_resolver_config
-                    .into_iter()
-                    .map(|(tld, config)| {
-                        (
-                            tld,
-                            AsyncResolver::tokio(config, ResolverOpts::default()).unwrap(),
-                        )
-                    })
-                    .collect::<HashMap<_, _>>()
-            });
+            .tld_resolvers()
+            .map(|(tld, config)| {
+                (
+                    tld.to_string(),
+                    AsyncResolver::tokio(config.clone(), ResolverOpts::default()).unwrap(),
+                )
+            })
+            .collect();
         DnsResolver {
             default_resolver: AsyncResolver::tokio(
                 ResolverConfig::default(),
@@ -96,11 +53,7 @@ impl DnsResolver {
     pub async fn resolve_txt_record(&self, url: &str) -> Result<Vec<String>> {
         let tld = url.split('.').filter(|s| !s.is_empty()).last();
         let resolver = tld
-            .and_then(|tld| {
-                self.tld_resolvers
-                    .as_ref()
-                    .and_then(|tld_resolvers| tld_resolvers.get(tld))
-            })
+            .and_then(|tld| self.tld_resolvers.get(tld))
             .unwrap_or(&self.default_resolver);
         let txt_response = resolver.txt_lookup(url).await?;
         let out = txt_response.into_iter().map(|r| r.to_string()).collect();
@@ -205,6 +205,8 @@ impl FromStr for Path {
             let root = parts.next().ok_or_else(|| anyhow!("path too short"))?;
             let root = if let Ok(c) = Cid::from_str(root) {
                 CidOrDomain::Cid(c)
+            } else if let Some(multihash) = from_peer_id(root) {
+                CidOrDomain::Cid(Cid::new_v1(Codec::Libp2pKey.into(), multihash))
             } else {
                 // TODO: url validation?
                 CidOrDomain::Domain(root.to_string())
@@ -1066,8 +1068,7 @@ impl<T: ContentLoader> Resolver<T> {
                 },
                 PathType::Ipns => match current.root() {
                     CidOrDomain::Cid(ref c) => {
-                        let c = self.load_ipns_record(c).await?;
-                        current = Path::from_cid(c);
+                        current = self.load_ipns_record(c).await?;
                     }
                     CidOrDomain::Domain(ref domain) => {
                         let mut records = self.dns_resolver.resolve_dnslink(domain).await?;
@@ -1101,8 +1102,14 @@ impl<T: ContentLoader> Resolver<T> {
     }
 
     #[tracing::instrument(skip(self))]
-    async fn load_ipns_record(&self, cid: &Cid) -> Result<Cid> {
-        todo!()
+    async fn load_ipns_record(&self, cid: &Cid) -> Result<Path> {
+        let name = libp2p::PeerId::from_bytes(&cid.hash().to_bytes())
+            .with_context(|| format!("invalid ipns name {cid}"))?;
+        let value = self.loader.load_ipns_record(&name).await?;
+        let value = std::str::from_utf8(&value).context("invalid ipns record value")?;
+        value
+            .parse()
+            .with_context(|| format!("invalid ipns record value {value}"))
     }
 }
 
@@ -1362,6 +1369,77 @@ mod tests {
         }
     }
 
+    #[derive(Debug, Clone)]
+    struct IpnsLoader {
+        blocks: HashMap<Cid, Bytes>,
+        names: HashMap<libp2p::PeerId, Bytes>,
+    }
+
+    #[async_trait]
+    impl ContentLoader for IpnsLoader {
+        async fn load_cid(&self, cid: &Cid, ctx: &LoaderContext) -> Result<LoadedCid> {
+            self.blocks.load_cid(cid, ctx).await
+        }
+
+        async fn stop_session(&self, _ctx: ContextId) -> Result<()> {
+            Ok(())
+        }
+
+        async fn has_cid(&self, cid: &Cid) -> Result<bool> {
+            Ok(self.blocks.contains_key(cid))
+        }
+
+        async fn load_ipns_record(&self, name: &libp2p::PeerId) -> Result<Bytes> {
+            self.names
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("not found"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_ipns() {
+        let data = Bytes::from_static(b"hello ipns");
+        let c = Cid::new_v1(IpldCodec::Raw.into(), Code::Sha2_256.digest(&data));
+        let a: libp2p::PeerId = "12D3KooWLo6JTNKXfjkZtKf8ooLQoXVXUEeuu4YDY3CYqK6rxHXt"
+            .parse()
+            .unwrap();
+        let b: libp2p::PeerId = "12D3KooWJHxkQKX8C5KAyqEPhn2ssT2in4TExyG9SXxi519tycL9"
+            .parse()
+            .unwrap();
+        let unknown: libp2p::PeerId = "QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ"
+            .parse()
+            .unwrap();
+
+        // a points to b, which points to the content
+        let loader = IpnsLoader {
+            blocks: [(c, data.clone())].into_iter().collect(),
+            names: [
+                (a, Bytes::from(format!("/ipns/{b}"))),
+                (b, Bytes::from(format!("/ipfs/{c}"))),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let resolver = Resolver::new(loader);
+
+        for name in [a, b] {
+            let path: Path = format!("/ipns/{name}").parse().unwrap();
+            assert_eq!(path.typ(), PathType::Ipns);
+            let out = resolver.resolve(path).await.unwrap();
+            let content = read_to_vec(
+                out.pretty(resolver.clone(), OutMetrics::default(), None)
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(content, data);
+        }
+
+        let path: Path = format!("/ipns/{unknown}").parse().unwrap();
+        assert!(resolver.resolve(path).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_unixfs_basics_cid_v0() {
         // Test content
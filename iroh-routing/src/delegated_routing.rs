@@ -0,0 +1,542 @@
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use async_stream::{stream, try_stream};
+use cid::{multihash::Multihash, Cid};
+use futures::{Stream, StreamExt};
+use libp2p::{Multiaddr, PeerId};
+use lru::LruCache;
+use reqwest::{header, Client, StatusCode};
+use serde::Deserialize;
+use tracing::{debug, trace};
+use url::Url;
+
+use crate::Provider;
+
+/// Content type of the streaming responses, one JSON record per line.
+const NDJSON: &str = "application/x-ndjson";
+/// Schema of the peer records, listing the protocols of the peer.
+const SCHEMA_PEER: &str = "peer";
+/// Schema of the legacy bitswap records.
+const SCHEMA_BITSWAP: &str = "bitswap";
+const TRANSPORT_BITSWAP: &str = "transport-bitswap";
+
+/// How long the providers of a [`Cid`] are cached.
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+const CACHE_SIZE: usize = 1024;
+/// Time an endpoint has to answer a lookup, including the whole response.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest line of a NDJSON response, an endpoint sending longer ones fails.
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// Client of the delegated routing HTTP API, as specified in
+/// <https://specs.ipfs.tech/routing/http-routing-v1/>.
+///
+/// Looks up the providers of a [`Cid`] with `GET /routing/v1/providers/{cid}` on all the
+/// endpoints at once. Only the bitswap providers are returned, and they are cached for
+/// [`DEFAULT_CACHE_TTL`] when all the endpoints answered.
+#[derive(Debug, Clone)]
+pub struct DelegatedRouting {
+    endpoints: Vec<Url>,
+    client: Client,
+    cache: Arc<Mutex<LruCache<Multihash, CachedProviders>>>,
+    cache_ttl: Duration,
+}
+
+#[derive(Debug)]
+struct CachedProviders {
+    providers: Vec<Provider>,
+    expires: Instant,
+}
+
+impl DelegatedRouting {
+    pub fn new(endpoints: Vec<Url>) -> Result<Self> {
+        for endpoint in &endpoints {
+            if endpoint.cannot_be_a_base() {
+                return Err(anyhow!("invalid delegated routing endpoint {}", endpoint));
+            }
+        }
+
+        Ok(Self {
+            endpoints,
+            client: Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+            cache: Arc::new(Mutex::new(LruCache::new(
+                NonZeroUsize::new(CACHE_SIZE).unwrap(),
+            ))),
+            cache_ttl: DEFAULT_CACHE_TTL,
+        })
+    }
+
+    pub fn with_cache_ttl(mut self, cache_ttl: Duration) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    pub fn endpoints(&self) -> &[Url] {
+        &self.endpoints
+    }
+
+    /// Returns the bitswap providers of `cid` found by all the endpoints.
+    ///
+    /// Fails only when no provider was found and an endpoint failed.
+    pub async fn find_providers(&self, cid: Cid) -> Result<Vec<Provider>> {
+        let mut stream = Box::pin(self.find_providers_stream(cid));
+        let mut providers = Vec::new();
+        let mut error = None;
+        while let Some(provider) = stream.next().await {
+            match provider {
+                Ok(provider) => providers.push(provider),
+                Err(err) => error = Some(err),
+            }
+        }
+        match error {
+            Some(err) if providers.is_empty() => Err(err),
+            _ => Ok(providers),
+        }
+    }
+
+    /// Streams the bitswap providers of `cid` as the endpoints return them.
+    ///
+    /// A provider returned by several endpoints is yielded once. The error of an endpoint is
+    /// yielded in place of its providers, the other endpoints are still queried. The providers
+    /// are only cached if no endpoint failed, so that a lookup with a failed endpoint is
+    /// retried in full.
+    pub fn find_providers_stream(
+        &self,
+        cid: Cid,
+    ) -> impl Stream<Item = Result<Provider>> + Send + 'static {
+        let this = self.clone();
+        stream! {
+            if let Some(providers) = this.cached(&cid) {
+                trace!("cached providers for {}", cid);
+                for provider in providers {
+                    yield Ok(provider);
+                }
+                return;
+            }
+
+            let mut responses = futures::stream::select_all(
+                this.endpoints
+                    .iter()
+                    .map(|endpoint| endpoint_providers(this.client.clone(), endpoint, cid).boxed()),
+            );
+            let mut seen = HashSet::new();
+            let mut providers = Vec::new();
+            let mut failed = false;
+            while let Some(provider) = responses.next().await {
+                match provider {
+                    Ok(provider) => {
+                        if seen.insert(provider.id) {
+                            providers.push(provider.clone());
+                            yield Ok(provider);
+                        }
+                    }
+                    Err(err) => {
+                        debug!("delegated routing lookup of {} failed: {:?}", cid, err);
+                        failed = true;
+                        yield Err(err);
+                    }
+                }
+            }
+            if !failed {
+                this.cache(&cid, providers);
+            }
+        }
+    }
+
+    fn cached(&self, cid: &Cid) -> Option<Vec<Provider>> {
+        let mut cache = self.cache.lock().unwrap();
+        match cache.get(cid.hash()) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.providers.clone()),
+            Some(_) => {
+                cache.pop(cid.hash());
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn cache(&self, cid: &Cid, providers: Vec<Provider>) {
+        // a content that is not provided yet may be soon, do not remember the misses
+        if providers.is_empty() {
+            return;
+        }
+        self.cache.lock().unwrap().put(
+            *cid.hash(),
+            CachedProviders {
+                providers,
+                expires: Instant::now() + self.cache_ttl,
+            },
+        );
+    }
+}
+
+/// Streams the bitswap providers of `cid` returned by one endpoint, reading NDJSON responses
+/// line by line.
+///
+/// Invalid records are skipped, a line longer than [`MAX_LINE_LEN`] fails the endpoint.
+fn endpoint_providers(
+    client: Client,
+    endpoint: &Url,
+    cid: Cid,
+) -> impl Stream<Item = Result<Provider>> + Send + 'static {
+    let url = providers_url(endpoint, &cid);
+    try_stream! {
+        let url = url?;
+        trace!("requesting providers from {}", url);
+        let response = client
+            .get(url.clone())
+            .header(header::ACCEPT, format!("{NDJSON}, application/json"))
+            .send()
+            .await?;
+        // no providers
+        if response.status() != StatusCode::NOT_FOUND {
+            let response = response.error_for_status()?;
+            let ndjson = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map_or(false, |value| value.starts_with(NDJSON));
+            if ndjson {
+                let mut body = response.bytes_stream();
+                let mut buf = Vec::new();
+                while let Some(chunk) = body.next().await {
+                    buf.extend_from_slice(&chunk?);
+                    while let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+                        let line: Vec<u8> = buf.drain(..=pos).collect();
+                        if let Some(provider) = parse_line_or_skip(&url, &line) {
+                            yield provider;
+                        }
+                    }
+                    if buf.len() > MAX_LINE_LEN {
+                        Err(anyhow!("{} sent a line longer than {} bytes", url, MAX_LINE_LEN))?;
+                    }
+                }
+                if let Some(provider) = parse_line_or_skip(&url, &buf) {
+                    yield provider;
+                }
+            } else {
+                let response: ProvidersResponse = response.json().await?;
+                for record in response.providers.into_iter().flatten() {
+                    if let Some(provider) = record.into_bitswap_provider() {
+                        yield provider;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn providers_url(endpoint: &Url, cid: &Cid) -> Result<Url> {
+    let mut url = endpoint.clone();
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("invalid delegated routing endpoint {}", endpoint))?
+        .pop_if_empty()
+        .extend(["routing", "v1", "providers", &cid.to_string()]);
+    Ok(url)
+}
+
+fn parse_line(line: &[u8]) -> Result<Option<Provider>> {
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    let record: ProviderRecord = serde_json::from_slice(line)?;
+    Ok(record.into_bitswap_provider())
+}
+
+/// Parses a line like [`parse_line`], logging and skipping an invalid record instead of
+/// failing the rest of the response.
+fn parse_line_or_skip(url: &Url, line: &[u8]) -> Option<Provider> {
+    match parse_line(line) {
+        Ok(provider) => provider,
+        Err(err) => {
+            debug!("skipping invalid provider record from {}: {:?}", url, err);
+            None
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProvidersResponse {
+    #[serde(default)]
+    providers: Option<Vec<ProviderRecord>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProviderRecord {
+    schema: String,
+    #[serde(rename = "ID")]
+    id: Option<String>,
+    #[serde(default)]
+    addrs: Option<Vec<String>>,
+    /// Protocols of a `peer` record.
+    #[serde(default)]
+    protocols: Option<Vec<String>>,
+    /// Protocol of a legacy `bitswap` record.
+    #[serde(default)]
+    protocol: Option<String>,
+}
+
+impl ProviderRecord {
+    /// Returns the provider if the record announces a bitswap peer, records of unknown
+    /// schemas and invalid peer ids are skipped.
+    fn into_bitswap_provider(self) -> Option<Provider> {
+        let bitswap = match self.schema.as_str() {
+            // a peer without protocols may still speak bitswap
+            SCHEMA_PEER => self.protocols.map_or(true, |p| {
+                p.is_empty() || p.iter().any(|p| p == TRANSPORT_BITSWAP)
+            }),
+            SCHEMA_BITSWAP => self.protocol.as_deref() == Some(TRANSPORT_BITSWAP),
+            _ => false,
+        };
+        if !bitswap {
+            return None;
+        }
+        let id: PeerId = self.id?.parse().ok()?;
+        let addrs = self
+            .addrs
+            .into_iter()
+            .flatten()
+            .filter_map(|addr| addr.parse::<Multiaddr>().ok())
+            .collect();
+        Some(Provider { id, addrs })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const TEST_CID: &str = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+
+    fn peer_record(peer_id: &PeerId, protocols: &[&str]) -> String {
+        serde_json::json!({
+            "Schema": "peer",
+            "ID": peer_id.to_string(),
+            "Addrs": ["/ip4/10.0.0.1/tcp/4001"],
+            "Protocols": protocols,
+        })
+        .to_string()
+    }
+
+    /// Serves `body` to every request for the providers of [`TEST_CID`], and a 404 to the
+    /// others. Returns the url of the server and the number of requests it received.
+    async fn mock_router(content_type: &'static str, body: String) -> (Url, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+                let request = String::from_utf8_lossy(&request);
+                let response = if request
+                    .starts_with(&format!("GET /routing/v1/providers/{TEST_CID} "))
+                {
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        content_type,
+                        body.len(),
+                        body
+                    )
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                };
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.ok();
+            }
+        });
+        (url, requests)
+    }
+
+    #[test]
+    fn test_parse_records() {
+        let peer_id = PeerId::random();
+        let provider = parse_line(peer_record(&peer_id, &[TRANSPORT_BITSWAP]).as_bytes())
+            .unwrap()
+            .unwrap();
+        assert_eq!(provider.id, peer_id);
+        assert_eq!(
+            provider.addrs,
+            vec!["/ip4/10.0.0.1/tcp/4001".parse().unwrap()]
+        );
+
+        // peers without bitswap are skipped
+        assert!(
+            parse_line(peer_record(&peer_id, &["transport-graphsync-filecoinv1"]).as_bytes())
+                .unwrap()
+                .is_none()
+        );
+        // as are unknown schemas
+        let record = format!(r#"{{"Schema":"unknown","ID":"{peer_id}"}}"#);
+        assert!(parse_line(record.as_bytes()).unwrap().is_none());
+
+        let record = format!(
+            r#"{{"Schema":"bitswap","Protocol":"transport-bitswap","ID":"{peer_id}","Addrs":null}}"#
+        );
+        let provider = parse_line(record.as_bytes()).unwrap().unwrap();
+        assert_eq!(provider.id, peer_id);
+        assert!(provider.addrs.is_empty());
+
+        assert!(parse_line(b"  \n").unwrap().is_none());
+        assert!(parse_line(b"{").is_err());
+    }
+
+    #[test]
+    fn test_providers_url() {
+        let cid: Cid = TEST_CID.parse().unwrap();
+        for endpoint in ["https://example.com", "https://example.com/"] {
+            assert_eq!(
+                providers_url(&endpoint.parse().unwrap(), &cid)
+                    .unwrap()
+                    .as_str(),
+                format!("https://example.com/routing/v1/providers/{TEST_CID}")
+            );
+        }
+        assert_eq!(
+            providers_url(&"https://example.com/delegated/".parse().unwrap(), &cid)
+                .unwrap()
+                .as_str(),
+            format!("https://example.com/delegated/routing/v1/providers/{TEST_CID}")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_providers() -> Result<()> {
+        let cid: Cid = TEST_CID.parse()?;
+        let peers: Vec<_> = (0..3).map(|_| PeerId::random()).collect();
+
+        let ndjson = format!(
+            "{}\n{}\n",
+            peer_record(&peers[0], &[TRANSPORT_BITSWAP]),
+            peer_record(&peers[1], &[TRANSPORT_BITSWAP]),
+        );
+        let (ndjson_url, ndjson_requests) = mock_router(NDJSON, ndjson).await;
+        let json = format!(
+            r#"{{"Providers":[{},{}]}}"#,
+            peer_record(&peers[1], &[TRANSPORT_BITSWAP]),
+            peer_record(&peers[2], &[TRANSPORT_BITSWAP]),
+        );
+        let (json_url, json_requests) = mock_router("application/json", json).await;
+
+        let routing = DelegatedRouting::new(vec![ndjson_url, json_url])?;
+        let providers = routing.find_providers(cid).await?;
+        let found: HashSet<_> = providers.iter().map(|p| p.id).collect();
+        assert_eq!(providers.len(), 3);
+        assert_eq!(found, peers.iter().copied().collect());
+
+        // the second lookup is answered from the cache
+        let cached = routing.find_providers(cid).await?;
+        assert_eq!(cached, providers);
+        assert_eq!(ndjson_requests.load(Ordering::SeqCst), 1);
+        assert_eq!(json_requests.load(Ordering::SeqCst), 1);
+
+        // until it expires
+        let routing = routing.with_cache_ttl(Duration::ZERO);
+        routing.cache(&cid, providers);
+        routing.find_providers(cid).await?;
+        assert_eq!(ndjson_requests.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_find_providers_failures() -> Result<()> {
+        let cid: Cid = TEST_CID.parse()?;
+        let peer_id = PeerId::random();
+        let (url, requests) =
+            mock_router(NDJSON, peer_record(&peer_id, &[TRANSPORT_BITSWAP])).await;
+        // nothing listens on port 9 of localhost
+        let unreachable: Url = "http://127.0.0.1:9".parse()?;
+
+        // an endpoint failing does not hide the providers of the others
+        let routing = DelegatedRouting::new(vec![unreachable.clone(), url.clone()])?;
+        let mut stream = Box::pin(routing.find_providers_stream(cid));
+        let mut found = Vec::new();
+        let mut errors = 0;
+        while let Some(provider) = stream.next().await {
+            match provider {
+                Ok(provider) => found.push(provider.id),
+                Err(_) => errors += 1,
+            }
+        }
+        assert_eq!(found, vec![peer_id]);
+        assert_eq!(errors, 1);
+
+        // nor are they cached, the next lookup asks all the endpoints again
+        assert_eq!(routing.find_providers(cid).await?.len(), 1);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        let routing = DelegatedRouting::new(vec![unreachable])?;
+        assert!(routing.find_providers(cid).await.is_err());
+
+        // a 404 means no providers
+        let routing = DelegatedRouting::new(vec![url])?;
+        let other: Cid = "bafkqaaa".parse()?;
+        assert!(routing.find_providers(other).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_find_providers_invalid_lines() -> Result<()> {
+        let cid: Cid = TEST_CID.parse()?;
+        let peers: Vec<_> = (0..2).map(|_| PeerId::random()).collect();
+
+        // an invalid record is skipped, the lookup still succeeds and is cached
+        let ndjson = format!(
+            "{}\n{{\"Schema\":\n{}\n",
+            peer_record(&peers[0], &[TRANSPORT_BITSWAP]),
+            peer_record(&peers[1], &[TRANSPORT_BITSWAP]),
+        );
+        let (url, requests) = mock_router(NDJSON, ndjson).await;
+        let routing = DelegatedRouting::new(vec![url])?;
+        let found: Vec<_> = routing
+            .find_providers(cid)
+            .await?
+            .into_iter()
+            .map(|p| p.id)
+            .collect();
+        assert_eq!(found, peers);
+        routing.find_providers(cid).await?;
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // a line without end fails the endpoint once it is too long
+        let ndjson = format!(
+            "{}\n{}",
+            peer_record(&peers[0], &[TRANSPORT_BITSWAP]),
+            "a".repeat(MAX_LINE_LEN + 1)
+        );
+        let (url, _) = mock_router(NDJSON, ndjson).await;
+        let routing = DelegatedRouting::new(vec![url])?;
+        let mut stream = Box::pin(routing.find_providers_stream(cid));
+        assert_eq!(stream.next().await.unwrap()?.id, peers[0]);
+        assert!(stream.next().await.unwrap().is_err());
+        assert!(stream.next().await.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,16 @@
+//! Lookups of content providers outside of the DHT, shared by the p2p node and the content
+//! loaders.
+
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+
+pub mod delegated_routing;
+
+/// A peer providing some content, along with its addresses.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Provider {
+    #[serde(rename = "ID")]
+    pub id: PeerId,
+    pub addrs: Vec<Multiaddr>,
+}
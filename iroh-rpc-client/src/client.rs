@@ -89,11 +89,13 @@ impl Client {
             p2p_addr,
             store_addr,
             channels,
+            retry,
         } = cfg;
+        let retry = retry.unwrap_or_default();
 
         let gateway = if let Some(addr) = gateway_addr {
             Some(
-                GatewayClient::new(addr)
+                GatewayClient::new_with_retry(addr, retry.clone())
                     .await
                     .context("Could not create gateway rpc client")?,
             )
@@ -106,7 +108,7 @@ impl Client {
         let mut p2p = P2pLBClient::new();
         if let Some(addr) = p2p_addr {
             for _i in 0..n_channels {
-                let sc = P2pClient::new(addr.clone())
+                let sc = P2pClient::new_with_retry(addr.clone(), retry.clone())
                     .await
                     .context("Could not create store rpc client")?;
                 p2p.clients.push(sc);
@@ -116,7 +118,7 @@ impl Client {
         let mut store = StoreLBClient::new();
         if let Some(addr) = store_addr {
             for _i in 0..n_channels {
-                let sc = StoreClient::new(addr.clone())
+                let sc = StoreClient::new_with_retry(addr.clone(), retry.clone())
                     .await
                     .context("Could not create store rpc client")?;
                 store.clients.push(sc);
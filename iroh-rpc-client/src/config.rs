@@ -3,6 +3,8 @@ use iroh_rpc_types::{gateway::GatewayAddr, p2p::P2pAddr, store::StoreAddr};
 use iroh_util::insert_into_config_map;
 use serde::{Deserialize, Serialize};
 
+use crate::reconnect::RetryConfig;
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
 /// Config for the rpc Client.
 pub struct Config {
@@ -17,6 +19,10 @@ pub struct Config {
     /// If `None` defaults to `1`, not used for in-memory addresses.
     // TODO: Consider changing this to NonZeroUsize instead of Option<usize>.
     pub channels: Option<usize>,
+    /// Retry and circuit breaker settings shared by all clients.
+    ///
+    /// If `None` defaults to [`RetryConfig::default`].
+    pub retry: Option<RetryConfig>,
 }
 
 impl Source for Config {
@@ -38,6 +44,9 @@ impl Source for Config {
         if let Some(channels) = &self.channels {
             insert_into_config_map(&mut map, "channels", channels.to_string());
         }
+        if let Some(retry) = &self.retry {
+            insert_into_config_map(&mut map, "retry", retry.collect()?);
+        }
         Ok(map)
     }
 }
@@ -50,6 +59,7 @@ impl Config {
             store_addr: Some("irpc://127.0.0.1:4402".parse().unwrap()),
             /// disable load balancing by default by just having 1 channel
             channels: Some(1),
+            retry: None,
         }
     }
 }
@@ -80,6 +90,7 @@ mod tests {
             Value::new(None, default.channels.unwrap().to_string()),
         );
         let got = Config::default().collect().unwrap();
+        assert!(got.get("retry").is_none());
         for key in got.keys() {
             let left = expect.get(key).unwrap();
             let right = got.get(key).unwrap();
@@ -99,4 +110,24 @@ mod tests {
 
         assert_eq!(expect, got);
     }
+
+    #[test]
+    fn test_build_config_with_retry() {
+        let expect = Config {
+            retry: Some(RetryConfig {
+                max_attempts: 7,
+                initial_backoff: std::time::Duration::from_millis(20),
+                ..Default::default()
+            }),
+            ..Config::default_network()
+        };
+        let got: Config = ConfigBuilder::builder()
+            .add_source(expect.clone())
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(expect, got);
+    }
 }
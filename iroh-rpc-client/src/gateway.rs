@@ -1,15 +1,15 @@
 use std::fmt;
 
 use anyhow::Result;
-use async_stream::stream;
-use futures::{Stream, StreamExt};
-use iroh_rpc_types::{gateway::*, VersionRequest, WatchRequest};
+use futures::Stream;
+use iroh_rpc_types::{gateway::*, VersionRequest};
 
-use crate::{StatusType, HEALTH_POLL_WAIT};
+use crate::reconnect::{CircuitState, ReconnectingClient, RetryConfig};
+use crate::StatusType;
 
 #[derive(Clone)]
 pub struct GatewayClient {
-    client: quic_rpc::RpcClient<GatewayService, crate::ChannelTypes>,
+    client: ReconnectingClient<GatewayService>,
 }
 
 impl fmt::Debug for GatewayClient {
@@ -22,10 +22,19 @@ impl fmt::Debug for GatewayClient {
 
 impl GatewayClient {
     pub async fn new(addr: GatewayAddr) -> anyhow::Result<Self> {
-        let client = crate::open_client::<GatewayService>(addr).await?;
+        Self::new_with_retry(addr, RetryConfig::default()).await
+    }
+
+    pub async fn new_with_retry(addr: GatewayAddr, retry: RetryConfig) -> anyhow::Result<Self> {
+        let client = ReconnectingClient::new(addr, retry).await?;
         Ok(Self { client })
     }
 
+    /// Returns the state of the circuit breaker guarding this client.
+    pub fn circuit_state(&self) -> CircuitState {
+        self.client.breaker().state()
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn version(&self) -> Result<String> {
         let res = self.client.rpc(VersionRequest).await?;
@@ -42,18 +51,6 @@ impl GatewayClient {
 
     #[tracing::instrument(skip(self))]
     pub async fn watch(&self) -> impl Stream<Item = (StatusType, String)> {
-        let client = self.client.clone();
-        stream! {
-            loop {
-                let res = client.server_streaming(WatchRequest).await;
-                if let Ok(mut res) = res {
-                    while let Some(Ok(version)) = res.next().await {
-                        yield (StatusType::Serving, version.version);
-                    }
-                }
-                yield (StatusType::Down, String::new());
-                tokio::time::sleep(HEALTH_POLL_WAIT).await;
-            }
-        }
+        self.client.watch()
     }
 }
@@ -2,6 +2,7 @@ pub mod client;
 pub mod config;
 pub mod gateway;
 pub mod network;
+pub mod reconnect;
 pub mod status;
 pub mod store;
 pub use self::config::Config;
@@ -12,6 +13,7 @@ use quic_rpc::{
     transport::{combined, http2, CombinedChannelTypes, Http2ChannelTypes, MemChannelTypes},
     RpcClient, RpcServer, Service,
 };
+pub use reconnect::{CircuitState, RetryConfig, ServiceUnavailable};
 pub use status::{ClientStatus, ServiceStatus, ServiceType, StatusType, HEALTH_POLL_WAIT};
 pub use store::StoreClient;
 
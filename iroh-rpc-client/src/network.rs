@@ -1,27 +1,38 @@
 use anyhow::Result;
-use async_stream::stream;
 use bytes::Bytes;
 use cid::Cid;
 use futures::{Stream, StreamExt};
-use iroh_rpc_types::{p2p::*, VersionRequest, WatchRequest};
+use iroh_rpc_types::{p2p::*, VersionRequest};
 use libp2p::gossipsub::{MessageId, TopicHash};
 use libp2p::{Multiaddr, PeerId};
 use std::collections::{HashMap, HashSet};
-use tracing::{debug, warn};
+use std::time::Duration;
+use tracing::debug;
+use zeroize::Zeroizing;
 
-use crate::{StatusType, HEALTH_POLL_WAIT};
+use crate::reconnect::{CircuitState, ReconnectingClient, RetryConfig};
+use crate::StatusType;
 
 #[derive(Debug, Clone)]
 pub struct P2pClient {
-    client: quic_rpc::RpcClient<P2pService, crate::ChannelTypes>,
+    client: ReconnectingClient<P2pService>,
 }
 
 impl P2pClient {
     pub async fn new(addr: P2pAddr) -> anyhow::Result<Self> {
-        let client = crate::open_client(addr).await?;
+        Self::new_with_retry(addr, RetryConfig::default()).await
+    }
+
+    pub async fn new_with_retry(addr: P2pAddr, retry: RetryConfig) -> anyhow::Result<Self> {
+        let client = ReconnectingClient::new(addr, retry).await?;
         Ok(Self { client })
     }
 
+    /// Returns the state of the circuit breaker guarding this client.
+    pub fn circuit_state(&self) -> CircuitState {
+        self.client.breaker().state()
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn version(&self) -> Result<String> {
         let res = self.client.rpc(VersionRequest).await?;
@@ -101,6 +112,21 @@ impl P2pClient {
         Ok(providers_stream)
     }
 
+    #[tracing::instrument(skip(self))]
+    pub async fn fetch_providers_bitswap(
+        &self,
+        ctx: u64,
+        cid: Cid,
+    ) -> Result<impl Stream<Item = Result<HashSet<PeerId>>>> {
+        let res = self
+            .client
+            .server_streaming(FetchProvidersBitswapRequest { cid, ctx })
+            .await?;
+        let providers_stream =
+            res.map(|p| Ok(p??.providers.into_iter().collect::<HashSet<PeerId>>()));
+        Ok(providers_stream)
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn start_providing(&self, key: &Cid) -> Result<()> {
         let key = Key(key.hash().to_bytes().into());
@@ -108,6 +134,15 @@ impl P2pClient {
         Ok(())
     }
 
+    /// Queues newly pinned content for announcement by the reprovider.
+    #[tracing::instrument(skip(self))]
+    pub async fn announce_pinned(&self, cid: &Cid) -> Result<()> {
+        self.client
+            .rpc(AnnouncePinnedRequest { cid: *cid })
+            .await??;
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn stop_providing(&self, key: &Cid) -> Result<()> {
         let key = Key(key.hash().to_bytes().into());
@@ -154,6 +189,8 @@ impl P2pClient {
             protocols: res.protocols,
             agent_version: res.agent_version,
             protocol_version: res.protocol_version,
+            reachability: res.reachability,
+            confirmed_addrs: res.confirmed_addrs,
         })
     }
 
@@ -168,17 +205,48 @@ impl P2pClient {
             protocols: res.protocols,
             agent_version: res.agent_version,
             protocol_version: res.protocol_version,
+            reachability: res.reachability,
+            confirmed_addrs: res.confirmed_addrs,
         })
     }
 
     #[tracing::instrument(skip(self))]
-    pub async fn disconnect(&self, peer_id: PeerId) -> Result<()> {
-        warn!("NetDisconnect not yet implemented on p2p node");
-        let req = DisconnectRequest { peer_id };
+    pub async fn disconnect(&self, peer_id: PeerId, ban: Option<Duration>) -> Result<()> {
+        let req = DisconnectRequest { peer_id, ban };
         self.client.rpc(req).await??;
         Ok(())
     }
 
+    #[tracing::instrument(skip(self))]
+    pub async fn add_peer_filter(
+        &self,
+        list: PeerFilterList,
+        target: PeerFilterTarget,
+    ) -> Result<()> {
+        self.client
+            .rpc(AddPeerFilterRequest { list, target })
+            .await??;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn remove_peer_filter(
+        &self,
+        list: PeerFilterList,
+        target: PeerFilterTarget,
+    ) -> Result<()> {
+        self.client
+            .rpc(RemovePeerFilterRequest { list, target })
+            .await??;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn peer_filters(&self) -> Result<(Vec<PeerFilterTarget>, Vec<PeerFilterTarget>)> {
+        let res = self.client.rpc(PeerFiltersRequest).await??;
+        Ok((res.allow, res.deny))
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn shutdown(&self) -> Result<()> {
         self.client.rpc(ShutdownRequest).await??;
@@ -251,6 +319,52 @@ impl P2pClient {
         Ok(res.was_subscribed)
     }
 
+    /// Subscribes to the topic and streams the messages received on it.
+    #[tracing::instrument(skip(self))]
+    pub async fn gossipsub_messages(
+        &self,
+        topic: TopicHash,
+    ) -> Result<impl Stream<Item = Result<GossipsubMessagesResponse>>> {
+        let req = GossipsubMessagesRequest {
+            topic_hash: topic.to_string(),
+        };
+        let res = self.client.server_streaming(req).await?;
+        let messages = res.map(|res| Ok(res??));
+        Ok(messages)
+    }
+
+    /// Becomes the validator of the topic, returns the stream of messages to validate.
+    ///
+    /// Every message must be reported with [`P2pClient::gossipsub_report_validation`], it is
+    /// only forwarded once it is accepted. The validator is removed when the stream is
+    /// dropped.
+    #[tracing::instrument(skip(self))]
+    pub async fn gossipsub_validate(
+        &self,
+        topic: TopicHash,
+    ) -> Result<impl Stream<Item = Result<GossipsubMessagesResponse>>> {
+        let req = GossipsubValidateRequest {
+            topic_hash: topic.to_string(),
+        };
+        let res = self.client.server_streaming(req).await?;
+        let messages = res.map(|res| Ok(res??));
+        Ok(messages)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn gossipsub_report_validation(
+        &self,
+        message_id: MessageId,
+        acceptance: GossipsubAcceptance,
+    ) -> Result<()> {
+        let req = GossipsubReportValidationRequest {
+            message_id: message_id.0.into(),
+            acceptance,
+        };
+        self.client.rpc(req).await??;
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn gossipsub_topics(&self) -> Result<Vec<TopicHash>> {
         let res = self.client.rpc(GossipsubTopicsRequest).await??;
@@ -267,6 +381,160 @@ impl P2pClient {
         Ok(res.was_subscribed)
     }
 
+    /// Publishes an IPNS record pointing to `value`.
+    ///
+    /// The record is signed with the keychain key `key`, or the identity of the node if `None`,
+    /// and published under its peer id. Returns once the record was put into the DHT, with the
+    /// name and the sequence number of the published record.
+    #[tracing::instrument(skip(self))]
+    pub async fn name_publish(
+        &self,
+        key: Option<PeerId>,
+        value: Bytes,
+        lifetime: Duration,
+        ttl: Duration,
+    ) -> Result<(PeerId, u64)> {
+        let req = NamePublishRequest {
+            key,
+            value,
+            lifetime,
+            ttl,
+        };
+        let res = self.client.rpc(req).await??;
+        Ok((res.name, res.sequence))
+    }
+
+    /// Resolves the IPNS record for `name`, returning the value it points to.
+    #[tracing::instrument(skip(self))]
+    pub async fn name_resolve(&self, name: PeerId) -> Result<Bytes> {
+        let res = self.client.rpc(NameResolveRequest { name }).await??;
+        Ok(res.value)
+    }
+
+    /// Validates a record and puts it into the DHT, waiting until `quorum` peers stored it.
+    #[tracing::instrument(skip(self, value))]
+    pub async fn put_record(&self, key: Bytes, value: Bytes, quorum: RecordQuorum) -> Result<()> {
+        let req = PutRecordRequest { key, value, quorum };
+        self.client.rpc(req).await??;
+        Ok(())
+    }
+
+    /// Looks up the records stored under `key` in the DHT, until `quorum` valid records are
+    /// found. The best record comes first.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_record(&self, key: Bytes, quorum: RecordQuorum) -> Result<Vec<Bytes>> {
+        let res = self.client.rpc(GetRecordRequest { key, quorum }).await??;
+        Ok(res.records)
+    }
+
+    /// Becomes the validator of the DHT records under `/<namespace>/`, returns the stream of
+    /// records to validate.
+    ///
+    /// Every record must be reported with [`P2pClient::record_report_validation`], records
+    /// that are not reported in time are invalid. The validator is removed when the stream
+    /// is dropped.
+    #[tracing::instrument(skip(self))]
+    pub async fn record_validate(
+        &self,
+        namespace: String,
+    ) -> Result<impl Stream<Item = Result<RecordValidateResponse>>> {
+        let req = RecordValidateRequest { namespace };
+        let res = self.client.server_streaming(req).await?;
+        let records = res.map(|res| Ok(res??));
+        Ok(records)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn record_report_validation(&self, id: u64, valid: bool) -> Result<()> {
+        self.client
+            .rpc(RecordReportValidationRequest { id, valid })
+            .await??;
+        Ok(())
+    }
+
+    /// Subscribes to the network events of the node, only to those of the given kinds unless
+    /// `kinds` is empty.
+    ///
+    /// Returns once the subscription is active, no later event is missed.
+    #[tracing::instrument(skip(self))]
+    pub async fn subscribe_network_events(
+        &self,
+        kinds: Vec<NetworkEventKind>,
+    ) -> Result<impl Stream<Item = Result<NetworkEvent>>> {
+        let mut res = self
+            .client
+            .server_streaming(SubscribeNetworkEventsRequest { kinds })
+            .await?
+            .boxed();
+        // the first response confirms the subscription
+        match res.next().await {
+            Some(confirmation) => {
+                confirmation??;
+            }
+            None => anyhow::bail!("the subscription was closed"),
+        }
+        let events = res.filter_map(|res| async move {
+            match res {
+                Ok(Ok(res)) => res.event.map(Ok),
+                Ok(Err(err)) => Some(Err(err.into())),
+                Err(err) => Some(Err(err.into())),
+            }
+        });
+        Ok(events)
+    }
+
+    /// Returns the bytes received and sent by the node, in total, per protocol and per
+    /// connected peer.
+    #[tracing::instrument(skip(self))]
+    pub async fn bandwidth_stats(&self) -> Result<BandwidthStatsResponse> {
+        let res = self.client.rpc(BandwidthStatsRequest).await??;
+        Ok(res)
+    }
+
+    /// Lists the identity keys by their peer id, and the key that is active.
+    #[tracing::instrument(skip(self))]
+    pub async fn keys(&self) -> Result<(Vec<PeerId>, Option<PeerId>)> {
+        let res = self.client.rpc(KeysRequest).await??;
+        Ok((res.keys, res.active))
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn key_generate(&self) -> Result<PeerId> {
+        let res = self.client.rpc(KeyGenerateRequest).await??;
+        Ok(res.peer_id)
+    }
+
+    #[tracing::instrument(skip(self, key))]
+    pub async fn key_import(&self, key: Bytes) -> Result<PeerId> {
+        let res = self.client.rpc(KeyImportRequest { key }).await??;
+        Ok(res.peer_id)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn key_export(
+        &self,
+        peer_id: PeerId,
+        format: KeyFormat,
+        unencrypted: bool,
+    ) -> Result<Zeroizing<Vec<u8>>> {
+        let res = self
+            .client
+            .rpc(KeyExportRequest {
+                peer_id,
+                format,
+                unencrypted,
+            })
+            .await??;
+        Ok(res.key)
+    }
+
+    /// Makes the key the identity of the node, from its next start on.
+    #[tracing::instrument(skip(self))]
+    pub async fn key_select(&self, peer_id: PeerId) -> Result<()> {
+        self.client.rpc(KeySelectRequest { peer_id }).await??;
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn check(&self) -> (StatusType, String) {
         match self.version().await {
@@ -277,19 +545,7 @@ impl P2pClient {
 
     #[tracing::instrument(skip(self))]
     pub async fn watch(&self) -> impl Stream<Item = (StatusType, String)> {
-        let client = self.client.clone();
-        stream! {
-            loop {
-                let res = client.server_streaming(WatchRequest).await;
-                if let Ok(mut res) = res {
-                    while let Some(Ok(version)) = res.next().await {
-                        yield (StatusType::Serving, version.version);
-                    }
-                }
-                yield (StatusType::Down, String::new());
-                tokio::time::sleep(HEALTH_POLL_WAIT).await;
-            }
-        }
+        self.client.watch()
     }
 }
 
@@ -301,4 +557,8 @@ pub struct Lookup {
     pub protocol_version: String,
     pub agent_version: String,
     pub protocols: Vec<String>,
+    /// The reachability of the node, only known for the local node.
+    pub reachability: Option<Reachability>,
+    /// The external addresses AutoNAT confirmed as reachable.
+    pub confirmed_addrs: Vec<Multiaddr>,
 }
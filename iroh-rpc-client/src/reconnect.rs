@@ -0,0 +1,653 @@
+//! Reconnection, retries and circuit breaking for the rpc clients.
+//!
+//! Every service client wraps its [`quic_rpc::RpcClient`] in a [`ReconnectingClient`].  When
+//! a call can not reach the service the channel is re-opened and the call is retried with
+//! exponential backoff.  Consecutive failures trip a [`CircuitBreaker`], which marks the
+//! service as down and fails calls immediately until the service had some time to recover.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use async_stream::stream;
+use config::{ConfigError, Map, Source, Value};
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use iroh_rpc_types::{Addr, WatchRequest, WatchResponse};
+use iroh_util::insert_into_config_map;
+use quic_rpc::message::{Msg, RpcMsg, ServerStreaming};
+use quic_rpc::Service;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{open_client, ChannelTypes, ClientError, StatusType, HEALTH_POLL_WAIT};
+
+/// Configures how a client retries failed calls and when it considers a service down.
+///
+/// The durations are (de)serialized as milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Maximum number of attempts for a single call, including the first one.
+    pub max_attempts: usize,
+    /// Delay before the first retry.
+    #[serde(with = "millis")]
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay between two retries.
+    #[serde(with = "millis")]
+    pub max_backoff: Duration,
+    /// Deadline for a single call.
+    #[serde(with = "millis")]
+    pub call_timeout: Duration,
+    /// Number of consecutive failures after which the service is marked down.
+    pub failure_threshold: usize,
+    /// How long a service stays marked down before a call is let through again.
+    #[serde(with = "millis")]
+    pub reset_timeout: Duration,
+}
+
+impl Source for RetryConfig {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let mut map: Map<String, Value> = Map::new();
+        // u64 values do not roundtrip through the config crate, use signed ints
+        insert_into_config_map(&mut map, "max_attempts", self.max_attempts as i64);
+        insert_into_config_map(&mut map, "initial_backoff", as_millis(self.initial_backoff));
+        insert_into_config_map(&mut map, "max_backoff", as_millis(self.max_backoff));
+        insert_into_config_map(&mut map, "call_timeout", as_millis(self.call_timeout));
+        insert_into_config_map(&mut map, "failure_threshold", self.failure_threshold as i64);
+        insert_into_config_map(&mut map, "reset_timeout", as_millis(self.reset_timeout));
+        Ok(map)
+    }
+}
+
+fn as_millis(duration: Duration) -> i64 {
+    duration.as_millis().try_into().unwrap_or(i64::MAX)
+}
+
+mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u64(duration.as_millis().try_into().unwrap_or(u64::MAX))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Duration::from_millis(u64::deserialize(deserializer)?))
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            // the gateway gives up on requests after 120 seconds, no need to wait longer
+            call_timeout: Duration::from_secs(120),
+            failure_threshold: 5,
+            reset_timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Returns the sequence of delays to wait between the attempts of a single call.
+    pub fn backoff(&self) -> Backoff {
+        Backoff {
+            next: self.initial_backoff,
+            max: self.max_backoff,
+            remaining: self.max_attempts.saturating_sub(1),
+        }
+    }
+}
+
+/// Exponentially growing delays, capped at a maximum.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    next: Duration,
+    max: Duration,
+    remaining: usize,
+}
+
+impl Iterator for Backoff {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let current = self.next.min(self.max);
+        self.next = self.next.saturating_mul(2);
+        Some(current)
+    }
+}
+
+/// The state of a [`CircuitBreaker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Calls go through.
+    Closed,
+    /// The service is considered down, calls fail immediately.
+    Open,
+    /// The reset timeout has passed, the next call probes whether the service is back.
+    HalfOpen,
+}
+
+/// Tracks consecutive failures of calls to a single service.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    inner: Arc<Mutex<BreakerState>>,
+    failure_threshold: usize,
+    reset_timeout: Duration,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: usize,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: usize, reset_timeout: Duration) -> Self {
+        Self {
+            inner: Default::default(),
+            failure_threshold: failure_threshold.max(1),
+            reset_timeout,
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let inner = self.inner.lock().unwrap();
+        match inner.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if opened_at.elapsed() < self.reset_timeout => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Returns `true` if a call may be attempted.
+    ///
+    /// When the breaker is half open only a single probing call is let through, others are
+    /// rejected until the probe reported its outcome or the reset timeout passed again.
+    pub fn allow(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.opened_at {
+            None => true,
+            Some(opened_at) if opened_at.elapsed() < self.reset_timeout => false,
+            Some(_) => {
+                inner.opened_at = Some(Instant::now());
+                true
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.opened_at.is_some() {
+            debug!("circuit closed");
+        }
+        inner.consecutive_failures = 0;
+        inner.opened_at = None;
+    }
+
+    /// Opens the breaker immediately, regardless of the number of failures so far.
+    pub fn trip(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures = self.failure_threshold;
+        inner.opened_at = Some(Instant::now());
+    }
+
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        if inner.consecutive_failures >= self.failure_threshold {
+            if inner.opened_at.is_none() {
+                warn!(
+                    "circuit opened after {} consecutive failures",
+                    inner.consecutive_failures
+                );
+            }
+            inner.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+/// Error returned for calls to a service whose circuit breaker is open.
+#[derive(Debug)]
+pub struct ServiceUnavailable {
+    addr: String,
+}
+
+impl fmt::Display for ServiceUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "service at {} is unavailable", self.addr)
+    }
+}
+
+impl std::error::Error for ServiceUnavailable {}
+
+/// An rpc client which re-opens its channel and retries calls when the service can not be
+/// reached.
+pub(crate) struct ReconnectingClient<S: Service> {
+    addr: Addr<S>,
+    client: Arc<RwLock<quic_rpc::RpcClient<S, ChannelTypes>>>,
+    breaker: CircuitBreaker,
+    config: RetryConfig,
+}
+
+impl<S: Service> Clone for ReconnectingClient<S> {
+    fn clone(&self) -> Self {
+        Self {
+            addr: self.addr.clone(),
+            client: self.client.clone(),
+            breaker: self.breaker.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<S: Service> fmt::Debug for ReconnectingClient<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReconnectingClient")
+            .field("addr", &self.addr)
+            .field("breaker", &self.breaker)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<S: Service> ReconnectingClient<S> {
+    pub async fn new(addr: Addr<S>, config: RetryConfig) -> Result<Self> {
+        let client = open_client(addr.clone()).await?;
+        Ok(Self {
+            addr,
+            client: Arc::new(RwLock::new(client)),
+            breaker: CircuitBreaker::new(config.failure_threshold, config.reset_timeout),
+            config,
+        })
+    }
+
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    fn current(&self) -> quic_rpc::RpcClient<S, ChannelTypes> {
+        self.client.read().unwrap().clone()
+    }
+
+    async fn reconnect(&self) {
+        match open_client(self.addr.clone()).await {
+            Ok(client) => {
+                *self.client.write().unwrap() = client;
+            }
+            Err(err) => {
+                debug!("failed to reopen channel to {}: {:?}", self.addr, err);
+            }
+        }
+    }
+
+    fn check_available(&self) -> Result<()> {
+        if self.breaker.allow() {
+            Ok(())
+        } else {
+            Err(ServiceUnavailable {
+                addr: self.addr.to_string(),
+            }
+            .into())
+        }
+    }
+
+    /// Handles a failed attempt, returns the delay before the next attempt if the call should
+    /// be retried.
+    async fn on_error(&self, err: &ClientError, backoff: &mut Backoff) -> Option<Duration> {
+        self.breaker.record_failure();
+        // Only retry if the request never made it to the service, otherwise it might be
+        // executed twice.
+        if !matches!(err, ClientError::Open(_)) {
+            return None;
+        }
+        let delay = backoff.next()?;
+        debug!(
+            "rpc call to {} failed, retrying in {:?}: {:?}",
+            self.addr, delay, err
+        );
+        self.reconnect().await;
+        Some(delay)
+    }
+
+    pub async fn rpc<M>(&self, msg: M) -> Result<M::Response>
+    where
+        M: RpcMsg<S> + Clone,
+    {
+        let mut backoff = self.config.backoff();
+        loop {
+            self.check_available()?;
+            let call = self.current().rpc(msg.clone());
+            match tokio::time::timeout(self.config.call_timeout, call).await {
+                Ok(Ok(res)) => {
+                    self.breaker.record_success();
+                    return Ok(res);
+                }
+                Ok(Err(err)) => match self.on_error(&err, &mut backoff).await {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => return Err(err.into()),
+                },
+                Err(_) => {
+                    // the request may have reached the service, do not retry it
+                    self.breaker.record_failure();
+                    return Err(anyhow!(
+                        "rpc call to {} timed out after {:?}",
+                        self.addr,
+                        self.config.call_timeout
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Opens a server streaming call.
+    ///
+    /// Opening the stream is retried like [`ReconnectingClient::rpc`], the call deadline does
+    /// not apply to the items of the stream.
+    pub async fn server_streaming<M>(
+        &self,
+        msg: M,
+    ) -> Result<BoxStream<'static, Result<M::Response>>>
+    where
+        M: Msg<S, Pattern = ServerStreaming> + Clone,
+    {
+        let mut backoff = self.config.backoff();
+        loop {
+            self.check_available()?;
+            let call = self.current().server_streaming(msg.clone());
+            match tokio::time::timeout(self.config.call_timeout, call).await {
+                Ok(Ok(stream)) => {
+                    self.breaker.record_success();
+                    return Ok(stream.map(|item| item.map_err(anyhow::Error::from)).boxed());
+                }
+                Ok(Err(err)) => match self.on_error(&err, &mut backoff).await {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => return Err(err.into()),
+                },
+                Err(_) => {
+                    self.breaker.record_failure();
+                    return Err(anyhow!(
+                        "opening stream to {} timed out after {:?}",
+                        self.addr,
+                        self.config.call_timeout
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Streams the health of the service.
+    ///
+    /// The service is reported down while its health stream is lost, and while the circuit
+    /// breaker is open because calls failed.
+    pub fn watch(&self) -> impl Stream<Item = (StatusType, String)>
+    where
+        WatchRequest: Msg<S, Pattern = ServerStreaming, Response = WatchResponse>,
+    {
+        let client = self.clone();
+        stream! {
+            loop {
+                let res = client.server_streaming(WatchRequest).await;
+                if let Ok(mut res) = res {
+                    while let Some(Ok(version)) = res.next().await {
+                        if client.breaker().state() == CircuitState::Open {
+                            yield (StatusType::Down, String::new());
+                        } else {
+                            yield (StatusType::Serving, version.version);
+                        }
+                    }
+                    // lost the health stream, mark the service down until it is back
+                    client.breaker().trip();
+                }
+                yield (StatusType::Down, String::new());
+                tokio::time::sleep(HEALTH_POLL_WAIT).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StoreClient;
+    use iroh_rpc_types::{
+        store::{StoreAddr, StoreRequest, StoreService},
+        VersionRequest, VersionResponse,
+    };
+    use tokio::task::JoinHandle;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let config = RetryConfig {
+            max_attempts: 6,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            ..Default::default()
+        };
+        let delays: Vec<_> = config.backoff().collect();
+        assert_eq!(
+            delays,
+            vec![
+                Duration::from_millis(100),
+                Duration::from_millis(200),
+                Duration::from_millis(400),
+                Duration::from_millis(500),
+                Duration::from_millis(500),
+            ]
+        );
+    }
+
+    #[test]
+    fn backoff_single_attempt() {
+        let config = RetryConfig {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        assert_eq!(config.backoff().next(), None);
+    }
+
+    #[test]
+    fn circuit_breaker_opens_and_recovers() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(50));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.allow());
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allow());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        // only a single probe is let through
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn circuit_breaker_trip() {
+        let breaker = CircuitBreaker::new(5, Duration::from_secs(60));
+        breaker.trip();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allow());
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn circuit_breaker_failed_probe_reopens() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(50));
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow());
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestStore;
+
+    impl TestStore {
+        async fn version(self, _: VersionRequest) -> VersionResponse {
+            VersionResponse {
+                version: "test".to_string(),
+            }
+        }
+
+        fn watch(self, _: WatchRequest) -> impl Stream<Item = WatchResponse> {
+            stream! {
+                loop {
+                    yield WatchResponse {
+                        version: "test".to_string(),
+                    };
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }
+        }
+    }
+
+    /// Serves version and watch requests until the returned task is aborted.
+    async fn serve(addr: StoreAddr) -> JoinHandle<()> {
+        // the previous server might still be releasing the port
+        let mut attempts = 0;
+        let server = loop {
+            match crate::create_server::<StoreService>(addr.clone()).await {
+                Ok(server) => break server,
+                Err(err) if attempts < 50 => {
+                    attempts += 1;
+                    debug!("binding {} failed: {:?}", addr, err);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                Err(err) => panic!("could not bind {addr}: {err:?}"),
+            }
+        };
+        tokio::spawn(async move {
+            loop {
+                match server.accept_one().await {
+                    Ok((StoreRequest::Version(req), chan)) => {
+                        let server = server.clone();
+                        tokio::spawn(async move {
+                            server.rpc(req, chan, TestStore, TestStore::version).await
+                        });
+                    }
+                    Ok((StoreRequest::Watch(req), chan)) => {
+                        let server = server.clone();
+                        tokio::spawn(async move {
+                            server
+                                .server_streaming(req, chan, TestStore, TestStore::watch)
+                                .await
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        })
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reconnects_after_server_restart() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr: StoreAddr = format!("irpc://127.0.0.1:{port}").parse().unwrap();
+        let retry = RetryConfig {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(50),
+            call_timeout: Duration::from_secs(1),
+            failure_threshold: 1,
+            reset_timeout: Duration::from_millis(100),
+        };
+
+        let server = serve(addr.clone()).await;
+        let client = StoreClient::new_with_retry(addr.clone(), retry)
+            .await
+            .unwrap();
+        assert_eq!(client.version().await.unwrap(), "test");
+        assert_eq!(client.circuit_state(), CircuitState::Closed);
+
+        // kill the server, calls fail and the breaker opens
+        server.abort();
+        assert!(server.await.unwrap_err().is_cancelled());
+        assert!(client.version().await.is_err());
+        assert_ne!(client.circuit_state(), CircuitState::Closed);
+
+        // bring it back on the same address, the client recovers on its own
+        let server = serve(addr).await;
+        let mut version = None;
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            if let Ok(v) = client.version().await {
+                version = Some(v);
+                break;
+            }
+        }
+        assert_eq!(version.as_deref(), Some("test"));
+        assert_eq!(client.circuit_state(), CircuitState::Closed);
+        server.abort();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn watch_reports_open_breaker() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr: StoreAddr = format!("irpc://127.0.0.1:{port}").parse().unwrap();
+        let retry = RetryConfig {
+            reset_timeout: Duration::from_secs(60),
+            ..Default::default()
+        };
+
+        let server = serve(addr.clone()).await;
+        let client = ReconnectingClient::<StoreService>::new(addr, retry)
+            .await
+            .unwrap();
+        let mut watch = Box::pin(client.watch());
+        assert_eq!(
+            watch.next().await,
+            Some((StatusType::Serving, "test".to_string()))
+        );
+
+        // failing calls open the breaker while the health stream is still up
+        client.breaker().trip();
+        assert_eq!(watch.next().await, Some((StatusType::Down, String::new())));
+
+        client.breaker().record_success();
+        assert_eq!(
+            watch.next().await,
+            Some((StatusType::Serving, "test".to_string()))
+        );
+        server.abort();
+    }
+}
@@ -1,24 +1,32 @@
 use anyhow::Result;
-use async_stream::stream;
 use bytes::Bytes;
 use cid::Cid;
-use futures::{Stream, StreamExt};
-use iroh_rpc_types::{store::*, VersionRequest, WatchRequest};
+use futures::Stream;
+use iroh_rpc_types::{store::*, VersionRequest};
 
-use crate::open_client;
-use crate::{StatusType, HEALTH_POLL_WAIT};
+use crate::reconnect::{CircuitState, ReconnectingClient, RetryConfig};
+use crate::StatusType;
 
 #[derive(Debug, Clone)]
 pub struct StoreClient {
-    client: quic_rpc::RpcClient<StoreService, crate::ChannelTypes>,
+    client: ReconnectingClient<StoreService>,
 }
 
 impl StoreClient {
     pub async fn new(addr: StoreAddr) -> anyhow::Result<Self> {
-        let client = open_client(addr).await?;
+        Self::new_with_retry(addr, RetryConfig::default()).await
+    }
+
+    pub async fn new_with_retry(addr: StoreAddr, retry: RetryConfig) -> anyhow::Result<Self> {
+        let client = ReconnectingClient::new(addr, retry).await?;
         Ok(Self { client })
     }
 
+    /// Returns the state of the circuit breaker guarding this client.
+    pub fn circuit_state(&self) -> CircuitState {
+        self.client.breaker().state()
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn version(&self) -> Result<String> {
         let res = self.client.rpc(VersionRequest).await?;
@@ -65,6 +73,33 @@ impl StoreClient {
         Ok(res.size)
     }
 
+    #[tracing::instrument(skip(self))]
+    pub async fn pin(&self, cid: Cid) -> Result<()> {
+        self.client.rpc(PinRequest { cid }).await??;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn unpin(&self, cid: Cid) -> Result<()> {
+        self.client.rpc(UnpinRequest { cid }).await??;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn list_pins(&self) -> Result<Vec<Cid>> {
+        let res = self.client.rpc(ListPinsRequest).await??;
+        Ok(res.cids)
+    }
+
+    /// Lists a page of at most `limit` stored blocks, starting at position `from`.
+    ///
+    /// Returns the position of the next page as well, `None` if this was the last one.
+    #[tracing::instrument(skip(self))]
+    pub async fn list_blocks(&self, from: u64, limit: u64) -> Result<(Vec<Cid>, Option<u64>)> {
+        let res = self.client.rpc(ListBlocksRequest { from, limit }).await??;
+        Ok((res.cids, res.next))
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn check(&self) -> (StatusType, String) {
         match self.version().await {
@@ -75,18 +110,6 @@ impl StoreClient {
 
     #[tracing::instrument(skip(self))]
     pub async fn watch(&self) -> impl Stream<Item = (StatusType, String)> {
-        let client = self.client.clone();
-        stream! {
-            loop {
-                let res = client.server_streaming(WatchRequest).await;
-                if let Ok(mut res) = res {
-                    while let Some(Ok(version)) = res.next().await {
-                        yield (StatusType::Serving, version.version);
-                    }
-                }
-                yield (StatusType::Down, String::new());
-                tokio::time::sleep(HEALTH_POLL_WAIT).await;
-            }
-        }
+        self.client.watch()
     }
 }
@@ -32,7 +32,7 @@ impl From<anyhow::Error> for RpcError {
 
 pub type RpcResult<T> = std::result::Result<T, RpcError>;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WatchRequest;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -40,7 +40,7 @@ pub struct WatchResponse {
     pub version: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct VersionRequest;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -8,15 +8,18 @@ use quic_rpc::{
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::time::Duration;
+use zeroize::Zeroizing;
 
 use crate::{RpcResult, VersionRequest, VersionResponse, WatchRequest, WatchResponse};
 
 pub type P2pAddr = super::addr::Addr<P2pService>;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Key(pub Bytes);
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LocalPeerIdRequest;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -24,7 +27,7 @@ pub struct LocalPeerIdResponse {
     pub peer_id: PeerId,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ExternalAddrsRequest;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -32,7 +35,7 @@ pub struct ExternalAddrsResponse {
     pub addrs: Vec<Multiaddr>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ListenersRequest;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -40,7 +43,7 @@ pub struct ListenersResponse {
     pub addrs: Vec<Multiaddr>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BitswapRequest {
     pub cid: Cid,
     pub providers: Vec<PeerId>,
@@ -53,7 +56,7 @@ pub struct BitswapResponse {
     pub ctx: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FetchProvidersDhtRequest {
     pub key: Key,
 }
@@ -63,33 +66,49 @@ pub struct FetchProvidersDhtResponse {
     pub providers: Vec<PeerId>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FetchProvidersBitswapRequest {
+    pub cid: Cid,
+    pub ctx: u64,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
+pub struct FetchProvidersBitswapResponse {
+    pub providers: Vec<PeerId>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NotifyNewBlocksBitswapRequest {
     pub blocks: Vec<BitswapBlock>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BitswapBlock {
     pub cid: Cid,
     pub data: Bytes,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct StopSessionBitswapRequest {
     pub ctx: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct StartProvidingRequest {
     pub key: Key,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct StopProvidingRequest {
     pub key: Key,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnnouncePinnedRequest {
+    pub cid: Cid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GetListeningAddrsRequest;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -98,7 +117,7 @@ pub struct GetListeningAddrsResponse {
     pub addrs: Vec<Multiaddr>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GetPeersRequest;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -106,32 +125,70 @@ pub struct GetPeersResponse {
     pub peers: BTreeMap<PeerId, Vec<Multiaddr>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ConnectRequest {
     pub peer_id: PeerId,
     pub addrs: Vec<Multiaddr>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ConnectByPeerIdRequest {
     pub peer_id: PeerId,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DisconnectRequest {
     pub peer_id: PeerId,
+    /// Rejects connections to and from the peer for this long.
+    pub ban: Option<Duration>,
 }
 
+/// The allow or the deny list of the peer filter.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerFilterList {
+    Allow,
+    Deny,
+}
+
+/// An entry of a peer filter list.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum PeerFilterTarget {
+    Peer(PeerId),
+    /// A range of IP addresses, like `/ip4/10.0.0.0/ipcidr/8`.
+    Addr(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AddPeerFilterRequest {
+    pub list: PeerFilterList,
+    pub target: PeerFilterTarget,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RemovePeerFilterRequest {
+    pub list: PeerFilterList,
+    pub target: PeerFilterTarget,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PeerFiltersRequest;
+
 #[derive(Serialize, Deserialize, Debug)]
+pub struct PeerFiltersResponse {
+    pub allow: Vec<PeerFilterTarget>,
+    pub deny: Vec<PeerFilterTarget>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ShutdownRequest;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LookupRequest {
     pub peer_id: PeerId,
     pub addr: Option<Multiaddr>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LookupLocalRequest;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -142,14 +199,26 @@ pub struct LookupResponse {
     pub listen_addrs: Vec<Multiaddr>,
     pub protocols: Vec<String>,
     pub observed_addrs: Vec<Multiaddr>,
+    /// The reachability of the node, only known for the local node.
+    pub reachability: Option<Reachability>,
+    /// The external addresses AutoNAT confirmed as reachable.
+    pub confirmed_addrs: Vec<Multiaddr>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Whether the node can be dialed from the public internet, as determined by AutoNAT.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reachability {
+    Public,
+    Private,
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GossipsubAddExplicitPeerRequest {
     pub peer_id: PeerId,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GossipsubAllMeshPeersRequest;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -157,7 +226,7 @@ pub struct GossipsubPeersResponse {
     pub peers: Vec<PeerId>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GossipsubAllPeersRequest;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -165,12 +234,12 @@ pub struct GossipsubAllPeersResponse {
     pub all: Vec<(PeerId, Vec<String>)>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GossipsubMeshPeersRequest {
     pub topic_hash: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GossipsubPublishRequest {
     pub topic_hash: String,
     pub data: Bytes,
@@ -181,12 +250,12 @@ pub struct GossipsubPublishResponse {
     pub message_id: Bytes,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GossipsubRemoveExplicitPeerRequest {
     pub peer_id: PeerId,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GossipsubSubscribeRequest {
     pub topic_hash: String,
 }
@@ -196,7 +265,7 @@ pub struct GossipsubSubscribeResponse {
     pub was_subscribed: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GossipsubTopicsRequest;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -204,7 +273,7 @@ pub struct GossipsubTopicsResponse {
     pub topics: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GossipsubUnsubscribeRequest {
     pub topic_hash: String,
 }
@@ -214,6 +283,274 @@ pub struct GossipsubUnsubscribeResponse {
     pub was_subscribed: bool,
 }
 
+/// Subscribes to a gossipsub topic and streams the messages received on it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GossipsubMessagesRequest {
+    pub topic_hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GossipsubMessagesResponse {
+    /// The peer that published the message, if known.
+    pub source: Option<PeerId>,
+    /// The peer the message was received from.
+    pub from: PeerId,
+    pub topic_hash: String,
+    pub data: Bytes,
+    pub message_id: Bytes,
+}
+
+/// The verdict of a validator on a gossipsub message.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GossipsubAcceptance {
+    /// The message is delivered and forwarded to other peers.
+    Accept,
+    /// The message is dropped and the peer that delivered it is penalized.
+    Reject,
+    /// The message is dropped without penalizing anyone.
+    Ignore,
+}
+
+/// Makes the client the validator of a gossipsub topic. The messages received on the topic
+/// are streamed to it, and only forwarded once they are reported as accepted with a
+/// [`GossipsubReportValidationRequest`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GossipsubValidateRequest {
+    pub topic_hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GossipsubReportValidationRequest {
+    pub message_id: Bytes,
+    pub acceptance: GossipsubAcceptance,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NamePublishRequest {
+    /// The keychain key to sign with, the node identity if `None`.
+    pub key: Option<PeerId>,
+    pub value: Bytes,
+    pub lifetime: Duration,
+    pub ttl: Duration,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NamePublishResponse {
+    pub name: PeerId,
+    pub sequence: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NameResolveRequest {
+    pub name: PeerId,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NameResolveResponse {
+    pub value: Bytes,
+    pub sequence: u64,
+}
+
+/// How many peers have to take part in a DHT record operation.
+///
+/// Relative quorums refer to the replication factor of the DHT.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordQuorum {
+    One,
+    Majority,
+    All,
+    N(NonZeroUsize),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PutRecordRequest {
+    pub key: Bytes,
+    pub value: Bytes,
+    /// Number of peers that must store the record.
+    pub quorum: RecordQuorum,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetRecordRequest {
+    pub key: Bytes,
+    /// Number of valid records to collect before the lookup ends.
+    pub quorum: RecordQuorum,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetRecordResponse {
+    /// The valid records found, the best one first.
+    pub records: Vec<Bytes>,
+}
+
+/// Makes the client the validator of the DHT records under `/<namespace>/`. The records put
+/// into, received for or retrieved from the DHT are streamed to it, and only used once they
+/// are reported as valid with a [`RecordReportValidationRequest`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordValidateRequest {
+    pub namespace: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecordValidateResponse {
+    /// Identifies the record in the [`RecordReportValidationRequest`].
+    pub id: u64,
+    pub key: Bytes,
+    pub value: Bytes,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordReportValidationRequest {
+    pub id: u64,
+    pub valid: bool,
+}
+
+/// The kinds of [`NetworkEvent`]s, to filter a subscription by.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NetworkEventKind {
+    PeerConnected,
+    PeerDisconnected,
+    GossipsubSubscribed,
+    GossipsubUnsubscribed,
+    GossipsubMessage,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum NetworkEvent {
+    PeerConnected(PeerId),
+    PeerDisconnected(PeerId),
+    /// A peer subscribed to a gossipsub topic.
+    GossipsubSubscribed {
+        peer_id: PeerId,
+        topic: String,
+    },
+    /// A peer unsubscribed from a gossipsub topic.
+    GossipsubUnsubscribed {
+        peer_id: PeerId,
+        topic: String,
+    },
+    GossipsubMessage {
+        /// The peer the message was received from.
+        from: PeerId,
+        /// The peer that published the message, if known.
+        source: Option<PeerId>,
+        id: Bytes,
+        topic: String,
+        data: Bytes,
+    },
+}
+
+impl NetworkEvent {
+    pub fn kind(&self) -> NetworkEventKind {
+        match self {
+            NetworkEvent::PeerConnected(_) => NetworkEventKind::PeerConnected,
+            NetworkEvent::PeerDisconnected(_) => NetworkEventKind::PeerDisconnected,
+            NetworkEvent::GossipsubSubscribed { .. } => NetworkEventKind::GossipsubSubscribed,
+            NetworkEvent::GossipsubUnsubscribed { .. } => NetworkEventKind::GossipsubUnsubscribed,
+            NetworkEvent::GossipsubMessage { .. } => NetworkEventKind::GossipsubMessage,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SubscribeNetworkEventsRequest {
+    /// The kinds of events to receive, all events when empty.
+    pub kinds: Vec<NetworkEventKind>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SubscribeNetworkEventsResponse {
+    /// `None` in the first response, which confirms the subscription.
+    pub event: Option<NetworkEvent>,
+}
+
+/// The protocols bandwidth is accounted to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BandwidthProtocol {
+    Bitswap,
+    Kademlia,
+    Gossipsub,
+    Identify,
+    Other,
+}
+
+/// Bytes received and sent.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BandwidthTotals {
+    pub inbound: u64,
+    pub outbound: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BandwidthStatsRequest;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BandwidthStatsResponse {
+    /// The traffic since the node started.
+    pub total: BandwidthTotals,
+    /// The traffic since the node started, per protocol.
+    pub protocols: Vec<(BandwidthProtocol, BandwidthTotals)>,
+    /// The traffic of the current connections, per connected peer.
+    pub peers: Vec<(PeerId, BandwidthTotals)>,
+}
+
+/// Encodings of private keys.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// The OpenSSH format, as used by the keychain.
+    Openssh,
+    /// The libp2p protobuf encoding.
+    Protobuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeysRequest;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KeysResponse {
+    pub keys: Vec<PeerId>,
+    /// The key used as identity when the node starts.
+    pub active: Option<PeerId>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeyGenerateRequest;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KeyGenerateResponse {
+    pub peer_id: PeerId,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeyImportRequest {
+    /// A private key in any of the [`KeyFormat`]s.
+    pub key: Bytes,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KeyImportResponse {
+    pub peer_id: PeerId,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeyExportRequest {
+    pub peer_id: PeerId,
+    pub format: KeyFormat,
+    /// Allows exporting the key in the clear from a keystore protected by a passphrase.
+    pub unencrypted: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KeyExportResponse {
+    /// The private key, wiped from memory once dropped.
+    pub key: Zeroizing<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeySelectRequest {
+    pub peer_id: PeerId,
+}
+
 #[derive(Serialize, Deserialize, Debug, From, TryInto)]
 pub enum P2pRequest {
     Watch(WatchRequest),
@@ -221,6 +558,7 @@ pub enum P2pRequest {
     Shutdown(ShutdownRequest),
     FetchBitswap(BitswapRequest),
     FetchProviderDht(FetchProvidersDhtRequest),
+    FetchProviderBitswap(FetchProvidersBitswapRequest),
     StopSessionBitswap(StopSessionBitswapRequest),
     NotifyNewBlocksBitswap(NotifyNewBlocksBitswapRequest),
     GetListeningAddrs(GetListeningAddrsRequest),
@@ -228,6 +566,9 @@ pub enum P2pRequest {
     PeerConnect(ConnectRequest),
     PeerConnectByPeerId(ConnectByPeerIdRequest),
     PeerDisconnect(DisconnectRequest),
+    AddPeerFilter(AddPeerFilterRequest),
+    RemovePeerFilter(RemovePeerFilterRequest),
+    PeerFilters(PeerFiltersRequest),
     Lookup(LookupRequest),
     LookupLocal(LookupLocalRequest),
     GossipsubAddExplicitPeer(GossipsubAddExplicitPeerRequest),
@@ -239,11 +580,28 @@ pub enum P2pRequest {
     GossipsubSubscribe(GossipsubSubscribeRequest),
     GossipsubTopics(GossipsubTopicsRequest),
     GossipsubUnsubscribe(GossipsubUnsubscribeRequest),
+    GossipsubMessages(GossipsubMessagesRequest),
+    GossipsubValidate(GossipsubValidateRequest),
+    GossipsubReportValidation(GossipsubReportValidationRequest),
     StartProviding(StartProvidingRequest),
     StopProviding(StopProvidingRequest),
+    AnnouncePinned(AnnouncePinnedRequest),
     LocalPeerId(LocalPeerIdRequest),
     ExternalAddrs(ExternalAddrsRequest),
     Listeners(ListenersRequest),
+    NamePublish(NamePublishRequest),
+    NameResolve(NameResolveRequest),
+    PutRecord(PutRecordRequest),
+    GetRecord(GetRecordRequest),
+    RecordValidate(RecordValidateRequest),
+    RecordReportValidation(RecordReportValidationRequest),
+    SubscribeNetworkEvents(SubscribeNetworkEventsRequest),
+    BandwidthStats(BandwidthStatsRequest),
+    Keys(KeysRequest),
+    KeyGenerate(KeyGenerateRequest),
+    KeyImport(KeyImportRequest),
+    KeyExport(KeyExportRequest),
+    KeySelect(KeySelectRequest),
 }
 
 #[derive(Serialize, Deserialize, Debug, From, TryInto)]
@@ -252,18 +610,31 @@ pub enum P2pResponse {
     Version(VersionResponse),
     FetchBitswap(RpcResult<BitswapResponse>),
     FetchProviderDht(RpcResult<FetchProvidersDhtResponse>),
+    FetchProviderBitswap(RpcResult<FetchProvidersBitswapResponse>),
     GetListeningAddrs(RpcResult<GetListeningAddrsResponse>),
     GetPeers(RpcResult<GetPeersResponse>),
     Lookup(RpcResult<LookupResponse>),
+    PeerFilters(RpcResult<PeerFiltersResponse>),
     GossipsubPeers(RpcResult<GossipsubPeersResponse>),
     GossipsubAllPeers(RpcResult<GossipsubAllPeersResponse>),
     GossipsubPublish(RpcResult<GossipsubPublishResponse>),
     GossipsubSubscribe(RpcResult<GossipsubSubscribeResponse>),
     GossipsubTopics(RpcResult<GossipsubTopicsResponse>),
     GossipsubUnsubscribe(RpcResult<GossipsubUnsubscribeResponse>),
+    GossipsubMessages(RpcResult<GossipsubMessagesResponse>),
     LocalPeerId(RpcResult<LocalPeerIdResponse>),
     ExternalAddrs(RpcResult<ExternalAddrsResponse>),
     Listeners(RpcResult<ListenersResponse>),
+    NamePublish(RpcResult<NamePublishResponse>),
+    NameResolve(RpcResult<NameResolveResponse>),
+    GetRecord(RpcResult<GetRecordResponse>),
+    RecordValidate(RpcResult<RecordValidateResponse>),
+    SubscribeNetworkEvents(RpcResult<SubscribeNetworkEventsResponse>),
+    BandwidthStats(RpcResult<BandwidthStatsResponse>),
+    Keys(RpcResult<KeysResponse>),
+    KeyGenerate(RpcResult<KeyGenerateResponse>),
+    KeyImport(RpcResult<KeyImportResponse>),
+    KeyExport(RpcResult<KeyExportResponse>),
     UnitResult(RpcResult<()>),
 }
 
@@ -303,6 +674,14 @@ impl Msg<P2pService> for FetchProvidersDhtRequest {
     type Pattern = ServerStreaming;
 }
 
+impl Msg<P2pService> for FetchProvidersBitswapRequest {
+    type Response = RpcResult<FetchProvidersBitswapResponse>;
+
+    type Update = Self;
+
+    type Pattern = ServerStreaming;
+}
+
 impl RpcMsg<P2pService> for StopSessionBitswapRequest {
     type Response = RpcResult<()>;
 }
@@ -331,6 +710,18 @@ impl RpcMsg<P2pService> for DisconnectRequest {
     type Response = RpcResult<()>;
 }
 
+impl RpcMsg<P2pService> for AddPeerFilterRequest {
+    type Response = RpcResult<()>;
+}
+
+impl RpcMsg<P2pService> for RemovePeerFilterRequest {
+    type Response = RpcResult<()>;
+}
+
+impl RpcMsg<P2pService> for PeerFiltersRequest {
+    type Response = RpcResult<PeerFiltersResponse>;
+}
+
 impl RpcMsg<P2pService> for LookupRequest {
     type Response = RpcResult<LookupResponse>;
 }
@@ -371,6 +762,26 @@ impl RpcMsg<P2pService> for GossipsubUnsubscribeRequest {
     type Response = RpcResult<GossipsubUnsubscribeResponse>;
 }
 
+impl Msg<P2pService> for GossipsubMessagesRequest {
+    type Response = RpcResult<GossipsubMessagesResponse>;
+
+    type Update = Self;
+
+    type Pattern = ServerStreaming;
+}
+
+impl Msg<P2pService> for GossipsubValidateRequest {
+    type Response = RpcResult<GossipsubMessagesResponse>;
+
+    type Update = Self;
+
+    type Pattern = ServerStreaming;
+}
+
+impl RpcMsg<P2pService> for GossipsubReportValidationRequest {
+    type Response = RpcResult<()>;
+}
+
 impl RpcMsg<P2pService> for GossipsubRemoveExplicitPeerRequest {
     type Response = RpcResult<()>;
 }
@@ -383,6 +794,10 @@ impl RpcMsg<P2pService> for StopProvidingRequest {
     type Response = RpcResult<()>;
 }
 
+impl RpcMsg<P2pService> for AnnouncePinnedRequest {
+    type Response = RpcResult<()>;
+}
+
 impl RpcMsg<P2pService> for LocalPeerIdRequest {
     type Response = RpcResult<LocalPeerIdResponse>;
 }
@@ -394,3 +809,63 @@ impl RpcMsg<P2pService> for ExternalAddrsRequest {
 impl RpcMsg<P2pService> for ListenersRequest {
     type Response = RpcResult<ListenersResponse>;
 }
+
+impl RpcMsg<P2pService> for NamePublishRequest {
+    type Response = RpcResult<NamePublishResponse>;
+}
+
+impl RpcMsg<P2pService> for NameResolveRequest {
+    type Response = RpcResult<NameResolveResponse>;
+}
+
+impl RpcMsg<P2pService> for PutRecordRequest {
+    type Response = RpcResult<()>;
+}
+
+impl RpcMsg<P2pService> for GetRecordRequest {
+    type Response = RpcResult<GetRecordResponse>;
+}
+
+impl Msg<P2pService> for RecordValidateRequest {
+    type Response = RpcResult<RecordValidateResponse>;
+
+    type Update = Self;
+
+    type Pattern = ServerStreaming;
+}
+
+impl RpcMsg<P2pService> for RecordReportValidationRequest {
+    type Response = RpcResult<()>;
+}
+
+impl Msg<P2pService> for SubscribeNetworkEventsRequest {
+    type Response = RpcResult<SubscribeNetworkEventsResponse>;
+
+    type Update = Self;
+
+    type Pattern = ServerStreaming;
+}
+
+impl RpcMsg<P2pService> for BandwidthStatsRequest {
+    type Response = RpcResult<BandwidthStatsResponse>;
+}
+
+impl RpcMsg<P2pService> for KeysRequest {
+    type Response = RpcResult<KeysResponse>;
+}
+
+impl RpcMsg<P2pService> for KeyGenerateRequest {
+    type Response = RpcResult<KeyGenerateResponse>;
+}
+
+impl RpcMsg<P2pService> for KeyImportRequest {
+    type Response = RpcResult<KeyImportResponse>;
+}
+
+impl RpcMsg<P2pService> for KeyExportRequest {
+    type Response = RpcResult<KeyExportResponse>;
+}
+
+impl RpcMsg<P2pService> for KeySelectRequest {
+    type Response = RpcResult<()>;
+}
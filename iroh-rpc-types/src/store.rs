@@ -13,7 +13,7 @@ use crate::{RpcResult, VersionRequest, VersionResponse, WatchRequest, WatchRespo
 
 pub type StoreAddr = super::addr::Addr<StoreService>;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct PutRequest {
     pub cid: Cid,
     pub blob: Bytes,
@@ -30,12 +30,12 @@ impl fmt::Debug for PutRequest {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PutManyRequest {
     pub blocks: Vec<PutRequest>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GetRequest {
     pub cid: Cid,
 }
@@ -45,7 +45,7 @@ pub struct GetResponse {
     pub data: Option<Bytes>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct HasRequest {
     pub cid: Cid,
 }
@@ -55,7 +55,7 @@ pub struct HasResponse {
     pub has: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GetLinksRequest {
     pub cid: Cid,
 }
@@ -65,7 +65,7 @@ pub struct GetLinksResponse {
     pub links: Option<Vec<Cid>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GetSizeRequest {
     pub cid: Cid,
 }
@@ -75,6 +75,38 @@ pub struct GetSizeResponse {
     pub size: Option<u64>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PinRequest {
+    pub cid: Cid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UnpinRequest {
+    pub cid: Cid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ListPinsRequest;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ListPinsResponse {
+    pub cids: Vec<Cid>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ListBlocksRequest {
+    /// Position to start listing from, `0` for the first block.
+    pub from: u64,
+    pub limit: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ListBlocksResponse {
+    pub cids: Vec<Cid>,
+    /// Position of the next page, `None` if all blocks have been listed.
+    pub next: Option<u64>,
+}
+
 #[derive(Serialize, Deserialize, Debug, From, TryInto)]
 pub enum StoreRequest {
     Watch(WatchRequest),
@@ -85,6 +117,10 @@ pub enum StoreRequest {
     Has(HasRequest),
     GetLinks(GetLinksRequest),
     GetSize(GetSizeRequest),
+    Pin(PinRequest),
+    Unpin(UnpinRequest),
+    ListPins(ListPinsRequest),
+    ListBlocks(ListBlocksRequest),
 }
 
 #[derive(Serialize, Deserialize, Debug, From, TryInto)]
@@ -95,6 +131,8 @@ pub enum StoreResponse {
     Has(RpcResult<HasResponse>),
     GetLinks(RpcResult<GetLinksResponse>),
     GetSize(RpcResult<GetSizeResponse>),
+    ListPins(RpcResult<ListPinsResponse>),
+    ListBlocks(RpcResult<ListBlocksResponse>),
     Unit(()),
     UnitResult(RpcResult<()>),
 }
@@ -143,3 +181,19 @@ impl RpcMsg<StoreService> for GetLinksRequest {
 impl RpcMsg<StoreService> for GetSizeRequest {
     type Response = RpcResult<GetSizeResponse>;
 }
+
+impl RpcMsg<StoreService> for PinRequest {
+    type Response = RpcResult<()>;
+}
+
+impl RpcMsg<StoreService> for UnpinRequest {
+    type Response = RpcResult<()>;
+}
+
+impl RpcMsg<StoreService> for ListPinsRequest {
+    type Response = RpcResult<ListPinsResponse>;
+}
+
+impl RpcMsg<StoreService> for ListBlocksRequest {
+    type Response = RpcResult<ListBlocksResponse>;
+}
@@ -141,12 +141,14 @@ impl P2pNode {
             store_addr: Some(rpc_store_addr_client.clone()),
             gateway_addr: None,
             channels: Some(1),
+            retry: None,
         };
         let rpc_p2p_client_config = iroh_rpc_client::Config {
             p2p_addr: Some(rpc_p2p_addr_client.clone()),
             store_addr: Some(rpc_store_addr_client.clone()),
             gateway_addr: None,
             channels: Some(1),
+            retry: None,
         };
         let mut libp2p_config = config::Libp2pConfig::default();
         libp2p_config.listening_multiaddrs =
@@ -163,6 +165,10 @@ impl P2pNode {
             libp2p: libp2p_config,
             rpc_client: rpc_p2p_client_config.clone(),
             key_store_path: db_path.parent().unwrap().to_path_buf(),
+            key_passphrase_env: None,
+            key_passphrase_path: None,
+            kad_store_path: None,
+            peerstore_path: None,
         };
 
         let rpc = Client::new(rpc_p2p_client_config).await?;
@@ -7,8 +7,9 @@ use iroh_rpc_client::{create_server, ServerError, ServerSocket, StoreServer, HEA
 use iroh_rpc_types::{
     store::{
         GetLinksRequest, GetLinksResponse, GetRequest, GetResponse, GetSizeRequest,
-        GetSizeResponse, HasRequest, HasResponse, PutManyRequest, PutRequest, StoreAddr,
-        StoreRequest, StoreService,
+        GetSizeResponse, HasRequest, HasResponse, ListBlocksRequest, ListBlocksResponse,
+        ListPinsRequest, ListPinsResponse, PinRequest, PutManyRequest, PutRequest, StoreAddr,
+        StoreRequest, StoreService, UnpinRequest,
     },
     VersionRequest, VersionResponse, WatchRequest, WatchResponse,
 };
@@ -115,6 +116,38 @@ impl RpcStore {
             })
             .await
     }
+
+    #[tracing::instrument(skip(self))]
+    async fn pin(self, req: PinRequest) -> Result<()> {
+        let cid = req.cid;
+        self.0.spawn_blocking(move |x| x.pin(&cid)).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn unpin(self, req: UnpinRequest) -> Result<()> {
+        let cid = req.cid;
+        self.0.spawn_blocking(move |x| x.unpin(&cid)).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn list_pins(self, _: ListPinsRequest) -> Result<ListPinsResponse> {
+        self.0
+            .spawn_blocking(move |x| {
+                let cids = x.pins()?;
+                Ok(ListPinsResponse { cids })
+            })
+            .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn list_blocks(self, req: ListBlocksRequest) -> Result<ListBlocksResponse> {
+        self.0
+            .spawn_blocking(move |x| {
+                let (cids, next) = x.blocks(req.from, req.limit as usize)?;
+                Ok(ListBlocksResponse { cids, next })
+            })
+            .await
+    }
 }
 
 /// dispatch a single request from the server 
@@ -130,6 +163,10 @@ async fn dispatch(s: StoreServer, req: StoreRequest, chan: ServerSocket<StoreSer
         Has(req) => s.rpc_map_err(req, chan, target, RpcStore::has).await,
         GetLinks(req) => s.rpc_map_err(req, chan, target, RpcStore::get_links).await,
         GetSize(req) => s.rpc_map_err(req, chan, target, RpcStore::get_size).await,
+        Pin(req) => s.rpc_map_err(req, chan, target, RpcStore::pin).await,
+        Unpin(req) => s.rpc_map_err(req, chan, target, RpcStore::unpin).await,
+        ListPins(req) => s.rpc_map_err(req, chan, target, RpcStore::list_pins).await,
+        ListBlocks(req) => s.rpc_map_err(req, chan, target, RpcStore::list_blocks).await,
     }
 }
 
@@ -18,7 +18,9 @@ use smallvec::SmallVec;
 use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use tokio::task;
 
-use crate::cf::{GraphV0, MetadataV0, CF_BLOBS_V0, CF_GRAPH_V0, CF_ID_V0, CF_METADATA_V0};
+use crate::cf::{
+    GraphV0, MetadataV0, CF_BLOBS_V0, CF_GRAPH_V0, CF_ID_V0, CF_METADATA_V0, CF_PINS_V0,
+};
 use crate::Config;
 
 #[derive(Clone, Debug)]
@@ -123,6 +125,10 @@ impl Store {
                 let opts = Options::default();
                 db.create_cf(CF_ID_V0, &opts)?;
             }
+            {
+                let opts = Options::default();
+                db.create_cf(CF_PINS_V0, &opts)?;
+            }
 
             Ok(db)
         })
@@ -142,6 +148,8 @@ impl Store {
     pub async fn open(config: Config) -> Result<Self> {
         let (mut options, cache) = default_options();
         options.create_if_missing(false);
+        // stores created by older versions do not have all column families yet
+        options.create_missing_column_families(true);
         // TODO: find a way to read existing options
 
         let path = config.path.clone();
@@ -149,7 +157,13 @@ impl Store {
             let db = RocksDb::open_cf(
                 &options,
                 path,
-                [CF_BLOBS_V0, CF_METADATA_V0, CF_GRAPH_V0, CF_ID_V0],
+                [
+                    CF_BLOBS_V0,
+                    CF_METADATA_V0,
+                    CF_GRAPH_V0,
+                    CF_ID_V0,
+                    CF_PINS_V0,
+                ],
             )?;
 
             // read last inserted id
@@ -225,6 +239,32 @@ impl Store {
         self.read_store()?.get_links(cid)
     }
 
+    /// Pins the given root, the block itself must already be stored.
+    #[tracing::instrument(skip(self))]
+    pub fn pin(&self, cid: &Cid) -> Result<()> {
+        self.write_store()?.pin(cid)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub fn unpin(&self, cid: &Cid) -> Result<()> {
+        self.write_store()?.unpin(cid)
+    }
+
+    /// Returns all pinned roots.
+    #[tracing::instrument(skip(self))]
+    pub fn pins(&self) -> Result<Vec<Cid>> {
+        self.read_store()?.pins()
+    }
+
+    /// Lists the cids of the stored blocks in insertion order, starting at position `from`.
+    ///
+    /// Returns at most `limit` cids together with the position to continue from, which is
+    /// `None` once all blocks are listed.
+    #[tracing::instrument(skip(self))]
+    pub fn blocks(&self, from: u64, limit: usize) -> Result<(Vec<Cid>, Option<u64>)> {
+        self.read_store()?.blocks(from, limit)
+    }
+
     #[tracing::instrument(skip(self))]
     pub fn consistency_check(&self) -> Result<Vec<String>> {
         self.read_store()?.consistency_check()
@@ -292,6 +332,7 @@ struct ColumnFamilies<'a> {
     metadata: &'a ColumnFamily,
     graph: &'a ColumnFamily,
     blobs: &'a ColumnFamily,
+    pins: &'a ColumnFamily,
 }
 
 impl<'a> ColumnFamilies<'a> {
@@ -309,6 +350,9 @@ impl<'a> ColumnFamilies<'a> {
             blobs: db
                 .cf_handle(CF_BLOBS_V0)
                 .context("missing column family: blobs")?,
+            pins: db
+                .cf_handle(CF_PINS_V0)
+                .context("missing column family: pins")?,
         })
     }
 }
@@ -405,6 +449,25 @@ impl<'a> WriteStore<'a> {
         Ok(())
     }
 
+    fn pin(&mut self, cid: &Cid) -> Result<()> {
+        let id = self
+            .get_id(cid)?
+            .with_context(|| format!("cannot pin {cid}: block not found"))?;
+        let id_bytes = id.to_be_bytes();
+        if self.db.get_pinned_cf(self.cf.blobs, id_bytes)?.is_none() {
+            bail!("cannot pin {cid}: block not found");
+        }
+        self.db.put_cf(self.cf.pins, id_bytes, b"")?;
+        Ok(())
+    }
+
+    fn unpin(&mut self, cid: &Cid) -> Result<()> {
+        if let Some(id) = self.get_id(cid)? {
+            self.db.delete_cf(self.cf.pins, id.to_be_bytes())?;
+        }
+        Ok(())
+    }
+
     /// Takes a list of cids and gives them ids, which are both stored and then returned.
     #[tracing::instrument(skip(self, cids))]
     fn ensure_id_many<I>(&mut self, cids: I) -> Result<Vec<u64>>
@@ -657,6 +720,52 @@ impl<'a> ReadStore<'a> {
         }
     }
 
+    fn pins(&self) -> Result<Vec<Cid>> {
+        let mut pins = Vec::new();
+        for elem in self.db.iterator_cf(self.cf.pins, IteratorMode::Start) {
+            let (key, _) = elem?;
+            let id = u64::from_be_bytes(key[..8].try_into()?);
+            let cid = self
+                .get_cid_by_id(id)?
+                .ok_or_else(|| anyhow!("invalid pin: {}", id))?;
+            pins.push(cid);
+        }
+        Ok(pins)
+    }
+
+    fn blocks(&self, from: u64, limit: usize) -> Result<(Vec<Cid>, Option<u64>)> {
+        let mut cids = Vec::with_capacity(limit);
+        let mut iter = self.db.raw_iterator_cf(self.cf.blobs);
+        iter.seek(from.to_be_bytes());
+        while let Some(key) = iter.key() {
+            let id = u64::from_be_bytes(key[..8].try_into()?);
+            if cids.len() == limit {
+                return Ok((cids, Some(id)));
+            }
+            let cid = self
+                .get_cid_by_id(id)?
+                .ok_or_else(|| anyhow!("missing metadata: {}", id))?;
+            cids.push(cid);
+            iter.next();
+        }
+        iter.status()?;
+        Ok((cids, None))
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_cid_by_id(&self, id: u64) -> Result<Option<Cid>> {
+        // can't use pinned, see get_links_by_id
+        match self.db.get_cf(self.cf.metadata, id.to_be_bytes())? {
+            Some(meta) => {
+                let meta = rkyv::check_archived_root::<MetadataV0>(&meta)
+                    .map_err(|e| anyhow!("{:?}", e))?;
+                let multihash = cid::multihash::Multihash::from_bytes(&meta.multihash)?;
+                Ok(Some(Cid::new_v1(meta.codec, multihash)))
+            }
+            None => Ok(None),
+        }
+    }
+
     /// Perform an internal consistency check on the store, and return all internal errors found.
     fn consistency_check(&self) -> anyhow::Result<Vec<String>> {
         let mut res = Vec::new();
@@ -919,4 +1028,46 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_pins() -> anyhow::Result<()> {
+        let root = Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(b"root"));
+        let link = Cid::new_v1(RAW, Code::Sha2_256.digest(b"link"));
+
+        let (store, _dir) = test_store().await?;
+        store.put(root, b"root", vec![link])?;
+        store.pin(&root)?;
+        // only stored blocks can be pinned, links have an id but no blob
+        assert!(store.pin(&link).is_err());
+        assert_eq!(store.pins()?, vec![root]);
+
+        store.unpin(&root)?;
+        assert!(store.pins()?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_blocks() -> anyhow::Result<()> {
+        let cids = (0..10u64)
+            .map(|i| Cid::new_v1(RAW, Code::Sha2_256.digest(&i.to_be_bytes())))
+            .collect::<Vec<_>>();
+        let link = Cid::new_v1(RAW, Code::Sha2_256.digest(b"link"));
+
+        let (store, _dir) = test_store().await?;
+        for cid in &cids {
+            store.put(*cid, cid.hash().digest(), vec![link])?;
+        }
+
+        let mut listed = Vec::new();
+        let mut from = Some(0);
+        while let Some(start) = from {
+            let (page, next) = store.blocks(start, 3)?;
+            assert!(page.len() <= 3);
+            listed.extend(page);
+            from = next;
+        }
+        // the link has no blob and is not listed
+        assert_eq!(listed, cids);
+        Ok(())
+    }
 }
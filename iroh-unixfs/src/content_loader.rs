@@ -12,7 +12,9 @@ use async_trait::async_trait;
 use bytes::Bytes;
 use cid::{multibase::Base, Cid};
 use futures::future::Either;
+use iroh_routing::delegated_routing::DelegatedRouting;
 use iroh_rpc_client::Client;
+use libp2p::PeerId;
 use rand::seq::SliceRandom;
 use reqwest::Url;
 use tracing::{debug, info, trace, warn};
@@ -33,6 +35,10 @@ pub trait ContentLoader: Sync + Send + std::fmt::Debug + Clone + 'static {
     async fn stop_session(&self, ctx: ContextId) -> Result<()>;
     /// Checks if the given cid is present in the local storage.
     async fn has_cid(&self, cid: &Cid) -> Result<bool>;
+    /// Resolves the IPNS record for the given name, returning the path it points to.
+    async fn load_ipns_record(&self, name: &PeerId) -> Result<Bytes> {
+        bail!("unable to resolve {}: IPNS is not supported", name)
+    }
 }
 
 #[async_trait]
@@ -48,6 +54,10 @@ impl<T: ContentLoader> ContentLoader for Arc<T> {
     async fn has_cid(&self, cid: &Cid) -> Result<bool> {
         self.as_ref().has_cid(cid).await
     }
+
+    async fn load_ipns_record(&self, name: &PeerId) -> Result<Bytes> {
+        self.as_ref().load_ipns_record(name).await
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -56,6 +66,8 @@ pub struct FullLoader {
     client: Client,
     /// API to talk to the indexer nodes.
     indexer: Option<Indexer>,
+    /// API to talk to the delegated routers.
+    delegated_routing: Option<DelegatedRouting>,
     /// Gateway endpoints.
     http_gateways: Vec<GatewayUrl>,
 }
@@ -63,6 +75,8 @@ pub struct FullLoader {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FullLoaderConfig {
     pub indexer: Option<IndexerUrl>,
+    /// Delegated routing endpoints, queried for providers along with the indexer.
+    pub delegated_routers: Vec<Url>,
     pub http_gateways: Vec<GatewayUrl>,
 }
 
@@ -111,10 +125,16 @@ impl GatewayUrl {
 impl FullLoader {
     pub fn new(client: Client, config: FullLoaderConfig) -> Result<Self> {
         let indexer = config.indexer.map(Indexer::new).transpose()?;
+        let delegated_routing = if config.delegated_routers.is_empty() {
+            None
+        } else {
+            Some(DelegatedRouting::new(config.delegated_routers)?)
+        };
 
         Ok(Self {
             client,
             indexer,
+            delegated_routing,
             http_gateways: config.http_gateways,
         })
     }
@@ -145,15 +165,27 @@ impl FullLoader {
     async fn fetch_bitswap(&self, ctx: ContextId, cid: &Cid) -> Result<Option<LoadedCid>> {
         match self.client.try_p2p() {
             Ok(p2p) => {
-                let providers: HashSet<_> = if let Some(ref indexer) = self.indexer {
-                    if let Ok(providers) = indexer.find_providers(*cid).await {
-                        providers.into_iter().map(|p| p.id).collect()
-                    } else {
-                        Default::default()
-                    }
-                } else {
-                    Default::default()
-                };
+                let (indexer_providers, routing_providers) = futures::future::join(
+                    async {
+                        match self.indexer {
+                            Some(ref indexer) => indexer.find_providers(*cid).await.ok(),
+                            None => None,
+                        }
+                    },
+                    async {
+                        match self.delegated_routing {
+                            Some(ref routing) => routing.find_providers(*cid).await.ok(),
+                            None => None,
+                        }
+                    },
+                )
+                .await;
+                let providers: HashSet<_> = indexer_providers
+                    .into_iter()
+                    .chain(routing_providers)
+                    .flatten()
+                    .map(|p| p.id)
+                    .collect();
 
                 let data = p2p.fetch_bitswap(ctx.into(), *cid, providers).await?;
                 Ok(Some(LoadedCid {
@@ -291,6 +323,10 @@ impl ContentLoader for FullLoader {
     async fn has_cid(&self, cid: &Cid) -> Result<bool> {
         self.client.try_store()?.has(*cid).await
     }
+
+    async fn load_ipns_record(&self, name: &PeerId) -> Result<Bytes> {
+        self.client.try_p2p()?.name_resolve(*name).await
+    }
 }
 
 #[derive(Debug, Clone)]
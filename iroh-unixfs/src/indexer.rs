@@ -4,7 +4,7 @@ use std::str::FromStr;
 use anyhow::Result;
 use cid::Cid;
 use config::ValueKind;
-use libp2p::{Multiaddr, PeerId};
+pub use iroh_routing::Provider;
 use multihash::Multihash;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
@@ -63,14 +63,6 @@ pub struct Indexer {
     client: Client,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "PascalCase")]
-pub struct Provider {
-    #[serde(rename = "ID")]
-    pub id: PeerId,
-    pub addrs: Vec<Multiaddr>,
-}
-
 impl Indexer {
     pub fn new(endpoint: IndexerUrl) -> Result<Self> {
         let client = Client::new();
@@ -0,0 +1,60 @@
+//! Configuration of the DNS resolvers, shared by the services resolving DNS names.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+
+use serde::{Deserialize, Serialize};
+use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DnsResolverConfig {
+    /// Mapping from TLD to the specific instance of resolver
+    tld_resolvers: Option<HashMap<String, ResolverConfig>>,
+}
+
+impl DnsResolverConfig {
+    pub fn empty() -> Self {
+        DnsResolverConfig {
+            tld_resolvers: None,
+        }
+    }
+
+    /// Returns the TLDs that have a separate resolver, along with its configuration.
+    pub fn tld_resolvers(&self) -> impl Iterator<Item = (&str, &ResolverConfig)> {
+        self.tld_resolvers
+            .iter()
+            .flatten()
+            .map(|(tld, config)| (tld.as_str(), config))
+    }
+
+    /// Resolves the names under `tld` with a separate resolver, replacing any previous one.
+    pub fn set_tld_resolver(&mut self, tld: impl Into<String>, config: ResolverConfig) {
+        self.tld_resolvers
+            .get_or_insert_with(Default::default)
+            .insert(tld.into(), config);
+    }
+}
+
+impl Default for DnsResolverConfig {
+    fn default() -> Self {
+        DnsResolverConfig {
+            /// Documentation on .eth TLD lives on https://eth.link/
+            tld_resolvers: Some(HashMap::from_iter(vec![(
+                "eth".to_string(),
+                ResolverConfig::from_parts(
+                    None,
+                    vec![],
+                    NameServerConfigGroup::from_ips_https(
+                        &[
+                            IpAddr::V4(Ipv4Addr::new(104, 18, 165, 219)),
+                            IpAddr::V4(Ipv4Addr::new(104, 18, 166, 219)),
+                        ],
+                        443,
+                        "resolver.cloudflare-eth.com".to_string(),
+                        true,
+                    ),
+                ),
+            )])),
+        }
+    }
+}
@@ -17,6 +17,7 @@ use cid::{
 use config::{Config, ConfigError, Environment, File, Map, Source, Value, ValueKind};
 use tracing::debug;
 
+pub mod dns;
 pub mod exitcodes;
 pub mod human;
 pub mod lock;
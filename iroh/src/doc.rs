@@ -49,6 +49,12 @@ hosted at https://gateway.lol to fetch the content from our local node:
 
   > curl https://gateway.lol/ipfs/bafybeihjgu5w6wbbxqevdgccj5xm453dbzpkwmkyoepvs3vh6wft4uvf2q/cat.jpg
 
+The root of added content is pinned, unless --no-pin is given. The p2p service
+periodically announces the content of the store again, which blocks are announced
+is controlled by the 'reprovider_strategy' setting of the p2p config: 'all'
+blocks, only the pinned 'roots', or the 'pinned' roots and all blocks they link
+to. Newly pinned content is announced right away.
+
 Implementation Interop:
 Iroh does *not* produce the same hashes as other IPFS implementations when given
 the same data. Iroh & other valid implementations can read each other's data,
@@ -163,6 +169,37 @@ for more info on multiaddrs see https://iroh.computer/docs/concepts#multiaddr
 If a peer ID is provided, connect first perform a distribtued hash table (DHT)
 lookup to learn the address of the given peer ID before dialing.";
 
+pub const P2P_DISCONNECT_LONG_DESCRIPTION: &str = "
+Closes all open connections to a peer. The peer is given either as a peer ID
+or as a multiaddr that ends in a peer ID. Disconnecting from a peer we are not
+connected to is an error.
+
+Disconnecting does not keep the peer away, p2p may connect to it again later
+or accept its connections. Use --ban to reject all connections to and from
+the peer for the given number of seconds:
+
+  > iroh p2p disconnect --ban 600 QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ
+
+A ban too long for the system clock to represent never expires. Bans are not
+persisted and end when the p2p service restarts.";
+
+pub const P2P_BLOCK_LONG_DESCRIPTION: &str = "
+Adds a peer or a range of IP addresses to the deny list of the p2p service.
+Open connections to blocked peers or addresses are closed, and new ones are
+rejected in both directions. Address ranges are given in multiaddr form:
+
+  > iroh p2p block QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ
+  > iroh p2p block /ip4/10.0.0.0/ipcidr/8
+
+Blocks are not persisted and end when the p2p service restarts. To block peers
+permanently, add them to the deny_peers or deny_addrs fields of the p2p config.
+The allow_peers and allow_addrs fields restrict the node to the listed peers.";
+
+pub const P2P_UNBLOCK_LONG_DESCRIPTION: &str = "
+Removes a peer or a range of IP addresses from the deny list of the p2p
+service, including entries from the config file. Unblocking a peer or address
+range that is not blocked is an error. The change is not persisted.";
+
 pub const P2P_LOOKUP_LONG_DESCRIPTION: &str = "
 Takes as input a peer ID or address and prints the output of the libp2p-identify
 protocol. When provided with a peer ID, the address is looked up on the 
@@ -181,6 +218,10 @@ lookup will output 6 types of the info about the peer:
   Listening Addresses - address this peer is listening for connections on
   Protocols           - identifiers for protocols this peer speaks
 
+For your local node, lookup also prints its reachability as determined by
+AutoNAT: public, private (behind a NAT or firewall) or unknown, along with the
+external addresses other peers confirmed they could dial.
+
 This command is a direct port of libp2p-lookup by mxinden:
 https://github.com/mxinden/libp2p-lookup";
 
@@ -199,3 +240,85 @@ for additional details about the peer. For example:
 
 For more info on multiaddrs see https://iroh.computer/docs/concepts#multiaddr.
 ";
+
+pub const P2P_STATS_LONG_DESCRIPTION: &str = "
+Shows the bytes the p2p node received and sent since it started, in total and
+per protocol, and the bytes exchanged with every connected peer over its current
+connections, the busiest peer first. Only the data of the protocols is counted,
+not the overhead of the transports, encryption and stream multiplexing.
+
+Traffic of bitswap, kademlia, gossipsub and identify is listed separately, the
+traffic of all other protocols, like ping and relay, is counted as 'other'.";
+
+pub const P2P_PUBSUB_LONG_DESCRIPTION: &str = "
+Publishes messages to and receives messages from gossipsub topics. 'pubsub sub'
+subscribes the p2p node to a topic and prints every message received on it,
+prefixed by the peer ID that published the message, until it is interrupted:
+
+  > iroh p2p pubsub sub chat
+  > iroh p2p pubsub pub chat 'hello world'
+
+Messages are only delivered to peers that are connected and subscribed to the
+topic. Publishing fails when no such peer is known. The node stays subscribed
+to the topic after 'pubsub sub' exits.";
+
+pub const KEY_LIST_LONG_DESCRIPTION: &str = "
+Lists the identity keys of the p2p node by their peer ID. The active key is
+marked, it is the identity the p2p node uses when it starts. The running node
+may still use another key if a new one was selected since it started.";
+
+pub const KEY_GENERATE_LONG_DESCRIPTION: &str = "
+Generates a new ed25519 identity key and adds it to the keychain of the p2p
+node. The new key is not used until it is selected with 'iroh key select'.";
+
+pub const KEY_IMPORT_LONG_DESCRIPTION: &str = "
+Adds an existing ed25519 private key to the keychain of the p2p node. The file
+may hold the key in the OpenSSH format, as written by ssh-keygen, or in the
+libp2p protobuf encoding used by other libp2p implementations. The format is
+detected automatically:
+
+  > iroh key import ~/.ssh/id_ed25519
+
+Importing a key that is already in the keychain is an error.";
+
+pub const KEY_EXPORT_LONG_DESCRIPTION: &str = "
+Writes the private key with the given peer ID to a new file, in the OpenSSH
+format by default. Use --format protobuf to write the libp2p protobuf encoding
+instead. Existing files are never overwritten. Keep the exported file private,
+anyone holding it can act as this peer.
+
+The key is always written unencrypted. If the keystore of the node is protected
+by a passphrase, the export is refused unless --unencrypted is given:
+
+  > iroh key export 12D3KooWLo6JTNKXfjkZtKf8ooLQoXVXUEeuu4YDY3CYqK6rxHXt peer.key";
+
+pub const KEY_SELECT_LONG_DESCRIPTION: &str = "
+Makes the key with the given peer ID the active identity of the p2p node. The
+selection is stored in the keychain, the p2p service uses the new identity once
+it is restarted.";
+
+pub const NAME_PUBLISH_LONG_DESCRIPTION: &str = "
+Publishes an IPNS record that points to <PATH>. The record is signed with the
+identity key of the p2p node, so the name it is published under is the peer ID
+of this node. Use --key to sign with another key from the keychain (see
+'iroh key list'), the record is then published under the peer ID of that key.
+Publishing again under the same name replaces the previous record.
+
+The record is stored on the Distributed Hash Table (DHT), the command returns
+once at least one other node accepted it. By default it stays
+valid for 24 hours, use --lifetime to change that. --ttl controls how long
+other nodes may cache the record before looking it up again. For example:
+
+  > iroh name publish /ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi
+  Published to 12D3KooWLo6JTNKXfjkZtKf8ooLQoXVXUEeuu4YDY3CYqK6rxHXt: /ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi
+
+The content can then be fetched as /ipns/<NAME>.";
+
+pub const NAME_RESOLVE_LONG_DESCRIPTION: &str = "
+Resolves an IPNS name to the path it points to. The name is a peer ID, optionally
+prefixed with /ipns/. Only records with a valid signature that have not expired
+are accepted. When several records are found the one with the highest sequence
+number wins. For example:
+
+  > iroh name resolve 12D3KooWLo6JTNKXfjkZtKf8ooLQoXVXUEeuu4YDY3CYqK6rxHXt
+  /ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+
+use crate::doc;
+use anyhow::{Context, Result};
+use clap::{Args, Subcommand, ValueEnum};
+use iroh_api::{KeyFormat, P2pApi, PeerId};
+
+#[derive(Args, Debug, Clone)]
+#[clap(about = "Manage the identity keys of the p2p node")]
+#[clap(
+    after_help = "key commands manage the keys the p2p node uses as its identity. The
+active key determines the peer ID of the node. See subcommands for additional
+details."
+)]
+pub struct Key {
+    #[clap(subcommand)]
+    command: KeyCommands,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum KeyCommands {
+    #[clap(about = "List the identity keys")]
+    #[clap(after_help = doc::KEY_LIST_LONG_DESCRIPTION)]
+    List {},
+    #[clap(about = "Generate a new identity key")]
+    #[clap(after_help = doc::KEY_GENERATE_LONG_DESCRIPTION)]
+    Generate {},
+    #[clap(about = "Import an identity key from a file")]
+    #[clap(after_help = doc::KEY_IMPORT_LONG_DESCRIPTION)]
+    Import {
+        /// File containing the private key
+        path: PathBuf,
+    },
+    #[clap(about = "Export an identity key to a file")]
+    #[clap(after_help = doc::KEY_EXPORT_LONG_DESCRIPTION)]
+    Export {
+        /// Peer ID of the key to export
+        peer_id: PeerId,
+        /// File to write the private key to
+        path: PathBuf,
+        /// Encoding of the exported key
+        #[clap(long, value_enum, default_value_t = KeyFormatArg::Openssh)]
+        format: KeyFormatArg,
+        /// Export the key even if the keystore is protected by a passphrase
+        #[clap(long)]
+        unencrypted: bool,
+    },
+    #[clap(about = "Select the identity key of the p2p node")]
+    #[clap(after_help = doc::KEY_SELECT_LONG_DESCRIPTION)]
+    Select {
+        /// Peer ID of the key to use
+        peer_id: PeerId,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum KeyFormatArg {
+    Openssh,
+    Protobuf,
+}
+
+impl From<KeyFormatArg> for KeyFormat {
+    fn from(format: KeyFormatArg) -> Self {
+        match format {
+            KeyFormatArg::Openssh => KeyFormat::Openssh,
+            KeyFormatArg::Protobuf => KeyFormat::Protobuf,
+        }
+    }
+}
+
+pub async fn run_command(p2p: &P2pApi, cmd: &Key) -> Result<()> {
+    match &cmd.command {
+        KeyCommands::List {} => {
+            let (keys, active) = p2p.keys().await?;
+            for peer_id in keys {
+                if Some(peer_id) == active {
+                    println!("{peer_id} (active)");
+                } else {
+                    println!("{peer_id}");
+                }
+            }
+        }
+        KeyCommands::Generate {} => {
+            let peer_id = p2p.generate_key().await?;
+            println!("Generated {peer_id}");
+        }
+        KeyCommands::Import { path } => {
+            let key = tokio::fs::read(path)
+                .await
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            let peer_id = p2p.import_key(key.into()).await?;
+            println!("Imported {peer_id}");
+        }
+        KeyCommands::Export {
+            peer_id,
+            path,
+            format,
+            unencrypted,
+        } => {
+            let key = p2p
+                .export_key(*peer_id, (*format).into(), *unencrypted)
+                .await?;
+            write_private(path, &key)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            println!("Exported {peer_id} to {}", path.display());
+        }
+        KeyCommands::Select { peer_id } => {
+            p2p.select_key(*peer_id).await?;
+            println!("Selected {peer_id}, restart the p2p service to use it");
+        }
+    };
+    Ok(())
+}
+
+/// Writes a private key to a new file, only readable by the current user where supported.
+fn write_private(path: &Path, key: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(key)
+}
@@ -1,6 +1,8 @@
 mod config;
 pub mod doc;
+pub mod key;
 pub mod metrics;
+pub mod name;
 pub mod p2p;
 pub mod run;
 pub mod services;
@@ -37,6 +37,17 @@ fn transform_error(r: Result<()>) -> Result<()> {
                 ));
             }
 
+            if let Some(unavailable) = e
+                .root_cause()
+                .downcast_ref::<iroh_rpc_client::ServiceUnavailable>()
+            {
+                return Err(anyhow!(
+                    "{}. Is the service running?\n{}",
+                    unavailable,
+                    "hint: see 'iroh status' to check the health of the services".yellow(),
+                ));
+            }
+
             let api_error = e.root_cause().downcast_ref::<ApiError>();
             if let Some(ApiError::ConnectionRefused { service }) = api_error {
                 return Err(anyhow!(
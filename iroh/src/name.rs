@@ -0,0 +1,86 @@
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use crate::doc;
+use anyhow::{Error, Result};
+use clap::{Args, Subcommand};
+use iroh_api::{Api, IpfsPath, PeerId};
+
+#[derive(Args, Debug, Clone)]
+#[clap(about = "Publish and resolve IPNS names")]
+#[clap(
+    after_help = "name commands manage IPNS records, mutable pointers to IPFS content. See
+subcommands for additional details."
+)]
+pub struct Name {
+    #[clap(subcommand)]
+    command: NameCommands,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum NameCommands {
+    #[clap(about = "Publish an IPNS record pointing to a path")]
+    #[clap(after_help = doc::NAME_PUBLISH_LONG_DESCRIPTION)]
+    Publish {
+        /// The path the name should point to, e.g. /ipfs/<CID>
+        path: IpfsPath,
+        /// How long the record stays valid, in seconds
+        #[clap(long, default_value_t = 24 * 60 * 60)]
+        lifetime: u64,
+        /// How long other nodes may cache the record, in seconds
+        #[clap(long, default_value_t = 60 * 60)]
+        ttl: u64,
+        /// Peer ID of the keychain key to sign with, defaults to the node identity
+        #[clap(long)]
+        key: Option<PeerId>,
+    },
+    #[clap(about = "Resolve an IPNS name")]
+    #[clap(after_help = doc::NAME_RESOLVE_LONG_DESCRIPTION)]
+    Resolve {
+        /// Peer ID or /ipns/<peer ID> to resolve
+        name: NameArg,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct NameArg(PeerId);
+
+impl FromStr for NameArg {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.strip_prefix("/ipns/").unwrap_or(s);
+        let peer_id = PeerId::from_str(name).map_err(|_| anyhow::anyhow!("invalid name"))?;
+        Ok(NameArg(peer_id))
+    }
+}
+
+impl Display for NameArg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub async fn run_command(api: &Api, cmd: &Name) -> Result<()> {
+    match &cmd.command {
+        NameCommands::Publish {
+            path,
+            lifetime,
+            ttl,
+            key,
+        } => {
+            let name = api
+                .name_publish(
+                    *key,
+                    path,
+                    Duration::from_secs(*lifetime),
+                    Duration::from_secs(*ttl),
+                )
+                .await?;
+            println!("Published to {name}: {path}");
+        }
+        NameCommands::Resolve { name } => {
+            let path = api.name_resolve(&name.0).await?;
+            println!("{path}");
+        }
+    };
+    Ok(())
+}
@@ -2,8 +2,13 @@ use crate::doc;
 use anyhow::{Error, Result};
 use clap::{Args, Subcommand};
 use crossterm::style::Stylize;
-use iroh_api::{Lookup, Multiaddr, P2pApi, PeerId, PeerIdOrAddr};
-use std::{collections::HashMap, fmt::Display, str::FromStr};
+use futures::StreamExt;
+use iroh_api::{
+    BandwidthProtocol, BandwidthStats, BandwidthTotals, Bytes, Lookup, Multiaddr, P2pApi,
+    PeerFilterTarget, PeerId, PeerIdOrAddr, Reachability,
+};
+use iroh_util::human::format_bytes;
+use std::{collections::HashMap, fmt::Display, str::FromStr, time::Duration};
 
 #[derive(Args, Debug, Clone)]
 #[clap(about = "Peer-2-peer commands")]
@@ -24,6 +29,27 @@ pub enum P2pCommands {
         /// Multiaddr or peer ID of a peer to connect to
         addr: PeerIdOrAddrArg,
     },
+    #[clap(about = "Disconnect from a peer")]
+    #[clap(after_help = doc::P2P_DISCONNECT_LONG_DESCRIPTION)]
+    Disconnect {
+        /// Multiaddr or peer ID of a peer to disconnect from
+        addr: PeerIdOrAddrArg,
+        /// Keep the peer from reconnecting for this many seconds
+        #[clap(long)]
+        ban: Option<u64>,
+    },
+    #[clap(about = "Reject all connections to and from a peer or address range")]
+    #[clap(after_help = doc::P2P_BLOCK_LONG_DESCRIPTION)]
+    Block {
+        /// Peer ID or address range, like /ip4/10.0.0.0/ipcidr/8
+        target: PeerFilterTargetArg,
+    },
+    #[clap(about = "Lift a block of a peer or address range")]
+    #[clap(after_help = doc::P2P_UNBLOCK_LONG_DESCRIPTION)]
+    Unblock {
+        /// Peer ID or address range, like /ip4/10.0.0.0/ipcidr/8
+        target: PeerFilterTargetArg,
+    },
     #[clap(about = "Retrieve info about a node")]
     #[clap(after_help = doc::P2P_LOOKUP_LONG_DESCRIPTION)]
     Lookup {
@@ -33,6 +59,31 @@ pub enum P2pCommands {
     #[clap(about = "List connected peers")]
     #[clap(after_help = doc::P2P_PEERS_LONG_DESCRIPTION)]
     Peers {},
+    #[clap(about = "Show the bandwidth used per protocol and per peer")]
+    #[clap(after_help = doc::P2P_STATS_LONG_DESCRIPTION)]
+    Stats {},
+    #[clap(about = "Publish and receive gossipsub messages")]
+    #[clap(after_help = doc::P2P_PUBSUB_LONG_DESCRIPTION)]
+    Pubsub {
+        #[clap(subcommand)]
+        command: PubsubCommands,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum PubsubCommands {
+    #[clap(about = "Subscribe to a topic and print the messages received on it")]
+    Sub {
+        /// Name of the topic
+        topic: String,
+    },
+    #[clap(about = "Publish a message on a topic")]
+    Pub {
+        /// Name of the topic
+        topic: String,
+        /// The message to publish
+        data: String,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -61,6 +112,32 @@ impl Display for PeerIdOrAddrArg {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct PeerFilterTargetArg(PeerFilterTarget);
+
+impl FromStr for PeerFilterTargetArg {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // address ranges are validated by the p2p service
+        if s.starts_with('/') {
+            return Ok(PeerFilterTargetArg(PeerFilterTarget::Addr(s.to_string())));
+        }
+        if let Ok(p) = PeerId::from_str(s) {
+            return Ok(PeerFilterTargetArg(PeerFilterTarget::Peer(p)));
+        }
+        Err(anyhow::anyhow!("invalid peer id or address range"))
+    }
+}
+
+impl Display for PeerFilterTargetArg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            PeerFilterTarget::Peer(p) => write!(f, "{p}"),
+            PeerFilterTarget::Addr(a) => write!(f, "{a}"),
+        }
+    }
+}
+
 pub async fn run_command(p2p: &P2pApi, cmd: &P2p) -> Result<()> {
     match &cmd.command {
         P2pCommands::Connect { addr } => match p2p.connect(&addr.0).await {
@@ -69,6 +146,22 @@ pub async fn run_command(p2p: &P2pApi, cmd: &P2p) -> Result<()> {
             }
             Err(e) => return Err(e),
         },
+        P2pCommands::Disconnect { addr, ban } => {
+            p2p.disconnect(&addr.0, ban.map(Duration::from_secs))
+                .await?;
+            match ban {
+                Some(secs) => println!("Disconnected from {addr}, banned for {secs}s"),
+                None => println!("Disconnected from {addr}"),
+            }
+        }
+        P2pCommands::Block { target } => {
+            p2p.block(target.0.clone()).await?;
+            println!("Blocked {target}");
+        }
+        P2pCommands::Unblock { target } => {
+            p2p.unblock(target.0.clone()).await?;
+            println!("Unblocked {target}");
+        }
         P2pCommands::Lookup { addr } => {
             let lookup = match addr {
                 Some(addr) => p2p.lookup(&addr.0).await?,
@@ -80,6 +173,31 @@ pub async fn run_command(p2p: &P2pApi, cmd: &P2p) -> Result<()> {
             let peers = p2p.peers().await?;
             display_peers(peers);
         }
+        P2pCommands::Stats {} => {
+            let stats = p2p.stats().await?;
+            display_stats(stats);
+        }
+        P2pCommands::Pubsub {
+            command: PubsubCommands::Sub { topic },
+        } => {
+            let mut messages = p2p.subscribe(topic.clone()).await?;
+            while let Some(message) = messages.next().await {
+                let message = message?;
+                let source = message.source.unwrap_or(message.from);
+                println!(
+                    "{} {}",
+                    format!("{source}:").bold().dim(),
+                    String::from_utf8_lossy(&message.data)
+                );
+            }
+        }
+        P2pCommands::Pubsub {
+            command: PubsubCommands::Pub { topic, data },
+        } => {
+            p2p.publish(topic.clone(), Bytes::from(data.clone()))
+                .await?;
+            println!("Published to {topic}");
+        }
     };
     Ok(())
 }
@@ -112,6 +230,22 @@ fn display_lookup(l: &Lookup) {
         format!("({}):", l.protocols.len()).bold().dim(),
         l.protocols.join("\n  ")
     );
+    if let Some(reachability) = l.reachability {
+        let reachability = match reachability {
+            Reachability::Public => "public",
+            Reachability::Private => "private",
+            Reachability::Unknown => "unknown",
+        };
+        println!("{}\n  {}", "Reachability:".bold().dim(), reachability);
+        println!(
+            "{} {}",
+            "Confirmed Addresses".bold().dim(),
+            format!("({}):", l.confirmed_addrs.len()).bold().dim()
+        );
+        l.confirmed_addrs
+            .iter()
+            .for_each(|addr| println!("  {addr}"));
+    }
 }
 
 fn display_peers(peers: HashMap<PeerId, Vec<Multiaddr>>) {
@@ -122,3 +256,36 @@ fn display_peers(peers: HashMap<PeerId, Vec<Multiaddr>>) {
         }
     }
 }
+
+fn display_stats(mut stats: BandwidthStats) {
+    let totals = |t: &BandwidthTotals| {
+        format!(
+            "in {:>10}  out {:>10}",
+            format_bytes(t.inbound),
+            format_bytes(t.outbound)
+        )
+    };
+    println!("{}\n  {}", "Total:".bold().dim(), totals(&stats.total));
+    println!("{}", "Protocols:".bold().dim());
+    for (protocol, t) in &stats.protocols {
+        let name = match protocol {
+            BandwidthProtocol::Bitswap => "bitswap",
+            BandwidthProtocol::Kademlia => "kademlia",
+            BandwidthProtocol::Gossipsub => "gossipsub",
+            BandwidthProtocol::Identify => "identify",
+            BandwidthProtocol::Other => "other",
+        };
+        println!("  {:<10} {}", name, totals(t));
+    }
+    println!(
+        "{} {}",
+        "Connected Peers".bold().dim(),
+        format!("({}):", stats.peers.len()).bold().dim()
+    );
+    stats
+        .peers
+        .sort_by_key(|(_, t)| std::cmp::Reverse(t.inbound + t.outbound));
+    for (peer_id, t) in &stats.peers {
+        println!("  {peer_id} {}", totals(t));
+    }
+}
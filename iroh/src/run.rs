@@ -17,6 +17,8 @@ use crate::config::{Config, CONFIG_FILE_NAME, ENV_PREFIX};
 use crate::doc;
 #[cfg(feature = "testing")]
 use crate::fixture::get_fixture_api;
+use crate::key::{run_command as run_key_command, Key};
+use crate::name::{run_command as run_name_command, Name};
 use crate::p2p::{run_command as run_p2p_command, P2p};
 use crate::services::require_services;
 use crate::size::size_stream;
@@ -38,6 +40,8 @@ pub struct Cli {
 #[derive(Subcommand, Debug, Clone)]
 enum Commands {
     P2p(P2p),
+    Name(Name),
+    Key(Key),
     #[clap(about = "Add a file or directory to iroh & make it available on IPFS")]
     #[clap(after_help = doc::ADD_LONG_DESCRIPTION )]
     Add {
@@ -52,6 +56,9 @@ enum Commands {
         /// Don't provide added content to the network
         #[clap(long)]
         offline: bool,
+        /// Do not pin the added content
+        #[clap(long)]
+        no_pin: bool,
         /// Select the chunker to use, when chunking data. Available chunkers are currently "fixed" and "rabin".
         #[clap(long, default_value_t = ChunkerConfig::Fixed(DEFAULT_CHUNKS_SIZE))]
         chunker: ChunkerConfig,
@@ -134,9 +141,13 @@ impl Cli {
                 recursive,
                 no_wrap,
                 offline,
+                no_pin,
                 chunker,
             } => {
-                add(api, path, *no_wrap, *recursive, *chunker, !*offline).await?;
+                add(
+                    api, path, *no_wrap, *recursive, *chunker, !*offline, !*no_pin,
+                )
+                .await?;
             }
             Commands::Get {
                 ipfs_path: path,
@@ -148,6 +159,8 @@ impl Cli {
                 println!("Saving file(s) to {}", root_path.to_str().unwrap());
             }
             Commands::P2p(p2p) => run_p2p_command(&api.p2p()?, p2p).await?,
+            Commands::Name(name) => run_name_command(api, name).await?,
+            Commands::Key(key) => run_key_command(&api.p2p()?, key).await?,
             Commands::Start { service, all } => {
                 let svc = match *all {
                     true => vec![
@@ -181,6 +194,7 @@ async fn add(
     recursive: bool,
     chunker: ChunkerConfig,
     provide: bool,
+    pin: bool,
 ) -> Result<()> {
     if !path.exists() {
         anyhow::bail!("Path does not exist");
@@ -273,6 +287,9 @@ async fn add(
     pb.finish_and_clear();
 
     let root = *cids.last().context("File processing failed")?;
+    if pin {
+        api.pin(root).await?;
+    }
 
     if provide {
         let pb = ProgressBar::new(cids.len().try_into().unwrap());
@@ -297,6 +314,10 @@ async fn add(
             pb.inc(1);
         }
         pb.finish_and_clear();
+        if pin {
+            // the rest of the pinned content is announced in the background
+            api.announce_pinned(root).await?;
+        }
     }
 
     println!("/ipfs/{root}");